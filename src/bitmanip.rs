@@ -0,0 +1,121 @@
+pub fn orc_b(value: u32) -> u32 {
+    let mut result = 0;
+    for byte in 0..4 {
+        if value >> (byte * 8) & 0xFF != 0 {
+            result |= 0xFF << (byte * 8);
+        }
+    }
+    result
+}
+
+fn clmul_wide(a: u32, b: u32) -> u64 {
+    let mut result: u64 = 0;
+    for i in 0..32 {
+        if b >> i & 1 != 0 {
+            result ^= (a as u64) << i;
+        }
+    }
+    result
+}
+
+pub fn clmul(a: u32, b: u32) -> u32 {
+    clmul_wide(a, b) as u32
+}
+
+pub fn clmulh(a: u32, b: u32) -> u32 {
+    (clmul_wide(a, b) >> 32) as u32
+}
+
+pub fn clmulr(a: u32, b: u32) -> u32 {
+    (clmul_wide(a, b) >> 31) as u32
+}
+
+pub fn brev8(value: u32) -> u32 {
+    let mut result = 0;
+    for byte in 0..4 {
+        result |= (((value >> (byte * 8)) as u8).reverse_bits() as u32) << (byte * 8);
+    }
+    result
+}
+
+pub fn zip(value: u32) -> u32 {
+    let mut result = 0;
+    for i in 0..16 {
+        result |= (value >> i & 1) << (2 * i);
+        result |= (value >> (i + 16) & 1) << (2 * i + 1);
+    }
+    result
+}
+
+pub fn unzip(value: u32) -> u32 {
+    let mut result = 0;
+    for i in 0..16 {
+        result |= (value >> (2 * i) & 1) << i;
+        result |= (value >> (2 * i + 1) & 1) << (i + 16);
+    }
+    result
+}
+
+pub fn xperm4(rs1: u32, rs2: u32) -> u32 {
+    let mut result = 0;
+    for i in (0..32).step_by(4) {
+        let index = rs2 >> i & 0xF;
+        if index < 8 {
+            result |= (rs1 >> (index * 4) & 0xF) << i;
+        }
+    }
+    result
+}
+
+pub fn xperm8(rs1: u32, rs2: u32) -> u32 {
+    let mut result = 0;
+    for i in (0..32).step_by(8) {
+        let index = rs2 >> i & 0xFF;
+        if index < 4 {
+            result |= (rs1 >> (index * 8) & 0xFF) << i;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitmanip::{brev8, clmul, clmulh, clmulr, orc_b, unzip, xperm4, xperm8, zip};
+
+    #[test]
+    fn test_orc_b() {
+        assert_eq!(orc_b(0x00000000), 0x00000000);
+        assert_eq!(orc_b(0x00120001), 0x00FF00FF);
+        assert_eq!(orc_b(0x80000100), 0xFF00FF00);
+    }
+
+    #[test]
+    fn test_clmul() {
+        assert_eq!(clmul(0b101, 0b11), 0b1111);
+        assert_eq!(clmul(0x80000000, 0x2), 0);
+        assert_eq!(clmulh(0x80000000, 0x2), 1);
+        assert_eq!(clmulr(0x80000000, 0x2), 2);
+        assert_eq!(clmulr(0x12345678, 0x9abcdef0), (clmulh(0x12345678, 0x9abcdef0) << 1) | (clmul(0x12345678, 0x9abcdef0) >> 31));
+    }
+
+    #[test]
+    fn test_brev8() {
+        assert_eq!(brev8(0x0180_F001), 0x8001_0F80);
+    }
+
+    #[test]
+    fn test_zip_unzip() {
+        assert_eq!(zip(0x0000FFFF), 0x55555555);
+        assert_eq!(zip(0xFFFF0000), 0xAAAAAAAA);
+        assert_eq!(unzip(0x55555555), 0x0000FFFF);
+        assert_eq!(unzip(zip(0x12345678)), 0x12345678);
+    }
+
+    #[test]
+    fn test_xperm() {
+        assert_eq!(xperm8(0x44332211, 0x00010203), 0x11223344);
+        assert_eq!(xperm8(0x44332211, 0xFF040100), 0x00002211);
+        assert_eq!(xperm4(0x76543210, 0x01234567), 0x01234567);
+        assert_eq!(xperm4(0xFEDCBA98, 0x0000008F), 0x88888800);
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::clint::{Clint, CLINT_BASE, CLINT_SIZE};
+use crate::cpu::block::Block;
+use crate::decode_cache::{DecodeCache, Decoded, PAGE_SHIFT};
+use crate::error::Error;
+use crate::instruction::Instruction;
+use crate::memory::Memory;
+use crate::snapshot::{Reader, Writer};
+
+/// The physical address space shared by all harts: main memory from address zero, the
+/// CLINT at `CLINT_BASE`, the LR/SC reservation held by each hart, and the decoded
+/// instructions of main memory.
+pub struct Bus {
+    memory: Memory,
+    pub(crate) clint: Clint,
+    reservations: Vec<Option<usize>>,
+    decode_cache: DecodeCache,
+    /// Stores not yet fenced by every hart, kept only in strict fetch mode.
+    unfenced: Option<Unfenced>,
+    clint_mapped: bool,
+}
+
+impl Bus {
+    pub fn new(memory: Memory, harts: usize) -> Self {
+        Self {
+            decode_cache: DecodeCache::new(memory.len()),
+            memory,
+            clint: Clint::new(harts),
+            reservations: vec![None; harts],
+            unfenced: None,
+            clint_mapped: true,
+        }
+    }
+
+    /// Removes the CLINT from the address space. Its timer keeps counting, but software
+    /// and timer interrupts can no longer be raised.
+    pub fn unmap_clint(&mut self) {
+        self.clint_mapped = false;
+    }
+
+    /// Starts recording stores so that fetches of code modified without a FENCE.I are caught.
+    pub fn set_strict_fetch(&mut self) {
+        self.unfenced = Some(Unfenced::new(self.memory.len(), self.reservations.len()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn in_clint(&self, index: usize) -> bool {
+        self.clint_mapped && (CLINT_BASE..CLINT_BASE + CLINT_SIZE).contains(&index)
+    }
+
+    /// Whether `size` bytes at `index` are backed by memory or a device.
+    pub fn contains(&self, index: usize, size: usize) -> bool {
+        let Some(end) = index.checked_add(size) else { return false };
+        end <= self.memory.len() || (self.in_clint(index) && end <= CLINT_BASE + CLINT_SIZE)
+    }
+
+    pub fn get8(&self, index: usize) -> Result<u8, Error> {
+        if self.in_clint(index) {
+            let offset = index - CLINT_BASE;
+            return Ok((self.clint.read32(offset & !3) >> ((offset & 3) * 8)) as u8);
+        }
+        self.memory.get8(index)
+    }
+
+    pub fn get16(&self, index: usize) -> Result<u16, Error> {
+        if self.in_clint(index) {
+            return Ok(self.get8(index)? as u16 | (self.get8(index + 1)? as u16) << 8);
+        }
+        self.memory.get16(index)
+    }
+
+    pub fn get32(&self, index: usize) -> Result<u32, Error> {
+        if self.in_clint(index) && index.is_multiple_of(4) {
+            return Ok(self.clint.read32(index - CLINT_BASE));
+        }
+        if self.in_clint(index) {
+            return Ok(self.get16(index)? as u32 | (self.get16(index + 2)? as u32) << 16);
+        }
+        self.memory.get32(index)
+    }
+
+    pub fn get8_sx(&self, index: usize) -> Result<u32, Error> {
+        if self.in_clint(index) {
+            return Ok(self.get8(index)? as i8 as u32);
+        }
+        self.memory.get8_sx(index)
+    }
+
+    pub fn get16_sx(&self, index: usize) -> Result<u32, Error> {
+        if self.in_clint(index) {
+            return Ok(self.get16(index)? as i16 as u32);
+        }
+        self.memory.get16_sx(index)
+    }
+
+    /// Fetches and decodes the instruction at `index`, reusing an earlier decoding of main memory.
+    pub fn fetch(&mut self, index: usize) -> Result<Decoded, Error> {
+        if let Some(decoded) = self.decode_cache.get(index) {
+            return Ok(decoded);
+        }
+        let decoded = Decoded::new(Instruction::from_u32(self.get32(index)?));
+        if !self.in_clint(index) {
+            self.decode_cache.insert(index, decoded);
+        }
+        Ok(decoded)
+    }
+
+    pub fn get_block(&self, index: usize) -> Option<Rc<Block>> {
+        self.decode_cache.get_block(index)
+    }
+
+    pub fn insert_block(&mut self, index: usize, block: Rc<Block>) {
+        self.decode_cache.insert_block(index, block)
+    }
+
+    /// Drops every reservation on the word a store to `index..index + size` touches, along
+    /// with the decoded instructions there and the blocks covering them, and marks the words it touches as unfenced
+    /// for every hart.
+    fn invalidate(&mut self, index: usize, size: usize) {
+        self.decode_cache.invalidate(index, size);
+
+        for reservation in self.reservations.iter_mut() {
+            if reservation.is_some_and(|reserved| reserved < index + size && index < reserved + 4) {
+                *reservation = None;
+            }
+        }
+
+        if let Some(unfenced) = self.unfenced.as_mut() {
+            unfenced.store(index, size);
+        }
+    }
+
+    /// Makes every store so far visible to instruction fetches of `hart`.
+    pub fn fence_i(&mut self, hart: usize) {
+        self.decode_cache.flush();
+        if let Some(unfenced) = self.unfenced.as_mut() {
+            unfenced.fence(hart);
+        }
+    }
+
+    /// Whether `hart` fetching the `size` bytes of code at `index` would see a store it has
+    /// not fenced.
+    pub fn is_unfenced(&self, hart: usize, index: usize, size: usize) -> bool {
+        self.unfenced.as_ref().is_some_and(|unfenced| unfenced.contains(hart, index, size))
+    }
+
+    pub fn set8(&mut self, data: u8, index: usize) -> Result<(), Error> {
+        if self.in_clint(index) {
+            self.invalidate(index, 1);
+            let offset = index - CLINT_BASE;
+            let shift = (offset & 3) * 8;
+            let word = self.clint.read32(offset & !3) & !(0xFF << shift) | (data as u32) << shift;
+            self.clint.write32(offset & !3, word);
+            return Ok(());
+        }
+        self.memory.set8(data, index)?;
+        self.invalidate(index, 1);
+        Ok(())
+    }
+
+    pub fn set16(&mut self, data: u16, index: usize) -> Result<(), Error> {
+        if self.in_clint(index) {
+            self.set8(data as u8, index)?;
+            return self.set8((data >> 8) as u8, index + 1);
+        }
+        self.memory.set16(data, index)?;
+        self.invalidate(index, 2);
+        Ok(())
+    }
+
+    pub fn set32(&mut self, data: u32, index: usize) -> Result<(), Error> {
+        if self.in_clint(index) && index.is_multiple_of(4) {
+            self.invalidate(index, 4);
+            self.clint.write32(index - CLINT_BASE, data);
+            return Ok(());
+        }
+        if self.in_clint(index) {
+            self.set16(data as u16, index)?;
+            return self.set16((data >> 16) as u16, index + 2);
+        }
+        self.memory.set32(data, index)?;
+        self.invalidate(index, 4);
+        Ok(())
+    }
+
+    /// Zeroes `size` bytes at `index` as one store, like CBO.ZERO does to a cache block.
+    pub fn zero(&mut self, index: usize, size: usize) -> Result<(), Error> {
+        if self.in_clint(index) {
+            return (index..index + size).step_by(4).try_for_each(|word| self.set32(0, word));
+        }
+        self.memory.zero(index, size)?;
+        self.invalidate(index, size);
+        Ok(())
+    }
+
+    pub fn reserve(&mut self, hart: usize, index: usize) {
+        self.reservations[hart] = Some(index);
+    }
+
+    /// Consumes the reservation of `hart`, returning whether it still covered `index`.
+    pub fn take_reservation(&mut self, hart: usize, index: usize) -> bool {
+        self.reservations[hart].take() == Some(index)
+    }
+
+    /// Saves memory, the CLINT and the reservations. Decoded instructions are dropped on
+    /// restore rather than saved.
+    pub fn save(&self, snapshot: &mut Writer) {
+        self.memory.save(snapshot);
+        self.save_devices(snapshot);
+    }
+
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        self.memory.restore(snapshot)?;
+        self.restore_devices(snapshot)
+    }
+
+    /// Saves everything but memory, for history that keeps memory itself.
+    pub fn save_devices(&self, snapshot: &mut Writer) {
+        self.clint.save(snapshot);
+        for reservation in &self.reservations {
+            snapshot.write_u64(reservation.map_or(u64::MAX, |address| address as u64));
+        }
+    }
+
+    /// Restores what `save_devices` saved, once memory has been restored.
+    pub fn restore_devices(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        self.clint.restore(snapshot)?;
+        for reservation in self.reservations.iter_mut() {
+            let address = snapshot.read_u64()?;
+            *reservation = (address != u64::MAX).then_some(address as usize);
+        }
+        self.decode_cache.flush();
+        if let Some(unfenced) = self.unfenced.as_mut() {
+            *unfenced = Unfenced::new(self.memory.len(), self.reservations.len());
+        }
+        Ok(())
+    }
+
+    /// Main memory, written directly rather than as a guest store, so decoded instructions
+    /// must be dropped with `restore_devices` afterwards.
+    pub(crate) fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn dump(&self) {
+        self.memory.dump()
+    }
+}
+
+/// Words in a page of main memory.
+const PAGE_WORDS: usize = 1 << (PAGE_SHIFT - 2);
+
+/// Stores each hart has not yet fenced, as sequence numbers compared with that of each hart's
+/// last FENCE.I. Stores to main memory are tracked by word in a table per page, kept only
+/// until every hart has fenced the last store to the page, so data stores cost the same
+/// however many harts there are and tracking never grows past one entry per word.
+struct Unfenced {
+    /// Stores made so far
+    stores: u64,
+    /// The store count at each hart's last FENCE.I
+    fences: Vec<u64>,
+    /// The last store to each word of a page, for pages not yet fenced by every hart
+    words: Vec<Option<Box<[u64]>>>,
+    /// The last store to each page
+    pages: Vec<u64>,
+    /// The last store to each device word not yet fenced by every hart
+    devices: HashMap<usize, u64>,
+}
+
+impl Unfenced {
+    fn new(memory_size: usize, harts: usize) -> Self {
+        let pages = memory_size.div_ceil(1 << PAGE_SHIFT);
+        Self { stores: 0, fences: vec![0; harts], words: vec![None; pages], pages: vec![0; pages], devices: HashMap::new() }
+    }
+
+    fn store(&mut self, index: usize, size: usize) {
+        self.stores += 1;
+        for word in (index & !3..index + size).step_by(4) {
+            let page = word >> PAGE_SHIFT;
+            match self.words.get_mut(page) {
+                Some(words) => {
+                    let words = words.get_or_insert_with(|| vec![0; PAGE_WORDS].into_boxed_slice());
+                    words[word >> 2 & (PAGE_WORDS - 1)] = self.stores;
+                    self.pages[page] = self.stores;
+                }
+                None => {
+                    self.devices.insert(word, self.stores);
+                }
+            }
+        }
+    }
+
+    /// Makes every store so far visible to `hart`, forgetting the pages every hart has fenced.
+    fn fence(&mut self, hart: usize) {
+        let oldest = self.fences.iter().copied().min().unwrap_or(0);
+        self.fences[hart] = self.stores;
+        let fenced = self.fences.iter().copied().min().unwrap_or(0);
+        if fenced > oldest {
+            for (words, &store) in self.words.iter_mut().zip(&self.pages) {
+                if store <= fenced {
+                    *words = None;
+                }
+            }
+            self.devices.retain(|_, store| *store > fenced);
+        }
+    }
+
+    fn contains(&self, hart: usize, index: usize, size: usize) -> bool {
+        let fence = self.fences[hart];
+        (index & !3..index + size).step_by(4).any(|word| {
+            let store = match self.words.get(word >> PAGE_SHIFT) {
+                Some(words) => words.as_ref().map(|words| words[word >> 2 & (PAGE_WORDS - 1)]),
+                None => self.devices.get(&word).copied(),
+            };
+            store.is_some_and(|store| store > fence)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::Bus;
+    use crate::clint::CLINT_BASE;
+    use crate::instruction::InstructionType;
+    use crate::memory::Memory;
+
+    #[test]
+    fn stores_invalidate_reservations() {
+        let mut bus = Bus::new(Memory::new(64), 2);
+        bus.reserve(0, 0x10);
+        bus.reserve(1, 0x10);
+        bus.set8(1, 0x13).unwrap();
+        assert!(!bus.take_reservation(0, 0x10));
+        assert!(!bus.take_reservation(1, 0x10));
+
+        bus.reserve(0, 0x10);
+        bus.set32(1, 0x14).unwrap();
+        assert!(bus.take_reservation(0, 0x10));
+        assert!(!bus.take_reservation(0, 0x10));
+    }
+
+    #[test]
+    fn clint_is_mapped() {
+        let mut bus = Bus::new(Memory::new(64), 1);
+        assert!(bus.contains(CLINT_BASE + 0xBFF8, 8));
+        assert!(!bus.contains(64, 1));
+
+        bus.set32(1, CLINT_BASE).unwrap();
+        assert!(bus.clint.software_pending(0));
+        assert_eq!(bus.get8(CLINT_BASE).unwrap(), 1);
+    }
+
+    #[test]
+    fn stores_invalidate_decoded_instructions() {
+        let mut bus = Bus::new(Memory::new(64), 1);
+        bus.set32(0x00000013, 0x10).unwrap();
+        assert_eq!(bus.fetch(0x10).unwrap()._type, Some(InstructionType::ADDI));
+        bus.set8(0x33, 0x10).unwrap();
+        assert_eq!(bus.fetch(0x10).unwrap()._type, Some(InstructionType::ADD));
+    }
+
+    #[test]
+    fn strict_fetch_tracks_unfenced_stores() {
+        let mut bus = Bus::new(Memory::new(64), 2);
+        bus.set32(0x13, 0x10).unwrap();
+        assert!(!bus.is_unfenced(0, 0x10, 4));
+
+        bus.set_strict_fetch();
+        bus.set16(0x13, 0x12).unwrap();
+        assert!(bus.is_unfenced(0, 0x10, 4));
+        assert!(bus.is_unfenced(1, 0x10, 4));
+
+        bus.fence_i(0);
+        assert!(!bus.is_unfenced(0, 0x10, 4));
+        assert!(bus.is_unfenced(1, 0x10, 4));
+
+        bus.fence_i(1);
+        bus.fetch(0x10).unwrap();
+        bus.set32(0x13, 0x20).unwrap();
+        assert!(!bus.is_unfenced(0, 0x10, 4));
+        assert!(bus.is_unfenced(0, 0x20, 4));
+    }
+
+    #[test]
+    fn strict_fetch_ignores_data_next_to_code() {
+        let mut bus = Bus::new(Memory::new(64), 1);
+        bus.set_strict_fetch();
+        bus.set32(0x13, 0x10).unwrap();
+        bus.fence_i(0);
+
+        // Data stored beside code that has not run yet
+        bus.set32(0x1234, 0x14).unwrap();
+        bus.set8(0x56, 0x1B).unwrap();
+        assert!(!bus.is_unfenced(0, 0x10, 4));
+        assert!(bus.is_unfenced(0, 0x14, 4));
+        assert!(bus.is_unfenced(0, 0x18, 4));
+        assert!(!bus.is_unfenced(0, 0x1C, 4));
+    }
+}
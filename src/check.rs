@@ -0,0 +1,342 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::io::BufRead;
+
+use crate::trace::Retired;
+
+/// The architectural effects of one committed instruction, as compared against a reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Commit {
+    hart: usize,
+    privilege: Option<u32>,
+    pc: usize,
+    instruction: u32,
+    registers: Vec<(usize, u32)>,
+    loads: Vec<usize>,
+    stores: Vec<(usize, u32)>,
+}
+
+impl Commit {
+    fn from_retired(retired: &Retired) -> Self {
+        Self {
+            hart: retired.hart,
+            privilege: Some(retired.privilege as u32),
+            pc: retired.pc,
+            instruction: retired.instruction.get_raw(),
+            registers: retired.registers.to_vec(),
+            loads: retired.memory.iter().filter(|access| !access.write).map(|access| access.address).collect(),
+            stores: retired.memory.iter().filter(|access| access.write)
+                .map(|access| (access.address, access.data)).collect(),
+        }
+    }
+
+    /// Parses a line of Spike's commit log, such as
+    /// `core   0: 3 0x00000010 (0x0002a303) x6  0x00000005 mem 0x00000100`.
+    /// Entries other than integer registers and memory, like CSR writes, are skipped.
+    fn parse_spike(line: &str) -> Option<Self> {
+        let (core, rest) = line.strip_prefix("core")?.split_once(':')?;
+        let mut tokens = rest.split_whitespace().peekable();
+        let privilege = tokens.next()?.parse().ok()?;
+        let pc = parse_hex(tokens.next()?)? as usize;
+        let instruction = parse_hex(tokens.next()?.strip_prefix('(')?.strip_suffix(')')?)?;
+
+        let mut commit = Self {
+            hart: core.trim().parse().ok()?,
+            privilege: Some(privilege),
+            pc,
+            instruction,
+            registers: Vec::new(),
+            loads: Vec::new(),
+            stores: Vec::new(),
+        };
+        while let Some(token) = tokens.next() {
+            if token == "mem" {
+                let address = parse_hex(tokens.next()?)? as usize;
+                match tokens.next_if(|token| token.starts_with("0x")) {
+                    Some(data) => commit.stores.push((address, parse_hex(data)?)),
+                    None => commit.loads.push(address),
+                }
+            } else if let Some(register) = token.strip_prefix('x').and_then(|register| register.parse().ok()) {
+                commit.registers.push((register, parse_hex(tokens.next()?)?));
+            }
+        }
+        Some(commit)
+    }
+
+    /// Parses a line of `name=value` pairs named after the RVFI signals, such as
+    /// `pc_rdata=0x10 insn=0x0002a303 rd_addr=6 rd_wdata=0x5 mem_addr=0x100 mem_rmask=0xf`.
+    /// A store writes `mem_wdata` with as many bytes as `mem_wmask` has bits set.
+    fn parse_rvfi(line: &str) -> Option<Self> {
+        let mut commit = Self {
+            hart: 0,
+            privilege: None,
+            pc: 0,
+            instruction: 0,
+            registers: Vec::new(),
+            loads: Vec::new(),
+            stores: Vec::new(),
+        };
+        let (mut rd, mut rd_data, mut address, mut rmask, mut wmask, mut wdata) = (0, 0, 0, 0, 0, 0);
+        for pair in line.split_whitespace() {
+            let (name, value) = pair.split_once('=')?;
+            let value = parse_hex(value).or_else(|| value.parse().ok())?;
+            match name {
+                "hart" => commit.hart = value as usize,
+                "mode" => commit.privilege = Some(value),
+                "pc_rdata" => commit.pc = value as usize,
+                "insn" => commit.instruction = value,
+                "rd_addr" => rd = value as usize,
+                "rd_wdata" => rd_data = value,
+                "mem_addr" => address = value as usize,
+                "mem_rmask" => rmask = value,
+                "mem_wmask" => wmask = value,
+                "mem_wdata" => wdata = value,
+                _ => (),
+            }
+        }
+
+        if rd != 0 {
+            commit.registers.push((rd, rd_data));
+        }
+        if rmask != 0 {
+            commit.loads.push(address);
+        }
+        if wmask != 0 {
+            let size = wmask.count_ones();
+            commit.stores.push((address, if size == 4 { wdata } else { wdata & ((1 << (size * 8)) - 1) }));
+        }
+        Some(commit)
+    }
+
+    /// What differs between the reference `self` and `actual`, one line per field.
+    fn differences(&self, actual: &Commit) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.hart != actual.hart {
+            differences.push(format!("hart: expected {}, got {}", self.hart, actual.hart));
+        }
+        if let (Some(expected), Some(got)) = (self.privilege, actual.privilege) {
+            if expected != got {
+                differences.push(format!("privilege: expected {}, got {}", expected, got));
+            }
+        }
+        if self.pc != actual.pc {
+            differences.push(format!("pc: expected 0x{:08x}, got 0x{:08x}", self.pc, actual.pc));
+        }
+        if self.instruction != actual.instruction {
+            differences.push(format!("instruction: expected 0x{:08x}, got 0x{:08x}", self.instruction, actual.instruction));
+        }
+        if self.registers != actual.registers {
+            differences.push(format!("registers: expected {}, got {}",
+                format_registers(&self.registers), format_registers(&actual.registers)));
+        }
+        if self.loads != actual.loads || self.stores != actual.stores {
+            differences.push(format!("memory: expected {}, got {}",
+                format_memory(&self.loads, &self.stores), format_memory(&actual.loads, &actual.stores)));
+        }
+        differences
+    }
+}
+
+impl fmt::Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "core {:3}: 0x{:08x} (0x{:08x})", self.hart, self.pc, self.instruction)?;
+        if !self.registers.is_empty() {
+            write!(f, " {}", format_registers(&self.registers))?;
+        }
+        if !self.loads.is_empty() || !self.stores.is_empty() {
+            write!(f, " {}", format_memory(&self.loads, &self.stores))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(value: &str) -> Option<u32> {
+    u32::from_str_radix(value.strip_prefix("0x")?, 16).ok()
+}
+
+fn format_registers(registers: &[(usize, u32)]) -> String {
+    if registers.is_empty() {
+        return "none".to_string();
+    }
+    registers.iter().map(|(register, data)| format!("x{}=0x{:08x}", register, data)).collect::<Vec<_>>().join(" ")
+}
+
+fn format_memory(loads: &[usize], stores: &[(usize, u32)]) -> String {
+    if loads.is_empty() && stores.is_empty() {
+        return "none".to_string();
+    }
+    loads.iter().map(|address| format!("load 0x{:08x}", address))
+        .chain(stores.iter().map(|(address, data)| format!("store 0x{:08x} 0x{:x}", address, data)))
+        .collect::<Vec<_>>().join(" ")
+}
+
+/// Compares every committed instruction against a reference commit log, in Spike's
+/// `--log-commits` format or as RVFI `name=value` lines, and reports the first divergence
+/// along with the instructions that led up to it. Instructions that trapped did not commit
+/// and are not compared, and lines that are neither format are skipped.
+pub struct Checker {
+    reference: Box<dyn BufRead>,
+    line_number: usize,
+    history: VecDeque<Commit>,
+    history_size: usize,
+    checked: usize,
+    divergence: Option<String>,
+}
+
+impl Checker {
+    pub fn new(reference: Box<dyn BufRead>, history_size: usize) -> Self {
+        Self { reference, line_number: 0, history: VecDeque::new(), history_size, checked: 0, divergence: None }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.divergence.is_some()
+    }
+
+    /// The report of how execution diverged from the reference, with the matching
+    /// instructions that led up to it, once it has.
+    pub fn get_divergence(&self) -> Option<&str> {
+        self.divergence.as_deref()
+    }
+
+    /// The next commit in the reference. `Err` says why the next line could not be read or
+    /// parsed.
+    fn next_reference(&mut self) -> Result<Option<Commit>, String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            self.line_number += 1;
+            match self.reference.read_line(&mut line) {
+                Ok(0) => return Ok(None),
+                Ok(_) => (),
+                Err(error) => return Err(format!("cannot read line {} of the reference: {}", self.line_number, error)),
+            }
+            let line = line.trim();
+            let commit = if line.starts_with("core") {
+                Commit::parse_spike(line)
+            } else if line.contains('=') {
+                Commit::parse_rvfi(line)
+            } else {
+                continue;
+            };
+            return commit.map(Some).ok_or_else(|| format!("cannot parse line {} of the reference: {}", self.line_number, line));
+        }
+    }
+
+    /// Checks `retired` against the next reference commit, returning false once execution has
+    /// diverged and should stop.
+    pub fn check(&mut self, retired: &Retired) -> bool {
+        if self.failed() {
+            return false;
+        }
+        if retired.trapped {
+            return true;
+        }
+
+        let actual = Commit::from_retired(retired);
+        let expected = match self.next_reference() {
+            Ok(Some(expected)) => expected,
+            Ok(None) => {
+                self.report(&actual, None, &["reference trace ended".to_string()]);
+                return false;
+            }
+            Err(difference) => {
+                self.report(&actual, None, &[difference]);
+                return false;
+            }
+        };
+
+        let differences = expected.differences(&actual);
+        if !differences.is_empty() {
+            self.report(&actual, Some(&expected), &differences);
+            return false;
+        }
+
+        self.checked += 1;
+        self.history.push_back(actual);
+        if self.history.len() > self.history_size {
+            self.history.pop_front();
+        }
+        true
+    }
+
+    /// Fails the check when the reference has commits left once execution has stopped.
+    pub fn finish(&mut self) {
+        if self.failed() {
+            return;
+        }
+        if let Ok(Some(expected)) = self.next_reference() {
+            self.divergence = Some(format!(
+                "Execution stopped after {} matching instructions, but the reference continues with\n  {}\n",
+                self.checked, expected));
+        }
+    }
+
+    fn report(&mut self, actual: &Commit, expected: Option<&Commit>, differences: &[String]) {
+        let mut report = format!("Divergence after {} matching instructions\n", self.checked);
+        if let Some(expected) = expected {
+            writeln!(report, "  expected: {}", expected).unwrap();
+        }
+        writeln!(report, "  actual:   {}", actual).unwrap();
+        for difference in differences {
+            writeln!(report, "  {}", difference).unwrap();
+        }
+        if !self.history.is_empty() {
+            writeln!(report, "Last {} matching instructions:", self.history.len()).unwrap();
+            for commit in &self.history {
+                writeln!(report, "  {}", commit).unwrap();
+            }
+        }
+        self.divergence = Some(report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::check::{Checker, Commit};
+    use crate::instruction::Instruction;
+    use crate::trace::{MemoryAccess, Retired};
+    use crate::trap::Privilege;
+
+    #[test]
+    fn reference_formats_parse_alike() {
+        let spike = Commit::parse_spike("core   0: 3 0x00000010 (0x0002a303) x6  0x00000005 mem 0x00000100").unwrap();
+        let rvfi = Commit::parse_rvfi("mode=3 pc_rdata=0x10 insn=0x0002a303 rd_addr=6 rd_wdata=0x5 mem_addr=0x100 mem_rmask=0xf").unwrap();
+        assert_eq!(spike, rvfi);
+
+        let store = Commit::parse_spike("core   1: 0 0x00000014 (0x00629023) c305_mtvec 0x00000000 mem 0x00000100 0x0005").unwrap();
+        assert_eq!(store.hart, 1);
+        assert_eq!(store.stores, vec![(0x100, 5)]);
+        assert!(store.registers.is_empty());
+    }
+
+    #[test]
+    fn stops_at_first_divergence() {
+        let reference = "\
+core   0: 3 0x00000010 (0x0002a303) x6  0x00000005 mem 0x00000100
+core   0: 3 0x00000014 (0x0002a303) x6  0x00000007 mem 0x00000100
+";
+        let mut checker = Checker::new(Box::new(reference.as_bytes()), 4);
+        let instruction = Instruction::from_u32(0x0002a303);
+        let load = [MemoryAccess { address: 0x100, size: 4, data: 5, write: false }];
+        let mut retired = Retired {
+            hart: 0,
+            privilege: Privilege::Machine,
+            pc: 0x10,
+            instruction: &instruction,
+            trapped: false,
+            registers: &[(6, 5)],
+            csrs: &[],
+            memory: &load,
+        };
+
+        assert!(checker.check(&retired));
+        retired.trapped = true;
+        assert!(checker.check(&retired));
+        retired.trapped = false;
+        retired.pc = 0x14;
+        assert!(!checker.check(&retired));
+        assert!(checker.failed());
+        assert!(checker.get_divergence().unwrap().contains("registers: expected x6=0x00000007, got x6=0x00000005"));
+        assert!(!checker.check(&retired));
+    }
+}
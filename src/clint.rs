@@ -0,0 +1,117 @@
+use crate::error::Error;
+use crate::snapshot::{Reader, Writer};
+
+pub const CLINT_BASE: usize = 0x0200_0000;
+pub const CLINT_SIZE: usize = 0x0001_0000;
+
+const MSIP: usize = 0x0000;
+const MTIMECMP: usize = 0x4000;
+const MTIME: usize = 0xBFF8;
+
+/// Core-local interruptor: a software interrupt register and timer compare per hart,
+/// and the shared `mtime`, laid out like the SiFive CLINT.
+pub struct Clint {
+    msip: Vec<u32>,
+    mtimecmp: Vec<u64>,
+    mtime: u64,
+}
+
+impl Clint {
+    pub fn new(harts: usize) -> Self {
+        Self { msip: vec![0; harts], mtimecmp: vec![u64::MAX; harts], mtime: 0 }
+    }
+
+    pub fn get_mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn tick(&mut self) {
+        self.advance(1);
+    }
+
+    pub fn advance(&mut self, ticks: u64) {
+        self.mtime = self.mtime.wrapping_add(ticks);
+    }
+
+    pub fn software_pending(&self, hart: usize) -> bool {
+        self.msip[hart] & 1 != 0
+    }
+
+    pub fn timer_pending(&self, hart: usize) -> bool {
+        self.mtime >= self.mtimecmp[hart]
+    }
+
+    fn replace_half(value: u64, offset: usize, data: u32) -> u64 {
+        if offset & 4 == 0 {
+            value & !0xFFFFFFFF | data as u64
+        } else {
+            value & 0xFFFFFFFF | (data as u64) << 32
+        }
+    }
+
+    /// Reads the aligned word at `offset` from the CLINT base. Unmapped offsets read as zero.
+    pub fn read32(&self, offset: usize) -> u32 {
+        let harts = self.msip.len();
+        match offset {
+            MSIP..MTIMECMP if offset / 4 < harts => self.msip[offset / 4],
+            MTIMECMP..MTIME if (offset - MTIMECMP) / 8 < harts => {
+                (self.mtimecmp[(offset - MTIMECMP) / 8] >> ((offset & 4) * 8)) as u32
+            }
+            MTIME.. => (self.mtime >> ((offset & 4) * 8)) as u32,
+            _ => 0,
+        }
+    }
+
+    pub fn save(&self, snapshot: &mut Writer) {
+        snapshot.write_u64(self.mtime);
+        for (msip, mtimecmp) in self.msip.iter().zip(&self.mtimecmp) {
+            snapshot.write_u32(*msip);
+            snapshot.write_u64(*mtimecmp);
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        self.mtime = snapshot.read_u64()?;
+        for (msip, mtimecmp) in self.msip.iter_mut().zip(self.mtimecmp.iter_mut()) {
+            *msip = snapshot.read_u32()?;
+            *mtimecmp = snapshot.read_u64()?;
+        }
+        Ok(())
+    }
+
+    pub fn write32(&mut self, offset: usize, data: u32) {
+        let harts = self.msip.len();
+        match offset {
+            MSIP..MTIMECMP if offset / 4 < harts => self.msip[offset / 4] = data & 1,
+            MTIMECMP..MTIME if (offset - MTIMECMP) / 8 < harts => {
+                let hart = (offset - MTIMECMP) / 8;
+                self.mtimecmp[hart] = Self::replace_half(self.mtimecmp[hart], offset, data);
+            }
+            MTIME.. => self.mtime = Self::replace_half(self.mtime, offset, data),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clint::Clint;
+
+    #[test]
+    fn clint_registers_work() {
+        let mut clint = Clint::new(2);
+        clint.write32(0x4, 1);
+        assert!(clint.software_pending(1));
+        assert!(!clint.software_pending(0));
+
+        clint.write32(0x4008, 2);
+        clint.write32(0x400C, 0);
+        assert!(!clint.timer_pending(1));
+        clint.tick();
+        clint.tick();
+        assert!(clint.timer_pending(1));
+        assert!(!clint.timer_pending(0));
+        assert_eq!(clint.read32(0xBFF8), 2);
+        assert_eq!(clint.read32(0x4008), 2);
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::elf::Elf;
+use crate::machine::MachineBuilder;
+
+/// Steps a test may take before it is considered hung.
+const STEP_LIMIT: usize = 10_000_000;
+
+/// How a conformance test ended.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// `tohost` was written 1, and the signature matches its reference if there is one
+    Pass,
+    /// `tohost` was written `test << 1 | 1` for the failing test number
+    Fail(u32),
+    /// The signature differs from the reference in the given number of words
+    SignatureMismatch(usize),
+    /// Every hart stopped before writing `tohost`
+    Halted,
+    /// `tohost` was not written within the step limit
+    Timeout,
+    /// The test could not run here, such as an RV64 test
+    Skipped(String),
+    /// The test could not be loaded
+    Error(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "pass"),
+            Outcome::Fail(test) => write!(f, "FAIL (test {})", test),
+            Outcome::SignatureMismatch(words) => write!(f, "FAIL ({} signature words differ)", words),
+            Outcome::Halted => write!(f, "FAIL (halted)"),
+            Outcome::Timeout => write!(f, "FAIL (timeout)"),
+            Outcome::Skipped(reason) => write!(f, "skipped ({})", reason),
+            Outcome::Error(error) => write!(f, "error ({})", error),
+        }
+    }
+}
+
+/// Runs a riscv-tests or riscv-arch-test executable on a machine from `builder` until it
+/// writes `tohost`. When it has `begin_signature` and `end_signature` symbols, the words
+/// between them are written to `signature`, one per line in hex, and compared with
+/// `reference` if that exists.
+pub fn run_test(bytes: &[u8], builder: &MachineBuilder, signature: &Path, reference: &Path) -> Outcome {
+    if bytes.get(4) == Some(&2) {
+        return Outcome::Skipped("RV64 is not supported".to_string());
+    }
+    let elf = match Elf::parse(bytes) {
+        Ok(elf) => elf,
+        Err(error) => return Outcome::Error(error),
+    };
+    let mut machine = match builder.clone().elf(&elf).build() {
+        Ok(machine) => machine,
+        Err(error) => return Outcome::Error(error.to_string()),
+    };
+
+    let base = elf.base();
+    let tohost = elf.symbol("tohost").map(|tohost| (tohost - base) as usize);
+    let mut steps = 0;
+    let result = loop {
+        if let Some(tohost) = tohost {
+            let result = machine.read32(tohost).unwrap_or(0);
+            if result != 0 {
+                break result;
+            }
+        }
+        if !machine.running() {
+            return Outcome::Halted;
+        }
+        if steps == STEP_LIMIT {
+            return Outcome::Timeout;
+        }
+        machine.step();
+        steps += 1;
+    };
+    if result != 1 {
+        return Outcome::Fail(result >> 1);
+    }
+
+    let (Some(begin), Some(end)) = (elf.symbol("begin_signature"), elf.symbol("end_signature")) else {
+        return Outcome::Pass;
+    };
+    let words: Vec<String> = ((begin - base) as usize..(end - base) as usize).step_by(4)
+        .map(|address| format!("{:08x}", machine.read32(address).unwrap_or(0)))
+        .collect();
+    if let Err(error) = fs::write(signature, words.iter().map(|word| format!("{}\n", word)).collect::<String>()) {
+        return Outcome::Error(format!("cannot write signature: {}", error));
+    }
+
+    let Ok(reference) = fs::read_to_string(reference) else {
+        return Outcome::Pass;
+    };
+    let expected: Vec<&str> = reference.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    let differences = words.iter().zip(&expected).filter(|(word, expected)| !word.eq_ignore_ascii_case(expected)).count()
+        + words.len().abs_diff(expected.len());
+    if differences != 0 {
+        return Outcome::SignatureMismatch(differences);
+    }
+    Outcome::Pass
+}
+
+/// Runs every test in `files`, returning each test's name, taken from its file name, and
+/// outcome. Signatures go to `<signature_dir>/<test>.signature`, next to the test by default,
+/// and are checked against a `<test>.reference_output` next to the test.
+pub fn run_tests(files: &[String], builder: &MachineBuilder, signature_dir: Option<&str>) -> Vec<(String, Outcome)> {
+    files.iter().map(|file| {
+        let name = test_name(file);
+        let directory = Path::new(file).parent().unwrap_or(Path::new(""));
+        let signature: PathBuf = signature_dir.map(Path::new).unwrap_or(directory).join(format!("{}.signature", name));
+        let reference = directory.join(format!("{}.reference_output", name));
+
+        let outcome = match fs::read(file) {
+            Ok(bytes) => run_test(&bytes, builder, &signature, &reference),
+            Err(error) => Outcome::Error(error.to_string()),
+        };
+        (name, outcome)
+    }).collect()
+}
+
+fn test_name(file: &str) -> String {
+    let path = Path::new(file);
+    path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::conformance::{run_test, Outcome};
+    use crate::elf::tests::executable;
+    use crate::machine::MachineBuilder;
+
+    fn run(code: &[u32]) -> Outcome {
+        let missing = Path::new("/nonexistent/test.signature");
+        run_test(&executable(code), &MachineBuilder::new(0x2000), missing, missing)
+    }
+
+    #[test]
+    fn tohost_reports_the_result() {
+        // auipc x5,1; addi x6,x0,1; sw x6,0(x5); jal x0,0
+        assert_eq!(run(&[0x00001297, 0x00100313, 0x0062a023, 0x0000006f]), Outcome::Pass);
+        // auipc x5,1; addi x6,x0,7; sw x6,0(x5); jal x0,0
+        assert_eq!(run(&[0x00001297, 0x00700313, 0x0062a023, 0x0000006f]), Outcome::Fail(3));
+        // ebreak
+        assert_eq!(run(&[0x00100073]), Outcome::Halted);
+    }
+}
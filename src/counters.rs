@@ -0,0 +1,148 @@
+use crate::error::Error;
+use crate::snapshot::{Reader, Writer};
+
+pub const CYCLE: usize = 0;
+const TIME: usize = 1;
+pub const INSTRET: usize = 2;
+
+/// Events a `mhpmevent` selector can count. Zero leaves the counter idle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Load = 1,
+    Store = 2,
+    TakenBranch = 3,
+    Trap = 4,
+}
+
+/// The 64-bit `mcycle`, `minstret` and `mhpmcounter3..31` counters, indexed like their
+/// CSR addresses, together with the event selectors and `mcountinhibit`. Slot 1 is left
+/// empty since `time` is a shadow of the CLINT's `mtime`.
+pub struct Counters {
+    counters: [u64; 32],
+    events: [u32; 32],
+    inhibit: u32,
+}
+
+impl Counters {
+    pub fn new() -> Self {
+        Self { counters: [0; 32], events: [0; 32], inhibit: 0 }
+    }
+
+    pub fn save(&self, snapshot: &mut Writer) {
+        for (counter, event) in self.counters.iter().zip(&self.events) {
+            snapshot.write_u64(*counter);
+            snapshot.write_u32(*event);
+        }
+        snapshot.write_u32(self.inhibit);
+    }
+
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        for (counter, event) in self.counters.iter_mut().zip(self.events.iter_mut()) {
+            *counter = snapshot.read_u64()?;
+            *event = snapshot.read_u32()?;
+        }
+        self.inhibit = snapshot.read_u32()?;
+        Ok(())
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        self.counters[index]
+    }
+
+    pub fn set_low(&mut self, index: usize, data: u32) {
+        if index == TIME {
+            return;
+        }
+        self.counters[index] = self.counters[index] & !0xFFFFFFFF | data as u64;
+    }
+
+    pub fn set_high(&mut self, index: usize, data: u32) {
+        if index == TIME {
+            return;
+        }
+        self.counters[index] = self.counters[index] & 0xFFFFFFFF | (data as u64) << 32;
+    }
+
+    pub fn get_event(&self, index: usize) -> u32 {
+        self.events[index]
+    }
+
+    pub fn set_event(&mut self, index: usize, event: u32) {
+        self.events[index] = event;
+    }
+
+    pub fn get_inhibit(&self) -> u32 {
+        self.inhibit
+    }
+
+    pub fn set_inhibit(&mut self, inhibit: u32) {
+        self.inhibit = inhibit & !(1 << TIME);
+    }
+
+    fn increment(&mut self, index: usize) {
+        self.add(index, 1);
+    }
+
+    fn add(&mut self, index: usize, count: u64) {
+        if self.inhibit >> index & 1 == 0 {
+            self.counters[index] = self.counters[index].wrapping_add(count);
+        }
+    }
+
+    /// Advances the clock by one instruction.
+    pub fn tick(&mut self, retired: bool) {
+        self.increment(CYCLE);
+        if retired {
+            self.increment(INSTRET);
+        }
+    }
+
+    /// Advances the clock by `instructions` instructions, `retired` of which retired.
+    pub fn advance(&mut self, instructions: u64, retired: u64) {
+        self.add(CYCLE, instructions);
+        self.add(INSTRET, retired);
+    }
+
+    /// Whether any `mhpmcounter` counts events, which must then be recorded per instruction.
+    pub fn counts_events(&self) -> bool {
+        self.events[3..].iter().any(|&event| event != 0)
+    }
+
+    pub fn record(&mut self, event: Event) {
+        for index in 3..32 {
+            if self.events[index] == event as u32 {
+                self.increment(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::counters::{Counters, Event, CYCLE, INSTRET};
+
+    #[test]
+    fn counters_count_correctly() {
+        let mut counters = Counters::new();
+        counters.set_event(3, Event::Load as u32);
+        counters.set_event(4, Event::Trap as u32);
+
+        counters.tick(true);
+        counters.tick(false);
+        counters.record(Event::Load);
+        assert_eq!(counters.get(CYCLE), 2);
+        assert_eq!(counters.get(INSTRET), 1);
+        assert_eq!(counters.get(3), 1);
+        assert_eq!(counters.get(4), 0);
+
+        counters.set_inhibit(0b1111);
+        counters.tick(true);
+        counters.record(Event::Load);
+        assert_eq!(counters.get(CYCLE), 2);
+        assert_eq!(counters.get(3), 1);
+        assert_eq!(counters.get_inhibit(), 0b1101);
+
+        counters.set_high(CYCLE, 1);
+        assert_eq!(counters.get(CYCLE), 0x100000002);
+    }
+}
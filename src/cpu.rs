@@ -1,21 +1,175 @@
-use crate::instruction::{Instruction, InstructionType};
-use crate::memory::Memory;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bitmanip;
+use crate::bus::Bus;
+use crate::check::Checker;
+use crate::counters::{Counters, Event};
+use crate::crypto;
+use crate::csr::*;
+use crate::cpu::hooks::{Action, CsrAccess, Hooks, InstructionEvent, TrapEntry};
+use crate::decode_cache::Decoded;
+use crate::error::Error;
+use crate::instruction::InstructionType;
+use crate::isa::Isa;
+use crate::pmp::{Access, Pmp};
 use crate::registers::Registers;
+use crate::rvfi::Rvfi;
+use crate::snapshot::{Reader, Writer};
+use crate::trace::{MemoryAccess, Retired, Tracer};
+use crate::trap::{Exception, Misaligned, Privilege};
+use crate::vector_registers::VectorRegisters;
+
+pub(crate) mod block;
+pub(crate) mod hooks;
+mod vector;
+
+/// The observers `CPU::take_observers` detached from a hart.
+pub(crate) struct Observers {
+    tracer: Option<Rc<RefCell<Tracer>>>,
+    checker: Option<Rc<RefCell<Checker>>>,
+    rvfi: Option<Rc<RefCell<Rvfi>>>,
+}
 
 pub struct CPU {
-    memory: Memory,
+    hart_id: usize,
+    bus: Rc<RefCell<Bus>>,
     pc: usize,
     registers: Registers,
+    privilege: Privilege,
+    csrs: Csrs,
+    counters: Counters,
+    pmp: Pmp,
+    trapped: bool,
+    vector_registers: VectorRegisters,
+    elen: usize,
+    pub(crate) vector_agnostic_ones: bool,
+    pub(crate) cache_block_size: usize,
+    pub(crate) misaligned: Misaligned,
+    pub(crate) isa: Isa,
     pub(crate) halted: bool,
+    fault: Option<Error>,
+    tracer: Option<Rc<RefCell<Tracer>>>,
+    checker: Option<Rc<RefCell<Checker>>>,
+    rvfi: Option<Rc<RefCell<Rvfi>>>,
+    hooks: Option<Rc<RefCell<Hooks>>>,
+    csr_writes: Vec<(u16, u32)>,
+    memory_accesses: Vec<MemoryAccess>,
 }
 
 impl CPU {
-    pub fn from_memory(memory: &Memory) -> Self {
-        Self { memory: memory.clone(), pc: 0, registers: Registers::new(), halted: false }
+    /// Creates hart `hart_id` attached to a `bus` that may be shared with other harts.
+    pub fn new(hart_id: usize, bus: Rc<RefCell<Bus>>) -> Self {
+        let mut cpu = Self {
+            hart_id,
+            bus,
+            pc: 0,
+            registers: Registers::new(),
+            privilege: Privilege::Machine,
+            csrs: Csrs::new(),
+            counters: Counters::new(),
+            pmp: Pmp::new(),
+            trapped: false,
+            vector_registers: VectorRegisters::new(128),
+            elen: 64,
+            vector_agnostic_ones: false,
+            cache_block_size: 64,
+            misaligned: Misaligned::Allow,
+            isa: Isa::all(),
+            halted: false,
+            fault: None,
+            tracer: None,
+            checker: None,
+            rvfi: None,
+            hooks: None,
+            csr_writes: Vec::new(),
+            memory_accesses: Vec::new(),
+        };
+        cpu.set_vector_config(128, 64);
+        cpu
+    }
+
+    /// Traces every instruction this hart retires, with the registers and memory it touched.
+    pub fn set_tracer(&mut self, tracer: Rc<RefCell<Tracer>>) {
+        self.tracer = Some(tracer);
+        self.registers.record_writes();
+    }
+
+    /// Checks every instruction this hart retires against a reference trace, halting at the
+    /// first divergence.
+    pub fn set_checker(&mut self, checker: Rc<RefCell<Checker>>) {
+        self.checker = Some(checker);
+        self.registers.record_writes();
+    }
+
+    /// Reports every instruction this hart retires as an RVFI packet.
+    pub fn set_rvfi(&mut self, rvfi: Rc<RefCell<Rvfi>>) {
+        self.rvfi = Some(rvfi);
+        self.registers.record_writes();
+    }
+
+    /// Detaches the tracer, checker and RVFI reporter, which should not see history replayed.
+    /// Hooks stay attached, since they may take part in execution.
+    pub(crate) fn take_observers(&mut self) -> Observers {
+        let observers = Observers { tracer: self.tracer.take(), checker: self.checker.take(), rvfi: self.rvfi.take() };
+        if !self.observed() {
+            self.registers.stop_recording_writes();
+        }
+        observers
+    }
+
+    pub(crate) fn set_observers(&mut self, observers: Observers) {
+        (self.tracer, self.checker, self.rvfi) = (observers.tracer, observers.checker, observers.rvfi);
+        if self.observed() {
+            self.registers.record_writes();
+        }
+    }
+
+    /// Whether retired instructions are traced, checked, reported or hooked, so their effects
+    /// must be recorded.
+    fn observed(&self) -> bool {
+        self.tracer.is_some() || self.checker.is_some() || self.rvfi.is_some() || self.hooks.is_some()
+    }
+
+    pub fn get_pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    pub fn get_register(&self, register: usize) -> u32 {
+        self.registers.get(register)
+    }
+
+    pub fn set_register(&mut self, register: usize, data: u32) {
+        self.registers.set(register, data)
+    }
+
+    /// Saves the hart's architectural state. Configuration such as the ISA, and attached
+    /// observers and hooks, are left to whoever restores it.
+    pub fn save(&self, snapshot: &mut Writer) {
+        snapshot.write_u64(self.pc as u64);
+        snapshot.write_u8(self.privilege as u8);
+        snapshot.write_u8(self.halted as u8);
+        self.registers.save(snapshot);
+        self.csrs.save(snapshot);
+        self.counters.save(snapshot);
+        self.pmp.save(snapshot);
+        self.vector_registers.save(snapshot);
     }
 
-    pub fn dump_memory(&self) {
-        self.memory.dump()
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        self.pc = snapshot.read_usize()?;
+        self.privilege = Privilege::from_bits(snapshot.read_u8()? as u32);
+        self.halted = snapshot.read_u8()? != 0;
+        self.fault = None;
+        self.registers.restore(snapshot)?;
+        self.csrs.restore(snapshot)?;
+        self.counters.restore(snapshot)?;
+        self.pmp.restore(snapshot)?;
+        self.vector_registers.restore(snapshot)
     }
 
     pub fn dump_registers(&self) {
@@ -23,332 +177,1140 @@ impl CPU {
         println!(" pc  {:08x}", self.pc);
     }
 
-    pub fn run(&mut self) {
-        while self.pc < self.memory.len() - 4 && !self.halted {
-            self.tick()
-        }
-    }
-
-    pub fn tick(&mut self) {
-        let instruction = Instruction::from_u32(self.memory.get32(self.pc));
-        println!("{:08x}    {}", self.pc, instruction);
-        self.execute_instruction(&instruction);
-    }
-
-    pub fn execute_instruction(&mut self, instruction: &Instruction) {
-        match instruction._type() {
-            Ok(_type) => match _type {
-                InstructionType::LUI => self.execute_lui(instruction),
-                InstructionType::AUIPC => self.execute_auipc(instruction),
-                InstructionType::JAL => self.execute_jal(instruction),
-                InstructionType::JALR => self.execute_jalr(instruction),
-                InstructionType::BEQ => self.execute_beq(instruction),
-                InstructionType::BNE => self.execute_bne(instruction),
-                InstructionType::BLT => self.execute_blt(instruction),
-                InstructionType::BGE => self.execute_bge(instruction),
-                InstructionType::BLTU => self.execute_bltu(instruction),
-                InstructionType::BGEU => self.execute_bgeu(instruction),
-                InstructionType::LB => self.execute_lb(instruction),
-                InstructionType::LH => self.execute_lh(instruction),
-                InstructionType::LW => self.execute_lw(instruction),
-                InstructionType::LBU => self.execute_lbu(instruction),
-                InstructionType::LHU => self.execute_lhu(instruction),
-                InstructionType::SB => self.execute_sb(instruction),
-                InstructionType::SH => self.execute_sh(instruction),
-                InstructionType::SW => self.execute_sw(instruction),
-                InstructionType::ADDI => self.execute_addi(instruction),
-                InstructionType::SLTI => self.execute_slti(instruction),
-                InstructionType::SLTIU => self.execute_sltiu(instruction),
-                InstructionType::XORI => self.execute_xori(instruction),
-                InstructionType::ORI => self.execute_ori(instruction),
-                InstructionType::ANDI => self.execute_andi(instruction),
-                InstructionType::SLLI => self.execute_slli(instruction),
-                InstructionType::SRLI => self.execute_srli(instruction),
-                InstructionType::SRAI => self.execute_srai(instruction),
-                InstructionType::ADD => self.execute_add(instruction),
-                InstructionType::SUB => self.execute_sub(instruction),
-                InstructionType::SLL => self.execute_sll(instruction),
-                InstructionType::SLT => self.execute_slt(instruction),
-                InstructionType::SLTU => self.execute_sltu(instruction),
-                InstructionType::XOR => self.execute_xor(instruction),
-                InstructionType::SRL => self.execute_srl(instruction),
-                InstructionType::SRA => self.execute_sra(instruction),
-                InstructionType::OR => self.execute_or(instruction),
-                InstructionType::AND => self.execute_and(instruction),
-                InstructionType::ECALL => self.pc += 4,
-                InstructionType::CSRRW => self.pc += 4,
-                InstructionType::CSRRS => self.pc += 4,
-                InstructionType::CSRRC => self.pc += 4,
-                InstructionType::CSRRWI => self.pc += 4,
-                InstructionType::CSRRSI => self.pc += 4,
-                InstructionType::CSRRCI => self.pc += 4,
-                InstructionType::EBREAK => self.halted = true,
+    pub fn dump_csrs(&self) {
+        println!("privilege  {:?}", self.privilege);
+        for (i, csr) in DUMPED.iter().enumerate() {
+            print!("{:<12}{:08x}", csr_name(*csr), self.read_csr(*csr));
+            if i % 4 == 3 { println!() } else { print!("  ") }
+        }
+        println!();
+    }
+
+    /// Whether the hart has work left: it has not halted or run off the end of memory.
+    pub fn running(&self) -> bool {
+        !self.halted && self.pc < self.bus.borrow().len().saturating_sub(4)
+    }
+
+    /// The guest fault that stopped this hart, if any.
+    pub fn get_fault(&self) -> Option<&Error> {
+        self.fault.as_ref()
+    }
+
+    /// Lets a hart stopped by a hook or EBREAK run again. Harts stopped by a fault stay stopped.
+    pub fn resume(&mut self) {
+        if self.fault.is_none() {
+            self.halted = false;
+        }
+    }
+
+    /// Stops the hart on `error`, leaving it for the embedder to report.
+    fn fault(&mut self, error: Error) {
+        self.fault = Some(error);
+        self.halted = true;
+    }
+
+    /// Takes an interrupt or runs the next instruction, returning how many steps that was:
+    /// none when a fetch hook stopped the hart before the instruction ran.
+    pub fn tick(&mut self) -> usize {
+        if self.take_interrupt() {
+            return 1;
+        }
+        if !self.check_access(self.pc, 4, Access::Execute) {
+            return 1;
+        }
+        if self.bus.borrow().is_unfenced(self.hart_id, self.pc, 4) {
+            self.fault(Error::UnfencedFetch { hart: self.hart_id, pc: self.pc });
+            return 1;
+        }
+        let decoded = self.bus.borrow_mut().fetch(self.pc);
+        match decoded {
+            Ok(decoded) => self.execute_instruction(&decoded) as usize,
+            Err(error) => {
+                self.fault(error);
+                1
             }
-            _ => ()
         }
     }
 
-    pub fn execute_lui(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let imm = instruction.get_imm_u() << 12;
+    /// Runs `decoded`, returning false if a fetch hook stopped the hart before it ran.
+    pub fn execute_instruction(&mut self, decoded: &Decoded) -> bool {
+        if self.hook_instruction(InstructionEvent::Fetch, &decoded.instruction) == Action::Stop {
+            return false;
+        }
+        let (pc, privilege) = (self.pc, self.privilege);
+        self.trapped = false;
+        self.dispatch(decoded);
+        self.retire(decoded, pc, privilege);
+        true
+    }
 
-        self.registers.set(rd as usize, imm);
+    /// Traces, checks, reports and hooks `decoded`, fetched from `pc` and executed at
+    /// `privilege`, and updates the counters once it has finished.
+    fn retire(&mut self, decoded: &Decoded, pc: usize, privilege: Privilege) {
+        if self.observed() {
+            let registers = self.registers.take_writes();
+            let (mut csrs, mut memory) = (std::mem::take(&mut self.csr_writes), std::mem::take(&mut self.memory_accesses));
+            let retired = Retired {
+                hart: self.hart_id,
+                privilege,
+                pc,
+                instruction: &decoded.instruction,
+                trapped: self.trapped,
+                registers: &registers,
+                csrs: &csrs,
+                memory: &memory,
+            };
+            let traced = self.tracer.as_ref().map_or(Ok(()), |tracer| tracer.borrow_mut().record(&retired));
+            if let Some(checker) = self.checker.as_ref() {
+                if !checker.borrow_mut().check(&retired) {
+                    self.halted = true;
+                }
+            }
+            if let Some(rvfi) = self.rvfi.as_ref() {
+                rvfi.borrow_mut().record(&retired, self.pc);
+            }
+            if let Err(error) = traced {
+                self.fault(error.into());
+            }
+            self.hook_retire(&retired);
+            csrs.clear();
+            memory.clear();
+            (self.csr_writes, self.memory_accesses) = (csrs, memory);
+        }
+
+        self.bus.borrow_mut().clint.tick();
+        self.counters.tick(!self.trapped);
+        if self.trapped {
+            return;
+        }
+
+        let event = match decoded._type {
+            Some(InstructionType::LB | InstructionType::LH | InstructionType::LW
+                | InstructionType::LBU | InstructionType::LHU | InstructionType::LR_W) => Event::Load,
+            Some(InstructionType::SB | InstructionType::SH | InstructionType::SW
+                | InstructionType::CBO_ZERO | InstructionType::SC_W | InstructionType::AMOSWAP_W
+                | InstructionType::AMOADD_W | InstructionType::AMOXOR_W | InstructionType::AMOAND_W
+                | InstructionType::AMOOR_W | InstructionType::AMOMIN_W | InstructionType::AMOMAX_W
+                | InstructionType::AMOMINU_W | InstructionType::AMOMAXU_W) => Event::Store,
+            Some(InstructionType::BEQ | InstructionType::BNE | InstructionType::BLT
+                | InstructionType::BGE | InstructionType::BLTU | InstructionType::BGEU)
+                if self.pc != pc + 4 => Event::TakenBranch,
+            Some(_) if decoded.instruction.opcode() == 0b0000111 => Event::Load,
+            Some(_) if decoded.instruction.opcode() == 0b0100111 => Event::Store,
+            _ => return,
+        };
+        self.counters.record(event);
+    }
+
+    fn dispatch(&mut self, decoded: &Decoded) {
+        let Some(_type) = decoded._type.filter(|_type| self.isa.supports(*_type)) else {
+            return self.illegal_instruction(decoded);
+        };
+        Self::handler(_type)(self, decoded)
+    }
+
+    /// The `execute_*` method that carries out instructions of type `_type`.
+    fn handler(_type: InstructionType) -> fn(&mut Self, &Decoded) {
+        match _type {
+            InstructionType::LUI => Self::execute_lui,
+            InstructionType::AUIPC => Self::execute_auipc,
+            InstructionType::JAL => Self::execute_jal,
+            InstructionType::JALR => Self::execute_jalr,
+            InstructionType::BEQ => Self::execute_beq,
+            InstructionType::BNE => Self::execute_bne,
+            InstructionType::BLT => Self::execute_blt,
+            InstructionType::BGE => Self::execute_bge,
+            InstructionType::BLTU => Self::execute_bltu,
+            InstructionType::BGEU => Self::execute_bgeu,
+            InstructionType::LB => Self::execute_lb,
+            InstructionType::LH => Self::execute_lh,
+            InstructionType::LW => Self::execute_lw,
+            InstructionType::LBU => Self::execute_lbu,
+            InstructionType::LHU => Self::execute_lhu,
+            InstructionType::SB => Self::execute_sb,
+            InstructionType::SH => Self::execute_sh,
+            InstructionType::SW => Self::execute_sw,
+            InstructionType::ADDI => Self::execute_addi,
+            InstructionType::SLTI => Self::execute_slti,
+            InstructionType::SLTIU => Self::execute_sltiu,
+            InstructionType::XORI => Self::execute_xori,
+            InstructionType::ORI => Self::execute_ori,
+            InstructionType::ANDI => Self::execute_andi,
+            InstructionType::SLLI => Self::execute_slli,
+            InstructionType::SRLI => Self::execute_srli,
+            InstructionType::SRAI => Self::execute_srai,
+            InstructionType::ADD => Self::execute_add,
+            InstructionType::SUB => Self::execute_sub,
+            InstructionType::SLL => Self::execute_sll,
+            InstructionType::SLT => Self::execute_slt,
+            InstructionType::SLTU => Self::execute_sltu,
+            InstructionType::XOR => Self::execute_xor,
+            InstructionType::SRL => Self::execute_srl,
+            InstructionType::SRA => Self::execute_sra,
+            InstructionType::OR => Self::execute_or,
+            InstructionType::AND => Self::execute_and,
+            InstructionType::ECALL => Self::execute_ecall,
+            InstructionType::MRET => Self::execute_mret,
+            InstructionType::WFI => Self::execute_nop,
+            InstructionType::CSRRW => Self::execute_csrrw,
+            InstructionType::CSRRS => Self::execute_csrrs,
+            InstructionType::CSRRC => Self::execute_csrrc,
+            InstructionType::CSRRWI => Self::execute_csrrwi,
+            InstructionType::CSRRSI => Self::execute_csrrsi,
+            InstructionType::CSRRCI => Self::execute_csrrci,
+            InstructionType::EBREAK => Self::execute_ebreak,
+            InstructionType::SH1ADD => Self::execute_sh1add,
+            InstructionType::SH2ADD => Self::execute_sh2add,
+            InstructionType::SH3ADD => Self::execute_sh3add,
+            InstructionType::ANDN => Self::execute_andn,
+            InstructionType::ORN => Self::execute_orn,
+            InstructionType::XNOR => Self::execute_xnor,
+            InstructionType::MIN => Self::execute_min,
+            InstructionType::MAX => Self::execute_max,
+            InstructionType::MINU => Self::execute_minu,
+            InstructionType::MAXU => Self::execute_maxu,
+            InstructionType::ROL => Self::execute_rol,
+            InstructionType::ROR => Self::execute_ror,
+            InstructionType::CLMUL => Self::execute_clmul,
+            InstructionType::CLMULH => Self::execute_clmulh,
+            InstructionType::CLMULR => Self::execute_clmulr,
+            InstructionType::BSET => Self::execute_bset,
+            InstructionType::BCLR => Self::execute_bclr,
+            InstructionType::BINV => Self::execute_binv,
+            InstructionType::BEXT => Self::execute_bext,
+            InstructionType::CLZ => Self::execute_clz,
+            InstructionType::CTZ => Self::execute_ctz,
+            InstructionType::CPOP => Self::execute_cpop,
+            InstructionType::SEXT_B => Self::execute_sext_b,
+            InstructionType::SEXT_H => Self::execute_sext_h,
+            InstructionType::ZEXT_H => Self::execute_zext_h,
+            InstructionType::ORC_B => Self::execute_orc_b,
+            InstructionType::REV8 => Self::execute_rev8,
+            InstructionType::RORI => Self::execute_rori,
+            InstructionType::BSETI => Self::execute_bseti,
+            InstructionType::BCLRI => Self::execute_bclri,
+            InstructionType::BINVI => Self::execute_binvi,
+            InstructionType::BEXTI => Self::execute_bexti,
+            InstructionType::PACK => Self::execute_pack,
+            InstructionType::PACKH => Self::execute_packh,
+            InstructionType::XPERM4 => Self::execute_xperm4,
+            InstructionType::XPERM8 => Self::execute_xperm8,
+            InstructionType::SHA512SIG0H => Self::execute_sha512sig0h,
+            InstructionType::SHA512SIG0L => Self::execute_sha512sig0l,
+            InstructionType::SHA512SIG1H => Self::execute_sha512sig1h,
+            InstructionType::SHA512SIG1L => Self::execute_sha512sig1l,
+            InstructionType::SHA512SUM0R => Self::execute_sha512sum0r,
+            InstructionType::SHA512SUM1R => Self::execute_sha512sum1r,
+            InstructionType::BREV8 => Self::execute_brev8,
+            InstructionType::ZIP => Self::execute_zip,
+            InstructionType::UNZIP => Self::execute_unzip,
+            InstructionType::SHA256SIG0 => Self::execute_sha256sig0,
+            InstructionType::SHA256SIG1 => Self::execute_sha256sig1,
+            InstructionType::SHA256SUM0 => Self::execute_sha256sum0,
+            InstructionType::SHA256SUM1 => Self::execute_sha256sum1,
+            InstructionType::SM3P0 => Self::execute_sm3p0,
+            InstructionType::SM3P1 => Self::execute_sm3p1,
+            InstructionType::AES32ESI => Self::execute_aes32esi,
+            InstructionType::AES32ESMI => Self::execute_aes32esmi,
+            InstructionType::AES32DSI => Self::execute_aes32dsi,
+            InstructionType::AES32DSMI => Self::execute_aes32dsmi,
+            InstructionType::SM4ED => Self::execute_sm4ed,
+            InstructionType::SM4KS => Self::execute_sm4ks,
+            InstructionType::CZERO_EQZ => Self::execute_czero_eqz,
+            InstructionType::CZERO_NEZ => Self::execute_czero_nez,
+            InstructionType::CBO_CLEAN => Self::execute_cbo_clean,
+            InstructionType::CBO_FLUSH => Self::execute_cbo_flush,
+            InstructionType::CBO_INVAL => Self::execute_cbo_inval,
+            InstructionType::CBO_ZERO => Self::execute_cbo_zero,
+            InstructionType::PAUSE => Self::execute_nop,
+            InstructionType::FENCE => Self::execute_nop,
+            InstructionType::FENCE_I => Self::execute_fence_i,
+            InstructionType::LR_W => Self::execute_lr_w,
+            InstructionType::SC_W => Self::execute_sc_w,
+            InstructionType::AMOSWAP_W => Self::execute_amoswap_w,
+            InstructionType::AMOADD_W => Self::execute_amoadd_w,
+            InstructionType::AMOXOR_W => Self::execute_amoxor_w,
+            InstructionType::AMOAND_W => Self::execute_amoand_w,
+            InstructionType::AMOOR_W => Self::execute_amoor_w,
+            InstructionType::AMOMIN_W => Self::execute_amomin_w,
+            InstructionType::AMOMAX_W => Self::execute_amomax_w,
+            InstructionType::AMOMINU_W => Self::execute_amominu_w,
+            InstructionType::AMOMAXU_W => Self::execute_amomaxu_w,
+            InstructionType::VSETVLI => Self::execute_vsetvli,
+            InstructionType::VSETIVLI => Self::execute_vsetivli,
+            InstructionType::VSETVL => Self::execute_vsetvl,
+            InstructionType::VLE => Self::execute_vle,
+            InstructionType::VLEFF => Self::execute_vleff,
+            InstructionType::VLM => Self::execute_vlm,
+            InstructionType::VLR => Self::execute_vlr,
+            InstructionType::VLSE => Self::execute_vlse,
+            InstructionType::VLUXEI => Self::execute_vluxei,
+            InstructionType::VLOXEI => Self::execute_vloxei,
+            InstructionType::VSE => Self::execute_vse,
+            InstructionType::VSM => Self::execute_vsm,
+            InstructionType::VSR => Self::execute_vsr,
+            InstructionType::VSSE => Self::execute_vsse,
+            InstructionType::VSUXEI => Self::execute_vsuxei,
+            InstructionType::VSOXEI => Self::execute_vsoxei,
+            InstructionType::VADD => Self::execute_vadd,
+            InstructionType::VSUB => Self::execute_vsub,
+            InstructionType::VRSUB => Self::execute_vrsub,
+            InstructionType::VMINU => Self::execute_vminu,
+            InstructionType::VMIN => Self::execute_vmin,
+            InstructionType::VMAXU => Self::execute_vmaxu,
+            InstructionType::VMAX => Self::execute_vmax,
+            InstructionType::VAND => Self::execute_vand,
+            InstructionType::VOR => Self::execute_vor,
+            InstructionType::VXOR => Self::execute_vxor,
+            InstructionType::VRGATHER => Self::execute_vrgather,
+            InstructionType::VRGATHEREI16 => Self::execute_vrgatherei16,
+            InstructionType::VSLIDEUP => Self::execute_vslideup,
+            InstructionType::VSLIDEDOWN => Self::execute_vslidedown,
+            InstructionType::VADC => Self::execute_vadc,
+            InstructionType::VMADC => Self::execute_vmadc,
+            InstructionType::VSBC => Self::execute_vsbc,
+            InstructionType::VMSBC => Self::execute_vmsbc,
+            InstructionType::VMERGE => Self::execute_vmerge,
+            InstructionType::VMV_V => Self::execute_vmv_v,
+            InstructionType::VMSEQ => Self::execute_vmseq,
+            InstructionType::VMSNE => Self::execute_vmsne,
+            InstructionType::VMSLTU => Self::execute_vmsltu,
+            InstructionType::VMSLT => Self::execute_vmslt,
+            InstructionType::VMSLEU => Self::execute_vmsleu,
+            InstructionType::VMSLE => Self::execute_vmsle,
+            InstructionType::VMSGTU => Self::execute_vmsgtu,
+            InstructionType::VMSGT => Self::execute_vmsgt,
+            InstructionType::VSADDU => Self::execute_vsaddu,
+            InstructionType::VSADD => Self::execute_vsadd,
+            InstructionType::VSSUBU => Self::execute_vssubu,
+            InstructionType::VSSUB => Self::execute_vssub,
+            InstructionType::VSLL => Self::execute_vsll,
+            InstructionType::VSMUL => Self::execute_vsmul,
+            InstructionType::VMVR => Self::execute_vmvr,
+            InstructionType::VSRL => Self::execute_vsrl,
+            InstructionType::VSRA => Self::execute_vsra,
+            InstructionType::VSSRL => Self::execute_vssrl,
+            InstructionType::VSSRA => Self::execute_vssra,
+            InstructionType::VNSRL => Self::execute_vnsrl,
+            InstructionType::VNSRA => Self::execute_vnsra,
+            InstructionType::VNCLIPU => Self::execute_vnclipu,
+            InstructionType::VNCLIP => Self::execute_vnclip,
+            InstructionType::VWREDSUMU => Self::execute_vwredsumu,
+            InstructionType::VWREDSUM => Self::execute_vwredsum,
+            InstructionType::VREDSUM => Self::execute_vredsum,
+            InstructionType::VREDAND => Self::execute_vredand,
+            InstructionType::VREDOR => Self::execute_vredor,
+            InstructionType::VREDXOR => Self::execute_vredxor,
+            InstructionType::VREDMINU => Self::execute_vredminu,
+            InstructionType::VREDMIN => Self::execute_vredmin,
+            InstructionType::VREDMAXU => Self::execute_vredmaxu,
+            InstructionType::VREDMAX => Self::execute_vredmax,
+            InstructionType::VAADDU => Self::execute_vaaddu,
+            InstructionType::VAADD => Self::execute_vaadd,
+            InstructionType::VASUBU => Self::execute_vasubu,
+            InstructionType::VASUB => Self::execute_vasub,
+            InstructionType::VSLIDE1UP => Self::execute_vslide1up,
+            InstructionType::VSLIDE1DOWN => Self::execute_vslide1down,
+            InstructionType::VMV_X_S => Self::execute_vmv_x_s,
+            InstructionType::VCPOP_M => Self::execute_vcpop_m,
+            InstructionType::VFIRST_M => Self::execute_vfirst_m,
+            InstructionType::VMV_S_X => Self::execute_vmv_s_x,
+            InstructionType::VZEXT_VF8 => Self::execute_vzext_vf8,
+            InstructionType::VSEXT_VF8 => Self::execute_vsext_vf8,
+            InstructionType::VZEXT_VF4 => Self::execute_vzext_vf4,
+            InstructionType::VSEXT_VF4 => Self::execute_vsext_vf4,
+            InstructionType::VZEXT_VF2 => Self::execute_vzext_vf2,
+            InstructionType::VSEXT_VF2 => Self::execute_vsext_vf2,
+            InstructionType::VMSBF_M => Self::execute_vmsbf_m,
+            InstructionType::VMSOF_M => Self::execute_vmsof_m,
+            InstructionType::VMSIF_M => Self::execute_vmsif_m,
+            InstructionType::VIOTA_M => Self::execute_viota_m,
+            InstructionType::VID_V => Self::execute_vid_v,
+            InstructionType::VCOMPRESS => Self::execute_vcompress,
+            InstructionType::VMANDN => Self::execute_vmandn,
+            InstructionType::VMAND => Self::execute_vmand,
+            InstructionType::VMOR => Self::execute_vmor,
+            InstructionType::VMXOR => Self::execute_vmxor,
+            InstructionType::VMORN => Self::execute_vmorn,
+            InstructionType::VMNAND => Self::execute_vmnand,
+            InstructionType::VMNOR => Self::execute_vmnor,
+            InstructionType::VMXNOR => Self::execute_vmxnor,
+            InstructionType::VDIVU => Self::execute_vdivu,
+            InstructionType::VDIV => Self::execute_vdiv,
+            InstructionType::VREMU => Self::execute_vremu,
+            InstructionType::VREM => Self::execute_vrem,
+            InstructionType::VMULHU => Self::execute_vmulhu,
+            InstructionType::VMUL => Self::execute_vmul,
+            InstructionType::VMULHSU => Self::execute_vmulhsu,
+            InstructionType::VMULH => Self::execute_vmulh,
+            InstructionType::VMADD => Self::execute_vmadd,
+            InstructionType::VNMSUB => Self::execute_vnmsub,
+            InstructionType::VMACC => Self::execute_vmacc,
+            InstructionType::VNMSAC => Self::execute_vnmsac,
+            InstructionType::VWADDU => Self::execute_vwaddu,
+            InstructionType::VWADD => Self::execute_vwadd,
+            InstructionType::VWSUBU => Self::execute_vwsubu,
+            InstructionType::VWSUB => Self::execute_vwsub,
+            InstructionType::VWADDU_W => Self::execute_vwaddu_w,
+            InstructionType::VWADD_W => Self::execute_vwadd_w,
+            InstructionType::VWSUBU_W => Self::execute_vwsubu_w,
+            InstructionType::VWSUB_W => Self::execute_vwsub_w,
+            InstructionType::VWMULU => Self::execute_vwmulu,
+            InstructionType::VWMULSU => Self::execute_vwmulsu,
+            InstructionType::VWMUL => Self::execute_vwmul,
+            InstructionType::VWMACCU => Self::execute_vwmaccu,
+            InstructionType::VWMACC => Self::execute_vwmacc,
+            InstructionType::VWMACCUS => Self::execute_vwmaccus,
+            InstructionType::VWMACCSU => Self::execute_vwmaccsu,
+            InstructionType::VFADD => Self::execute_vfadd,
+            InstructionType::VFREDUSUM => Self::execute_vfredusum,
+            InstructionType::VFSUB => Self::execute_vfsub,
+            InstructionType::VFREDOSUM => Self::execute_vfredosum,
+            InstructionType::VFMIN => Self::execute_vfmin,
+            InstructionType::VFREDMIN => Self::execute_vfredmin,
+            InstructionType::VFMAX => Self::execute_vfmax,
+            InstructionType::VFREDMAX => Self::execute_vfredmax,
+            InstructionType::VFSGNJ => Self::execute_vfsgnj,
+            InstructionType::VFSGNJN => Self::execute_vfsgnjn,
+            InstructionType::VFSGNJX => Self::execute_vfsgnjx,
+            InstructionType::VFSQRT_V => Self::execute_vfsqrt_v,
+            InstructionType::VMFEQ => Self::execute_vmfeq,
+            InstructionType::VMFLE => Self::execute_vmfle,
+            InstructionType::VMFLT => Self::execute_vmflt,
+            InstructionType::VMFNE => Self::execute_vmfne,
+            InstructionType::VFDIV => Self::execute_vfdiv,
+            InstructionType::VFMUL => Self::execute_vfmul,
+            InstructionType::VFMADD => Self::execute_vfmadd,
+            InstructionType::VFNMADD => Self::execute_vfnmadd,
+            InstructionType::VFMSUB => Self::execute_vfmsub,
+            InstructionType::VFNMSUB => Self::execute_vfnmsub,
+            InstructionType::VFMACC => Self::execute_vfmacc,
+            InstructionType::VFNMACC => Self::execute_vfnmacc,
+            InstructionType::VFMSAC => Self::execute_vfmsac,
+            InstructionType::VFNMSAC => Self::execute_vfnmsac,
+        }
+    }
+
+    /// Takes `exception` into machine mode. Without a handler in `mtvec` there is nowhere to
+    /// go, so the hart stops with the fault instead.
+    fn trap(&mut self, exception: Exception, tval: u32) {
+        self.trapped = true;
+        self.counters.record(Event::Trap);
+
+        // A hart serving RVFI-DII always takes the trap, like the Sail model, so the harness
+        // sees the redirect to mtvec rather than a stopped hart.
+        if self.csrs.get(MTVEC) == 0 && self.rvfi.is_none() {
+            let (hart, pc) = (self.hart_id, self.pc);
+            let error = match exception {
+                Exception::IllegalInstruction => Error::IllegalInstruction { hart, pc, instruction: tval },
+                _ => Error::Fault { hart, pc, instruction: self.bus.borrow().get32(pc).ok(), exception, tval },
+            };
+            return self.fault(error);
+        }
+
+        self.enter_trap(exception as u32, tval);
+    }
+
+    /// Takes the highest-priority interrupt that is both pending and enabled, returning
+    /// whether one was taken. Software interrupts outrank timer interrupts.
+    fn take_interrupt(&mut self) -> bool {
+        let pending = self.read_csr(MIP) & self.csrs.get(MIE);
+        let enabled = self.privilege < Privilege::Machine || self.csrs.get(MSTATUS) & MSTATUS_MIE != 0;
+        if pending == 0 || !enabled {
+            return false;
+        }
+
+        let code = if pending & MIP_MSIP != 0 { 3 } else { 7 };
+        self.counters.record(Event::Trap);
+        self.enter_trap(MCAUSE_INTERRUPT | code, 0);
+        true
+    }
+
+    fn enter_trap(&mut self, cause: u32, tval: u32) {
+        let mut mstatus = self.csrs.get(MSTATUS) & !(MSTATUS_MPIE | 0b11 << MSTATUS_MPP_SHIFT);
+        if mstatus & MSTATUS_MIE != 0 {
+            mstatus |= MSTATUS_MPIE;
+        }
+        mstatus &= !MSTATUS_MIE;
+        mstatus |= (self.privilege as u32) << MSTATUS_MPP_SHIFT;
+
+        self.csrs.set(MSTATUS, mstatus);
+        self.csrs.set(MEPC, self.pc as u32);
+        self.csrs.set(MCAUSE, cause);
+        self.csrs.set(MTVAL, tval);
+        self.privilege = Privilege::Machine;
+        let epc = self.pc;
+        self.pc = (self.csrs.get(MTVEC) & !0b11) as usize;
+        if cause & MCAUSE_INTERRUPT != 0 && self.csrs.get(MTVEC) & 0b11 == 1 {
+            self.pc += 4 * (cause & !MCAUSE_INTERRUPT) as usize;
+        }
+        self.hook_trap_entry(TrapEntry { cause, tval, epc });
+    }
+
+    /// Whether the current hart may access `size` bytes at `address`. Loads and stores run at
+    /// the privilege in `mstatus.MPP` when `mstatus.MPRV` is set.
+    fn access_allowed(&self, address: usize, size: usize, access: Access) -> bool {
+        let mstatus = self.csrs.get(MSTATUS);
+        let privilege = if access != Access::Execute && mstatus & MSTATUS_MPRV != 0 {
+            Privilege::from_bits(mstatus >> MSTATUS_MPP_SHIFT)
+        } else {
+            self.privilege
+        };
+
+        self.bus.borrow().contains(address, size)
+            && self.pmp.check(address, size, access, privilege)
+    }
+
+    /// Raises the access fault matching `access` unless `access_allowed` permits it. Accesses
+    /// that are not naturally aligned are first handled according to the `misaligned` policy;
+    /// fetches from a PC that is not 4-byte aligned always trap.
+    fn check_access(&mut self, address: usize, size: usize, access: Access) -> bool {
+        let fault = match access {
+            Access::Read => Exception::LoadAccessFault,
+            Access::Write => Exception::StoreAccessFault,
+            Access::Execute => Exception::InstructionAccessFault,
+        };
+
+        if !address.is_multiple_of(size) {
+            let exception = match (access, self.misaligned) {
+                (Access::Execute, _) => Some(Exception::InstructionAddressMisaligned),
+                (_, Misaligned::Allow) => None,
+                (Access::Read, Misaligned::Trap) => Some(Exception::LoadAddressMisaligned),
+                (Access::Write, Misaligned::Trap) => Some(Exception::StoreAddressMisaligned),
+                (_, Misaligned::Fault) => Some(fault),
+            };
+            if let Some(exception) = exception {
+                self.trap(exception, address as u32);
+                return false;
+            }
+        }
+
+        if self.access_allowed(address, size, access) {
+            return true;
+        }
+        self.trap(fault, address as u32);
+        false
+    }
+
+    /// Reads `size` bytes at `address`, sign-extending them when `signed`. The access must
+    /// already have been checked, so a bus error stops the hart rather than trapping.
+    fn load(&mut self, address: usize, size: usize, signed: bool) -> u32 {
+        let bus = self.bus.borrow();
+        let data = match (size, signed) {
+            (1, false) => bus.get8(address).map(u32::from),
+            (1, true) => bus.get8_sx(address),
+            (2, false) => bus.get16(address).map(u32::from),
+            (2, true) => bus.get16_sx(address),
+            _ => bus.get32(address),
+        };
+        drop(bus);
+        let mut access = match data {
+            Ok(data) => MemoryAccess { address, size, data, write: false },
+            Err(error) => {
+                self.fault(error);
+                return 0;
+            }
+        };
+        self.hook_memory(&mut access);
+        let data = access.data;
+
+        if self.observed() {
+            self.memory_accesses.push(MemoryAccess { address, size, data, write: false });
+        }
+        data
+    }
+
+    /// Writes the low `size` bytes of `data` to `address`. The access must already have been
+    /// checked, so a bus error stops the hart rather than trapping.
+    fn store(&mut self, address: usize, size: usize, data: u32) {
+        let mut access = MemoryAccess { address, size, data, write: true };
+        self.hook_memory(&mut access);
+        let data = access.data;
+
+        let mut bus = self.bus.borrow_mut();
+        let result = match size {
+            1 => bus.set8(data as u8, address),
+            2 => bus.set16(data as u16, address),
+            _ => bus.set32(data, address),
+        };
+        drop(bus);
+        if let Err(error) = result {
+            return self.fault(error);
+        }
+
+        if self.observed() {
+            let data = if size == 4 { data } else { data & ((1 << (size * 8)) - 1) };
+            self.memory_accesses.push(MemoryAccess { address, size, data, write: true });
+        }
+    }
+
+    /// Atomics are never split into smaller accesses, so a misaligned one raises an access
+    /// fault whatever the `misaligned` policy.
+    fn check_atomic_alignment(&mut self, address: usize, fault: Exception) -> bool {
+        if address.is_multiple_of(4) {
+            return true;
+        }
+        self.trap(fault, address as u32);
+        false
+    }
+
+    /// Moves to `target`, returning whether the jump happened. A target that is not 4-byte
+    /// aligned raises an instruction-address-misaligned exception on the jump itself.
+    fn jump(&mut self, target: u32) -> bool {
+        if !target.is_multiple_of(4) {
+            self.trap(Exception::InstructionAddressMisaligned, target);
+            return false;
+        }
+        self.pc = target as usize;
+        true
+    }
+
+    /// Takes a conditional branch to `pc + imm` when `taken`.
+    fn branch(&mut self, taken: bool, imm: u32) {
+        if taken {
+            self.jump((self.pc as u32).wrapping_add(imm));
+            return;
+        }
+        self.pc += 4;
+    }
+
+    fn illegal_instruction(&mut self, decoded: &Decoded) {
+        self.trap(Exception::IllegalInstruction, decoded.instruction.get_raw());
+    }
+
+    /// CSR addresses encode the lowest privilege allowed to access them in bits 9:8,
+    /// and mark read-only registers with 0b11 in bits 11:10.
+    /// User-level counters are further hidden from lower privileges by `mcounteren` and `scounteren`.
+    fn csr_accessible(&self, csr: u16, write: bool) -> bool {
+        let privilege = (csr >> 8 & 0b11) as u32;
+        let read_only = csr >> 10 == 0b11;
+
+        if let CYCLE..=HPMCOUNTER31 | CYCLEH..=HPMCOUNTER31H = csr {
+            let enable = 1 << (csr & 0x1F);
+            if self.privilege < Privilege::Machine && self.csrs.get(MCOUNTEREN) & enable == 0 {
+                return false;
+            }
+            if self.privilege < Privilege::Supervisor && self.csrs.get(SCOUNTEREN) & enable == 0 {
+                return false;
+            }
+        }
+
+        privilege <= self.privilege as u32 && !(write && read_only)
+    }
+
+    fn read_csr(&self, csr: u16) -> u32 {
+        match csr {
+            VCSR => self.csrs.get(VXRM) << 1 | self.csrs.get(VXSAT),
+            VLENB => self.vector_registers.vlenb() as u32,
+            MHARTID => self.hart_id as u32,
+            MISA => self.isa.misa(),
+            MIP => {
+                let clint = &self.bus.borrow().clint;
+                let software = if clint.software_pending(self.hart_id) { MIP_MSIP } else { 0 };
+                let timer = if clint.timer_pending(self.hart_id) { MIP_MTIP } else { 0 };
+                self.csrs.get(MIP) | software | timer
+            }
+            TIME => self.bus.borrow().clint.get_mtime() as u32,
+            TIMEH => (self.bus.borrow().clint.get_mtime() >> 32) as u32,
+            CYCLE..=HPMCOUNTER31 | MCYCLE..=MHPMCOUNTER31 => self.counters.get(csr as usize & 0x1F) as u32,
+            CYCLEH..=HPMCOUNTER31H | MCYCLEH..=MHPMCOUNTER31H => (self.counters.get(csr as usize & 0x1F) >> 32) as u32,
+            MHPMEVENT3..=MHPMEVENT31 => self.counters.get_event(csr as usize & 0x1F),
+            MCOUNTINHIBIT => self.counters.get_inhibit(),
+            PMPCFG0..=PMPCFG15 => self.pmp.get_config((csr - PMPCFG0) as usize),
+            PMPADDR0..=PMPADDR63 => self.pmp.get_address((csr - PMPADDR0) as usize),
+            _ => self.csrs.get(csr),
+        }
+    }
+
+    /// Writes a CSR from an instruction, recording the resulting value for the trace.
+    fn write_csr(&mut self, csr: u16, data: u32) {
+        self.set_csr(csr, data);
+        if self.observed() {
+            self.csr_writes.push((csr, self.read_csr(csr)));
+        }
+    }
+
+    fn set_csr(&mut self, csr: u16, data: u32) {
+        match csr {
+            VCSR => {
+                self.csrs.set(VXRM, data >> 1 & 0b11);
+                self.csrs.set(VXSAT, data & 1);
+            }
+            VXRM => self.csrs.set(VXRM, data & 0b11),
+            VXSAT => self.csrs.set(VXSAT, data & 1),
+            VSTART => self.csrs.set(VSTART, data & (self.vector_registers.vlenb() as u32 * 8 - 1)),
+            MENVCFG | SENVCFG => self.csrs.set(csr, data & (ENVCFG_CBZE | ENVCFG_CBCFE | 0b11 << ENVCFG_CBIE_SHIFT | 1)),
+            MIP => self.csrs.set(MIP, data & !(MIP_MSIP | MIP_MTIP)),
+            MEPC => self.csrs.set(MEPC, data & !0b11),
+            MCYCLE..=MHPMCOUNTER31 => self.counters.set_low(csr as usize & 0x1F, data),
+            MCYCLEH..=MHPMCOUNTER31H => self.counters.set_high(csr as usize & 0x1F, data),
+            MHPMEVENT3..=MHPMEVENT31 => self.counters.set_event(csr as usize & 0x1F, data),
+            MCOUNTINHIBIT => self.counters.set_inhibit(data),
+            PMPCFG0..=PMPCFG15 => self.pmp.set_config((csr - PMPCFG0) as usize, data),
+            PMPADDR0..=PMPADDR63 => self.pmp.set_address((csr - PMPADDR0) as usize, data),
+            MISA => (),
+            _ => self.csrs.set(csr, data),
+        }
+    }
+
+    pub fn execute_csrrw(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let csr = decoded.instruction.get_csr();
+
+        if !self.csr_accessible(csr, true) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let mut read = None;
+        if rd != 0 {
+            let csr_value = self.read_csr(csr);
+            self.registers.set(rd as usize, csr_value);
+            read = Some(csr_value);
+        }
+        self.write_csr(csr, rs1_value);
+        self.hook_csr(CsrAccess { csr, read, written: Some(rs1_value) });
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_csrrs(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let csr = decoded.instruction.get_csr();
+
+        if !self.csr_accessible(csr, rs1 != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let csr_value = self.read_csr(csr);
+        self.registers.set(rd as usize, csr_value);
+        if rs1 != 0 {
+            self.write_csr(csr, csr_value | rs1_value);
+        }
+        self.hook_csr(CsrAccess { csr, read: Some(csr_value), written: (rs1 != 0).then_some(csr_value | rs1_value) });
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_csrrc(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let csr = decoded.instruction.get_csr();
+
+        if !self.csr_accessible(csr, rs1 != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let csr_value = self.read_csr(csr);
+        self.registers.set(rd as usize, csr_value);
+        if rs1 != 0 {
+            self.write_csr(csr, csr_value & !rs1_value);
+        }
+        self.hook_csr(CsrAccess { csr, read: Some(csr_value), written: (rs1 != 0).then_some(csr_value & !rs1_value) });
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_csrrwi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let uimm = decoded.rs1 as u32;
+        let csr = decoded.instruction.get_csr();
+
+        if !self.csr_accessible(csr, true) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let mut read = None;
+        if rd != 0 {
+            let csr_value = self.read_csr(csr);
+            self.registers.set(rd as usize, csr_value);
+            read = Some(csr_value);
+        }
+        self.write_csr(csr, uimm);
+        self.hook_csr(CsrAccess { csr, read, written: Some(uimm) });
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_csrrsi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let uimm = decoded.rs1 as u32;
+        let csr = decoded.instruction.get_csr();
+
+        if !self.csr_accessible(csr, uimm != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let csr_value = self.read_csr(csr);
+        self.registers.set(rd as usize, csr_value);
+        if uimm != 0 {
+            self.write_csr(csr, csr_value | uimm);
+        }
+        self.hook_csr(CsrAccess { csr, read: Some(csr_value), written: (uimm != 0).then_some(csr_value | uimm) });
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_csrrci(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let uimm = decoded.rs1 as u32;
+        let csr = decoded.instruction.get_csr();
+
+        if !self.csr_accessible(csr, uimm != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let csr_value = self.read_csr(csr);
+        self.registers.set(rd as usize, csr_value);
+        if uimm != 0 {
+            self.write_csr(csr, csr_value & !uimm);
+        }
+        self.hook_csr(CsrAccess { csr, read: Some(csr_value), written: (uimm != 0).then_some(csr_value & !uimm) });
 
         self.pc += 4;
     }
 
 
-    pub fn execute_auipc(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let imm = instruction.get_imm_u() << 12;
+    /// WFI, PAUSE and FENCE have nothing to wait for or order in this emulator.
+    pub fn execute_nop(&mut self, _decoded: &Decoded) {
+        self.pc += 4;
+    }
+
+
+    pub fn execute_ebreak(&mut self, decoded: &Decoded) {
+        match self.hook_instruction(InstructionEvent::Ebreak, &decoded.instruction) {
+            Action::Handled => self.pc += 4,
+            Action::Stop => (),
+            Action::Continue => self.halted = true,
+        }
+    }
+
+
+    pub fn execute_ecall(&mut self, decoded: &Decoded) {
+        match self.hook_instruction(InstructionEvent::Ecall, &decoded.instruction) {
+            Action::Handled => self.pc += 4,
+            Action::Stop => (),
+            Action::Continue => self.trap(Exception::ecall_from(self.privilege), 0),
+        }
+    }
+
+
+    pub fn execute_mret(&mut self, decoded: &Decoded) {
+        if self.privilege != Privilege::Machine {
+            return self.illegal_instruction(decoded);
+        }
+
+        let mstatus = self.csrs.get(MSTATUS);
+        let mut next = mstatus & !(MSTATUS_MIE | 0b11 << MSTATUS_MPP_SHIFT) | MSTATUS_MPIE;
+        if mstatus & MSTATUS_MPIE != 0 {
+            next |= MSTATUS_MIE;
+        }
+
+        self.csrs.set(MSTATUS, next);
+        self.privilege = Privilege::from_bits(mstatus >> MSTATUS_MPP_SHIFT);
+        self.pc = self.csrs.get(MEPC) as usize;
+        self.hook_instruction(InstructionEvent::TrapExit, &decoded.instruction);
+    }
+
 
-        self.registers.set(rd as usize, imm + self.pc as u32);
+    pub fn execute_lui(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let imm = decoded.imm;
+
+        self.registers.set(rd as usize, imm);
 
         self.pc += 4;
     }
 
 
-    pub fn execute_jal(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let imm = instruction.get_imm_j();
+    pub fn execute_auipc(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let imm = decoded.imm;
 
-        self.registers.set(rd as usize, (self.pc + 4) as u32);
+        self.registers.set(rd as usize, imm.wrapping_add(self.pc as u32));
 
-        self.pc += imm as usize;
+        self.pc += 4;
     }
 
 
-    pub fn execute_jalr(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_jal(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let imm = decoded.imm;
+
+        let link = (self.pc + 4) as u32;
+        if self.jump((self.pc as u32).wrapping_add(imm)) {
+            self.registers.set(rd as usize, link);
+        }
+    }
+
 
-        let rs_value = self.registers.get(rs as usize);
-        self.registers.set(rd as usize, (self.pc + 4) as u32);
+    pub fn execute_jalr(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs = decoded.rs1;
+        let imm = decoded.imm;
 
-        self.pc = ((imm + rs_value) & 0xFFFFFE) as usize;
+        let link = (self.pc + 4) as u32;
+        let target = self.registers.get(rs as usize).wrapping_add(imm) & !1;
+        if self.jump(target) {
+            self.registers.set(rd as usize, link);
+        }
     }
 
 
-    pub fn execute_bne(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_b();
+    pub fn execute_bne(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        let pc_increment = if rs1_value != rs2_value { imm } else { 4 };
-        self.pc += pc_increment as usize;
+        self.branch(rs1_value != rs2_value, imm);
     }
 
 
-    pub fn execute_blt(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_b();
+    pub fn execute_blt(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        let pc_increment = if (rs1_value as i32) < rs2_value as i32 { imm } else { 4 };
-        self.pc += pc_increment as usize;
+        self.branch((rs1_value as i32) < rs2_value as i32, imm);
     }
 
 
-    pub fn execute_bge(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_b();
+    pub fn execute_bge(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        let pc_increment = if rs1_value as i32 >= rs2_value as i32 { imm } else { 4 };
-        self.pc += pc_increment as usize;
+        self.branch(rs1_value as i32 >= rs2_value as i32, imm);
     }
 
 
-    pub fn execute_bltu(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_b();
+    pub fn execute_bltu(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        let pc_increment = if rs1_value < rs2_value { imm } else { 4 };
-        self.pc += pc_increment as usize;
+        self.branch(rs1_value < rs2_value, imm);
     }
 
 
-    pub fn execute_bgeu(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_b();
+    pub fn execute_bgeu(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        let pc_increment = if rs1_value >= rs2_value { imm } else { 4 };
-        self.pc += pc_increment as usize;
+        self.branch(rs1_value >= rs2_value, imm);
     }
 
 
-    pub fn execute_beq(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_b();
+    pub fn execute_beq(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        let pc_increment = if rs1_value == rs2_value { imm } else { 4 };
-        self.pc += pc_increment as usize;
+        self.branch(rs1_value == rs2_value, imm);
     }
 
-    pub fn execute_addi(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_addi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
-        self.registers.set(rd as usize, rs1_value + imm);
+        self.registers.set(rd as usize, rs1_value.wrapping_add(imm));
 
         self.pc += 4;
     }
 
 
-    pub fn execute_lbu(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_lbu(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
-        let data = self.memory.get8((rs1_value + imm) as usize);
-        self.registers.set(rd as usize, data as u32);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 1, Access::Read) {
+            return;
+        }
+        let data = self.load(address, 1, false);
+        self.registers.set(rd as usize, data);
         self.pc += 4;
     }
 
-    pub fn execute_lb(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_lb(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
-        let data = self.memory.get8_sx((rs1_value + imm) as usize);
-        self.registers.set(rd as usize, data as u32);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 1, Access::Read) {
+            return;
+        }
+        let data = self.load(address, 1, true);
+        self.registers.set(rd as usize, data);
         self.pc += 4;
     }
 
-    pub fn execute_lhu(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_lhu(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
-        let data = self.memory.get16((rs1_value + imm) as usize);
-        self.registers.set(rd as usize, data as u32);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 2, Access::Read) {
+            return;
+        }
+        let data = self.load(address, 2, false);
+        self.registers.set(rd as usize, data);
         self.pc += 4;
     }
 
 
-    pub fn execute_lh(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_lh(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
-        let data = self.memory.get16_sx((rs1_value + imm) as usize);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 2, Access::Read) {
+            return;
+        }
+        let data = self.load(address, 2, true);
         self.registers.set(rd as usize, data);
         self.pc += 4;
     }
 
 
-    pub fn execute_lw(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_lw(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
-        let data = self.memory.get32((rs1_value + imm) as usize);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 4, Access::Read) {
+            return;
+        }
+        let data = self.load(address, 4, false);
         self.registers.set(rd as usize, data);
         self.pc += 4;
     }
 
 
-    pub fn execute_sb(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_s();
+    pub fn execute_sb(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        self.memory.set8(rs2_value as u8, (rs1_value + imm) as usize);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 1, Access::Write) {
+            return;
+        }
+        self.store(address, 1, rs2_value);
 
         self.pc += 4;
     }
 
 
-    pub fn execute_sh(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_s();
+    pub fn execute_sh(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        self.memory.set16(rs2_value as u16, (rs1_value + imm) as usize);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 2, Access::Write) {
+            return;
+        }
+        self.store(address, 2, rs2_value);
 
         self.pc += 4;
     }
 
 
-    pub fn execute_sw(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_s();
+    pub fn execute_sw(&mut self, decoded: &Decoded) {
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
 
-        self.memory.set32(rs2_value, (rs1_value + imm) as usize);
+        let address = rs1_value.wrapping_add(imm) as usize;
+        if !self.check_access(address, 4, Access::Write) {
+            return;
+        }
+        self.store(address, 4, rs2_value);
 
         self.pc += 4;
     }
 
 
-    pub fn execute_slti(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_i();
+    pub fn execute_slti(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
-        self.registers.set(rs2 as usize, if (rs1_value as i32) < imm as i32 { 1 } else { 0 });
+        self.registers.set(rd as usize, if (rs1_value as i32) < imm as i32 { 1 } else { 0 });
 
         self.pc += 4;
     }
 
 
-    pub fn execute_sltiu(&mut self, instruction: &Instruction) {
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
-        let imm = instruction.get_imm_i();
+    pub fn execute_sltiu(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
-        self.registers.set(rs2 as usize, if rs1_value < imm { 1 } else { 0 });
+        self.registers.set(rd as usize, if rs1_value < imm { 1 } else { 0 });
 
         self.pc += 4;
     }
 
 
-    pub fn execute_xori(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_xori(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
@@ -358,10 +1320,10 @@ impl CPU {
     }
 
 
-    pub fn execute_ori(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_ori(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
@@ -371,10 +1333,10 @@ impl CPU {
     }
 
 
-    pub fn execute_andi(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_imm_i();
+    pub fn execute_andi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
@@ -384,10 +1346,10 @@ impl CPU {
     }
 
 
-    pub fn execute_slli(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_shamt();
+    pub fn execute_slli(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
@@ -397,10 +1359,10 @@ impl CPU {
     }
 
 
-    pub fn execute_srli(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_shamt();
+    pub fn execute_srli(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
@@ -410,10 +1372,10 @@ impl CPU {
     }
 
 
-    pub fn execute_srai(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let imm = instruction.get_shamt();
+    pub fn execute_srai(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
 
         let rs1_value = self.registers.get(rs1 as usize);
 
@@ -423,10 +1385,10 @@ impl CPU {
     }
 
 
-    pub fn execute_add(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_add(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -436,10 +1398,10 @@ impl CPU {
         self.pc += 4;
     }
 
-    pub fn execute_sub(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_sub(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -449,10 +1411,10 @@ impl CPU {
         self.pc += 4;
     }
 
-    pub fn execute_sll(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_sll(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -463,10 +1425,10 @@ impl CPU {
     }
 
 
-    pub fn execute_slt(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_slt(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -477,10 +1439,10 @@ impl CPU {
     }
 
 
-    pub fn execute_sltu(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_sltu(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -491,10 +1453,10 @@ impl CPU {
     }
 
 
-    pub fn execute_xor(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_xor(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -505,10 +1467,10 @@ impl CPU {
     }
 
 
-    pub fn execute_srl(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_srl(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -519,10 +1481,10 @@ impl CPU {
     }
 
 
-    pub fn execute_sra(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_sra(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -533,10 +1495,10 @@ impl CPU {
     }
 
 
-    pub fn execute_or(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_or(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -547,10 +1509,10 @@ impl CPU {
     }
 
 
-    pub fn execute_and(&mut self, instruction: &Instruction) {
-        let rd = instruction.get_rd();
-        let rs1 = instruction.get_rs1();
-        let rs2 = instruction.get_rs2();
+    pub fn execute_and(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
 
         let rs1_value = self.registers.get(rs1 as usize);
         let rs2_value = self.registers.get(rs2 as usize);
@@ -559,4 +1521,1550 @@ impl CPU {
 
         self.pc += 4;
     }
-}
\ No newline at end of file
+
+    pub fn execute_sh1add(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, (rs1_value << 1).wrapping_add(rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sh2add(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, (rs1_value << 2).wrapping_add(rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sh3add(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, (rs1_value << 3).wrapping_add(rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_andn(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value & !rs2_value);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_orn(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value | !rs2_value);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_xnor(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, !(rs1_value ^ rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_min(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, (rs1_value as i32).min(rs2_value as i32) as u32);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_max(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, (rs1_value as i32).max(rs2_value as i32) as u32);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_minu(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value.min(rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_maxu(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value.max(rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_rol(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value.rotate_left(rs2_value & 0b11111));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_ror(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value.rotate_right(rs2_value & 0b11111));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_clmul(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, bitmanip::clmul(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_clmulh(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, bitmanip::clmulh(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_clmulr(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, bitmanip::clmulr(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_bset(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value | 1 << (rs2_value & 0b11111));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_bclr(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value & !(1 << (rs2_value & 0b11111)));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_binv(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value ^ 1 << (rs2_value & 0b11111));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_bext(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, rs1_value >> (rs2_value & 0b11111) & 1);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_clz(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value.leading_zeros());
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_ctz(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value.trailing_zeros());
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_cpop(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value.count_ones());
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sext_b(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value as u8 as i8 as u32);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sext_h(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value as u16 as i16 as u32);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_zext_h(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value & 0xFFFF);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_orc_b(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, bitmanip::orc_b(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_rev8(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value.swap_bytes());
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_rori(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value.rotate_right(imm));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_bseti(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value | 1 << imm);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_bclri(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value & !(1 << imm));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_binvi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value ^ 1 << imm);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_bexti(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let imm = decoded.imm;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, rs1_value >> imm & 1);
+
+        self.pc += 4;
+    }
+
+    pub fn execute_pack(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, (rs2_value & 0xFFFF) << 16 | rs1_value & 0xFFFF);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_packh(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, (rs2_value & 0xFF) << 8 | rs1_value & 0xFF);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_xperm4(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, bitmanip::xperm4(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_xperm8(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, bitmanip::xperm8(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha512sig0h(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sha512sig0h(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha512sig0l(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sha512sig0l(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha512sig1h(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sha512sig1h(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha512sig1l(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sha512sig1l(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha512sum0r(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sha512sum0r(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha512sum1r(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sha512sum1r(rs1_value, rs2_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_brev8(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, bitmanip::brev8(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_zip(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, bitmanip::zip(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_unzip(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, bitmanip::unzip(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha256sig0(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, crypto::sha256sig0(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha256sig1(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, crypto::sha256sig1(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha256sum0(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, crypto::sha256sum0(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sha256sum1(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, crypto::sha256sum1(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sm3p0(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, crypto::sm3p0(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sm3p1(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+
+        self.registers.set(rd as usize, crypto::sm3p1(rs1_value));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_aes32esi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let bs = decoded.instruction.get_bs();
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::aes32esi(rs1_value, rs2_value, bs));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_aes32esmi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let bs = decoded.instruction.get_bs();
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::aes32esmi(rs1_value, rs2_value, bs));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_aes32dsi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let bs = decoded.instruction.get_bs();
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::aes32dsi(rs1_value, rs2_value, bs));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_aes32dsmi(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let bs = decoded.instruction.get_bs();
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::aes32dsmi(rs1_value, rs2_value, bs));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sm4ed(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let bs = decoded.instruction.get_bs();
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sm4ed(rs1_value, rs2_value, bs));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sm4ks(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+        let bs = decoded.instruction.get_bs();
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, crypto::sm4ks(rs1_value, rs2_value, bs));
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_czero_eqz(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, if rs2_value == 0 { 0 } else { rs1_value });
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_czero_nez(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let rs1_value = self.registers.get(rs1 as usize);
+        let rs2_value = self.registers.get(rs2 as usize);
+
+        self.registers.set(rd as usize, if rs2_value != 0 { 0 } else { rs1_value });
+
+        self.pc += 4;
+    }
+
+    /// Cache-block operations below machine mode are gated by `menvcfg`, and in user mode
+    /// additionally by `senvcfg`.
+    fn cache_block_enabled(&self, enabled: impl Fn(u32) -> bool) -> bool {
+        (self.privilege == Privilege::Machine || enabled(self.csrs.get(MENVCFG)))
+            && (self.privilege != Privilege::User || enabled(self.csrs.get(SENVCFG)))
+    }
+
+    /// Returns the start of the cache block holding the address in `rs1`, or raises a store
+    /// access fault if the block may not be accessed. Zeroing needs write permission, the
+    /// management operations either read or write. The emulator has no caches, so clean,
+    /// flush and invalidate stop here.
+    fn cache_block(&mut self, decoded: &Decoded, zero: bool) -> Option<usize> {
+        let rs1 = decoded.rs1;
+        let rs1_value = self.registers.get(rs1 as usize);
+        let block = rs1_value as usize & !(self.cache_block_size - 1);
+
+        let writable = self.access_allowed(block, self.cache_block_size, Access::Write);
+        let readable = self.access_allowed(block, self.cache_block_size, Access::Read);
+        if !writable && (zero || !readable) {
+            self.trap(Exception::StoreAccessFault, rs1_value);
+            return None;
+        }
+
+        Some(block)
+    }
+
+    pub fn execute_cbo_clean(&mut self, decoded: &Decoded) {
+        if !self.cache_block_enabled(|envcfg| envcfg & ENVCFG_CBCFE != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        if self.cache_block(decoded, false).is_some() {
+            self.pc += 4;
+        }
+    }
+
+
+    pub fn execute_cbo_flush(&mut self, decoded: &Decoded) {
+        if !self.cache_block_enabled(|envcfg| envcfg & ENVCFG_CBCFE != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        if self.cache_block(decoded, false).is_some() {
+            self.pc += 4;
+        }
+    }
+
+
+    pub fn execute_cbo_inval(&mut self, decoded: &Decoded) {
+        if !self.cache_block_enabled(|envcfg| envcfg >> ENVCFG_CBIE_SHIFT & 0b11 != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        if self.cache_block(decoded, false).is_some() {
+            self.pc += 4;
+        }
+    }
+
+
+    pub fn execute_cbo_zero(&mut self, decoded: &Decoded) {
+        if !self.cache_block_enabled(|envcfg| envcfg & ENVCFG_CBZE != 0) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let Some(block) = self.cache_block(decoded, true) else { return };
+        let size = self.cache_block_size;
+        let mut access = MemoryAccess { address: block, size, data: 0, write: true };
+        self.hook_memory(&mut access);
+
+        let result = self.bus.borrow_mut().zero(block, size);
+        if let Err(error) = result {
+            return self.fault(error);
+        }
+        if self.observed() {
+            self.memory_accesses.push(MemoryAccess { address: block, size, data: 0, write: true });
+        }
+        self.pc += 4;
+    }
+
+
+    /// Makes earlier stores visible to this hart's instruction fetches. Plain FENCE needs no
+    /// work since every hart sees memory in program order.
+    pub fn execute_fence_i(&mut self, _decoded: &Decoded) {
+        self.bus.borrow_mut().fence_i(self.hart_id);
+        self.pc += 4;
+    }
+
+
+    pub fn execute_lr_w(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        let address = self.registers.get(rs1 as usize) as usize;
+        if !self.check_atomic_alignment(address, Exception::LoadAccessFault) {
+            return;
+        }
+        if !self.check_access(address, 4, Access::Read) {
+            return;
+        }
+        let data = self.load(address, 4, false);
+        self.bus.borrow_mut().reserve(self.hart_id, address);
+        self.registers.set(rd as usize, data);
+
+        self.pc += 4;
+    }
+
+
+    pub fn execute_sc_w(&mut self, decoded: &Decoded) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let address = self.registers.get(rs1 as usize) as usize;
+        let rs2_value = self.registers.get(rs2 as usize);
+        if !self.check_atomic_alignment(address, Exception::StoreAccessFault) {
+            return;
+        }
+        if !self.check_access(address, 4, Access::Write) {
+            return;
+        }
+
+        let reserved = self.bus.borrow_mut().take_reservation(self.hart_id, address);
+        if reserved {
+            self.store(address, 4, rs2_value);
+        }
+        self.registers.set(rd as usize, !reserved as u32);
+
+        self.pc += 4;
+    }
+
+    /// Atomically replaces the word at `rs1` with `op(old, rs2)` and returns the old value in `rd`.
+    /// Faults are reported as store/AMO access faults since AMOs both read and write.
+    fn execute_amo(&mut self, decoded: &Decoded, op: impl Fn(u32, u32) -> u32) {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+        let rs2 = decoded.rs2;
+
+        let address = self.registers.get(rs1 as usize) as usize;
+        let rs2_value = self.registers.get(rs2 as usize);
+        if !self.check_atomic_alignment(address, Exception::StoreAccessFault) {
+            return;
+        }
+        if !self.access_allowed(address, 4, Access::Read) || !self.access_allowed(address, 4, Access::Write) {
+            return self.trap(Exception::StoreAccessFault, address as u32);
+        }
+
+        let data = self.load(address, 4, false);
+        self.store(address, 4, op(data, rs2_value));
+        self.registers.set(rd as usize, data);
+
+        self.pc += 4;
+    }
+
+    pub fn execute_amoswap_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |_, b| b);
+    }
+
+    pub fn execute_amoadd_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| a.wrapping_add(b));
+    }
+
+    pub fn execute_amoxor_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| a ^ b);
+    }
+
+    pub fn execute_amoand_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| a & b);
+    }
+
+    pub fn execute_amoor_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| a | b);
+    }
+
+    pub fn execute_amomin_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| (a as i32).min(b as i32) as u32);
+    }
+
+    pub fn execute_amomax_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| (a as i32).max(b as i32) as u32);
+    }
+
+    pub fn execute_amominu_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| a.min(b));
+    }
+
+    pub fn execute_amomaxu_w(&mut self, decoded: &Decoded) {
+        self.execute_amo(decoded, |a, b| a.max(b));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::bus::Bus;
+    use crate::clint::CLINT_BASE;
+    use crate::cpu::CPU;
+    use crate::cpu::hooks::{Action, Hooks};
+    use crate::decode_cache::Decoded;
+    use crate::csr::{
+        ENVCFG_CBCFE, MCAUSE, MCOUNTEREN, MENVCFG, MEPC, MIE, MIP_MSIP, MSTATUS, MSTATUS_MIE, MTVAL,
+        MTVEC, SCOUNTEREN, SENVCFG, VL, VTYPE, VXSAT,
+    };
+    use crate::trap::{Misaligned, Privilege};
+    use crate::instruction::Instruction;
+    use crate::memory::Memory;
+
+    fn single_hart(size: usize) -> CPU {
+        CPU::new(0, Rc::new(RefCell::new(Bus::new(Memory::new(size), 1))))
+    }
+
+    fn execute(instruction: u32, rs1_value: u32, rs2_value: u32) -> u32 {
+        let mut cpu = single_hart(16);
+        cpu.registers.set(11, rs1_value);
+        cpu.registers.set(12, rs2_value);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        cpu.registers.get(10)
+    }
+
+    #[test]
+    fn test_set_less_than_immediate() {
+        assert_eq!(execute(0xfff5a513, 0xFFFFFFFE, 0), 1);
+        assert_eq!(execute(0xfff5a513, 0, 0), 0);
+        assert_eq!(execute(0x0055a513, 4, 0), 1);
+        assert_eq!(execute(0xfff5b513, 0xFFFFFFFE, 0), 1);
+        assert_eq!(execute(0xfff5b513, 0xFFFFFFFF, 0), 0);
+    }
+
+    #[test]
+    fn test_wrapping_arithmetic() {
+        assert_eq!(execute(0x00158513, 0xFFFFFFFF, 0), 0);
+
+        let mut cpu = single_hart(64);
+        cpu.pc = 0x10;
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0xfffff517)));
+        assert_eq!(cpu.registers.get(10), 0xFFFFF010);
+    }
+
+    #[test]
+    fn test_zba() {
+        assert_eq!(execute(0x20c5a533, 0x10, 0x3), 0x23);
+        assert_eq!(execute(0x20c5c533, 0x10, 0x3), 0x43);
+        assert_eq!(execute(0x20c5e533, 0x10, 0x3), 0x83);
+    }
+
+    #[test]
+    fn test_zbb() {
+        assert_eq!(execute(0x40c5f533, 0xFF00FF00, 0x0FF00FF0), 0xF000F000);
+        assert_eq!(execute(0x40c5c533, 0xFF00FF00, 0x0FF00FF0), 0x0F0F0F0F);
+        assert_eq!(execute(0x60059513, 0x00010000, 0), 15);
+        assert_eq!(execute(0x60159513, 0x00010000, 0), 16);
+        assert_eq!(execute(0x60259513, 0xF00F0001, 0), 9);
+        assert_eq!(execute(0x0ac5c533, 0xFFFFFFFF, 0x1), 0xFFFFFFFF);
+        assert_eq!(execute(0x0ac5d533, 0xFFFFFFFF, 0x1), 0x1);
+        assert_eq!(execute(0x60459513, 0x00000080, 0), 0xFFFFFF80);
+        assert_eq!(execute(0x60559513, 0x00017FFF, 0), 0x00007FFF);
+        assert_eq!(execute(0x0805c533, 0xFFFF8000, 0), 0x00008000);
+        assert_eq!(execute(0x60c59533, 0x80000001, 4), 0x00000018);
+        assert_eq!(execute(0x6045d513, 0x00000018, 0), 0x80000001);
+        assert_eq!(execute(0x6985d513, 0x11223344, 0), 0x44332211);
+    }
+
+    #[test]
+    fn test_zbs() {
+        assert_eq!(execute(0x28c59533, 0x0, 31), 0x80000000);
+        assert_eq!(execute(0x48c59533, 0xFFFFFFFF, 4), 0xFFFFFFEF);
+        assert_eq!(execute(0x68c59533, 0x10, 36), 0x0);
+        assert_eq!(execute(0x48c5d533, 0x10, 4), 0x1);
+        assert_eq!(execute(0x48c5d513, 0x1000, 0), 0x1);
+    }
+
+    #[test]
+    fn test_zbkb() {
+        assert_eq!(execute(0x08c5c533, 0xAAAA1111, 0xBBBB2222), 0x22221111);
+        assert_eq!(execute(0x08c5f533, 0xAAAA1111, 0xBBBB2222), 0x00002211);
+        assert_eq!(execute(0x6875d513, 0x00000001, 0), 0x00000080);
+        assert_eq!(execute(0x08f59513, 0x0000FFFF, 0), 0x55555555);
+        assert_eq!(execute(0x08f5d513, 0x55555555, 0), 0x0000FFFF);
+    }
+
+    #[test]
+    fn test_zicond() {
+        assert_eq!(execute(0x0ec5d533, 0x1234, 0), 0);
+        assert_eq!(execute(0x0ec5d533, 0x1234, 1), 0x1234);
+        assert_eq!(execute(0x0ec5f533, 0x1234, 0), 0x1234);
+        assert_eq!(execute(0x0ec5f533, 0x1234, 1), 0);
+    }
+
+    #[test]
+    fn test_zicbo() {
+        let mut cpu = single_hart(1024);
+        for address in 0x100..0x200 {
+            cpu.bus.borrow_mut().set8(0xFF, address).unwrap();
+        }
+        cpu.registers.set(10, 0x150);
+        let stores = Rc::new(RefCell::new(Vec::new()));
+        let recorded = stores.clone();
+        cpu.hooks = Some(Rc::new(RefCell::new(Hooks::new().on_memory_write(0..usize::MAX, move |_, access| {
+            recorded.borrow_mut().push((access.address, access.size));
+            Action::Continue
+        }))));
+
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0025200f)));
+        assert_eq!(cpu.pc, 4);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0045200f)));
+        assert_eq!(cpu.bus.borrow_mut().get8(0x13F).unwrap(), 0xFF);
+        assert_eq!(cpu.bus.borrow_mut().get32(0x140).unwrap(), 0);
+        assert_eq!(cpu.bus.borrow_mut().get8(0x17F).unwrap(), 0);
+        assert_eq!(cpu.bus.borrow_mut().get8(0x180).unwrap(), 0xFF);
+        assert_eq!(*stores.borrow(), [(0x140, 64)]);
+        cpu.hooks = None;
+
+        cpu.pmp.set_address(0, 0xFFFFFFFF);
+        cpu.pmp.set_config(0, 0x1F);
+        cpu.privilege = Privilege::User;
+        cpu.csrs.set(MTVEC, 0x300);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0025200f)));
+        assert_eq!(cpu.pc, 0x300);
+        assert_eq!(cpu.csrs.get(MCAUSE), 2);
+        assert_eq!(cpu.privilege, Privilege::Machine);
+
+        cpu.privilege = Privilege::User;
+        cpu.csrs.set(MENVCFG, ENVCFG_CBCFE);
+        cpu.csrs.set(SENVCFG, ENVCFG_CBCFE);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0025200f)));
+        assert_eq!(cpu.pc, 0x304);
+    }
+
+    #[test]
+    fn test_pmp() {
+        let mut cpu = single_hart(1024);
+        cpu.bus.borrow_mut().set32(0x12345678, 0x100).unwrap();
+        cpu.registers.set(5, 0x200 >> 2);
+        cpu.registers.set(6, 0x0F);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x3b029073)));
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x3a031073)));
+
+        cpu.privilege = Privilege::User;
+        cpu.csrs.set(MTVEC, 0x40);
+        cpu.registers.set(8, 0x100);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00042383)));
+        assert_eq!(cpu.registers.get(7), 0x12345678);
+
+        cpu.registers.set(8, 0x300);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00042383)));
+        assert_eq!(cpu.csrs.get(MCAUSE), 5);
+        assert_eq!(cpu.csrs.get(MTVAL), 0x300);
+        assert_eq!(cpu.pc, 0x40);
+
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00042383)));
+        assert_eq!(cpu.pc, 0x44);
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut cpu = single_hart(64);
+        let program = [0x3230d073, 0x3241d073, 0x00002283, 0x00000463, 0xc0202573, 0xc00025f3, 0xc0302673, 0xc04026f3];
+        for instruction in program {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!(cpu.registers.get(10), 4);
+        assert_eq!(cpu.registers.get(11), 5);
+        assert_eq!(cpu.registers.get(12), 1);
+        assert_eq!(cpu.registers.get(13), 1);
+
+        cpu.privilege = Privilege::User;
+        cpu.csrs.set(MTVEC, 0x20);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0xc00025f3)));
+        assert_eq!(cpu.csrs.get(MCAUSE), 2);
+
+        cpu.privilege = Privilege::User;
+        cpu.csrs.set(MCOUNTEREN, 1);
+        cpu.csrs.set(SCOUNTEREN, 1);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0xc00025f3)));
+        assert_eq!(cpu.registers.get(11), 9);
+    }
+
+    #[test]
+    fn test_vector() {
+        let mut cpu = single_hart(1024);
+        for (index, word) in [1, 2, 3, 4].iter().enumerate() {
+            cpu.bus.borrow_mut().set32(*word, 0x100 + index * 4).unwrap();
+        }
+        cpu.registers.set(11, 4);
+        cpu.registers.set(12, 0x100);
+        cpu.registers.set(13, 0x200);
+
+        let program = [0x0d05f557, 0x02066087, 0x02108157, 0x0206e127, 0x021021d7, 0x42302557];
+        for instruction in program {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!(cpu.bus.borrow_mut().get32(0x20C).unwrap(), 8);
+        assert_eq!(cpu.registers.get(10), 10);
+
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x6211b057)));
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00153357)));
+        assert_eq!(cpu.vector_registers.get(6, 1, 32), 0);
+        assert_eq!(cpu.vector_registers.get(6, 2, 32), 13);
+
+        cpu.registers.set(11, 255);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0xc0047557)));
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x8215c257)));
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00902573)));
+        assert_eq!(cpu.vector_registers.get(4, 1, 8), 255);
+        assert_eq!(cpu.registers.get(10), 1);
+
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0xc2202573)));
+        assert_eq!(cpu.registers.get(10), 16);
+        assert!(!cpu.halted);
+    }
+
+    #[test]
+    fn test_vector_vsetvli() {
+        let mut cpu = single_hart(0x400);
+        cpu.csrs.set(MTVEC, 0x300);
+        cpu.registers.set(11, 100);
+        // vsetvli a0, a1, e32, m2, ta, ma: VLMAX is 8 with VLEN 128
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0d15f557)));
+        assert_eq!((cpu.registers.get(10), cpu.csrs.get(VL)), (8, 8));
+        // vsetvli a0, zero, e8, m1, tu, mu asks for VLMAX
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00007557)));
+        assert_eq!(cpu.registers.get(10), 16);
+
+        // vsetvl a0, a1, a2 with e64 at mf8, which ELEN 64 cannot hold, then with a reserved bit
+        for vtype in [3 << 3 | 0b101, 1 << 8] {
+            cpu.registers.set(12, vtype);
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x80c5f557)));
+            assert_eq!((cpu.registers.get(10), cpu.csrs.get(VL), cpu.csrs.get(VTYPE)), (0, 0, 1 << 31));
+        }
+        // vadd.vv v1, v2, v3 is illegal while vill is set
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x022180d7)));
+        assert_eq!((cpu.pc, cpu.csrs.get(MCAUSE)), (0x300, 2));
+    }
+
+    #[test]
+    fn test_vector_saturation_and_division() {
+        let mut cpu = single_hart(0x400);
+        cpu.registers.set(11, 4);
+        cpu.registers.set(12, 10);
+        [250, 10, 255, 0].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(1, index, 8, value));
+        // vsetvli a0, a1, e8, m1, tu, mu; vsaddu.vi v3, v1, 5
+        for instruction in [0x0005f557, 0x8212b1d7] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(3, index, 8)), [255, 15, 255, 5]);
+        assert_eq!(cpu.csrs.get(VXSAT), 1);
+
+        cpu.csrs.set(VXSAT, 0);
+        // vsaddu.vx v2, v1, a2
+        cpu.vector_registers.set(1, 0, 8, 245);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x82164157)));
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(2, index, 8)), [255, 20, 255, 10]);
+        assert_eq!(cpu.csrs.get(VXSAT), 1);
+        cpu.csrs.set(VXSAT, 0);
+        cpu.vector_registers.set(1, 2, 8, 0);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x82164157)));
+        assert_eq!(cpu.csrs.get(VXSAT), 0);
+
+        cpu.registers.set(11, 2);
+        [7, 9].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(1, index, 32, value));
+        [0, 2].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(2, index, 32, value));
+        // vsetvli a0, a1, e32, m1, tu, mu; vdivu.vv v3, v1, v2; vremu.vv v4, v1, v2
+        for instruction in [0x0105f557, 0x821121d7, 0x8a112257] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1].map(|index| cpu.vector_registers.get(3, index, 32)), [0xFFFFFFFF, 4]);
+        assert_eq!([0, 1].map(|index| cpu.vector_registers.get(4, index, 32)), [7, 1]);
+    }
+
+    #[test]
+    fn test_vector_reduction() {
+        let mut cpu = single_hart(0x400);
+        cpu.registers.set(11, 4);
+        [1, 2, 3, 4, 1000].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(2, index, 16, value));
+        cpu.vector_registers.set(3, 0, 16, 100);
+        cpu.vector_registers.set(0, 0, 8, 0b0101);
+        // vsetvli a0, a1, e16, m1, tu, mu; vredsum.vs v1, v2, v3; vredsum.vs v4, v2, v3, v0.t
+        for instruction in [0x0085f557, 0x0221a0d7, 0x0021a257] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!(cpu.vector_registers.get(1, 0, 16), 110);
+        assert_eq!(cpu.vector_registers.get(4, 0, 16), 104);
+    }
+
+    #[test]
+    fn test_vector_permutation() {
+        let mut cpu = single_hart(0x400);
+        cpu.registers.set(11, 4);
+        cpu.registers.set(12, 2);
+        [1, 2, 3, 4].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(2, index, 32, value));
+        [9, 9, 9, 9].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(4, index, 32, value));
+        // vsetvli a0, a1, e32, m1, tu, mu; vslideup.vi v4, v2, 1; vslidedown.vi v6, v2, 1;
+        // vslidedown.vx v7, v2, a2
+        for instruction in [0x0105f557, 0x3a20b257, 0x3e20b357, 0x3e2643d7] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [9, 1, 2, 3]);
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(6, index, 32)), [2, 3, 4, 0]);
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(7, index, 32)), [3, 4, 0, 0]);
+
+        [3, 0, 5, 1].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(3, index, 32, value));
+        // vrgather.vv v4, v2, v3; vrgather.vx v5, v2, a2
+        for instruction in [0x32218257, 0x322642d7] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [4, 1, 0, 2]);
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(5, index, 32)), [3, 3, 3, 3]);
+
+        [9, 9, 9, 9].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(4, index, 32, value));
+        cpu.vector_registers.set(1, 0, 8, 0b1010);
+        cpu.vector_registers.set(0, 0, 8, 0b0010);
+        // vcompress.vm v4, v2, v1; vcpop.m a0, v1
+        for instruction in [0x5e20a257, 0x42182557] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [2, 4, 9, 9]);
+        assert_eq!(cpu.registers.get(10), 2);
+        // vcpop.m a0, v1, v0.t
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x40182557)));
+        assert_eq!(cpu.registers.get(10), 1);
+    }
+
+    #[test]
+    fn test_vector_widening_and_narrowing() {
+        let mut cpu = single_hart(0x400);
+        cpu.registers.set(11, 4);
+        [200, 100, 255, 1].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(2, index, 8, value));
+        [100, 100, 255, 2].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(3, index, 8, value));
+        // vsetvli a0, a1, e8, m1, tu, mu; vwaddu.vv v4, v2, v3; vnsrl.wi v6, v4, 1
+        for instruction in [0x0005f557, 0xc221a257, 0xb240b357] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 16)), [300, 200, 510, 3]);
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(6, index, 8)), [150, 100, 255, 1]);
+    }
+
+    #[test]
+    fn test_vector_segment() {
+        let mut cpu = single_hart(0x400);
+        for (index, halfword) in [1, 2, 3, 4, 5, 6].iter().enumerate() {
+            cpu.bus.borrow_mut().set16(*halfword, 0x100 + index * 2).unwrap();
+        }
+        cpu.registers.set(11, 3);
+        // vsetvli a0, a1, e16, m1, tu, mu
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0085f557)));
+        cpu.registers.set(11, 0x100);
+        cpu.registers.set(12, 0x200);
+        // vlseg2e16.v v2, (a1); vsseg2e16.v v2, (a2)
+        for instruction in [0x2205d107, 0x22065127] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2].map(|index| cpu.vector_registers.get(2, index, 16)), [1, 3, 5]);
+        assert_eq!([0, 1, 2].map(|index| cpu.vector_registers.get(3, index, 16)), [2, 4, 6]);
+        assert_eq!([0, 1, 2, 3, 4, 5].map(|index| cpu.bus.borrow_mut().get16(0x200 + index * 2).unwrap()), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_vector_fault_only_first() {
+        let mut cpu = single_hart(0x400);
+        cpu.csrs.set(MTVEC, 0x300);
+        cpu.bus.borrow_mut().set32(5, 0x3F8).unwrap();
+        cpu.bus.borrow_mut().set32(6, 0x3FC).unwrap();
+        cpu.registers.set(11, 4);
+        // vsetvli a0, a1, e32, m1, tu, mu
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0105f557)));
+        // vle32ff.v v2, (a1) two elements before the end of memory
+        cpu.registers.set(11, 0x3F8);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0305e107)));
+        assert_eq!((cpu.csrs.get(VL), cpu.pc), (2, 8));
+        assert_eq!([0, 1].map(|index| cpu.vector_registers.get(2, index, 32)), [5, 6]);
+
+        // A fault on the first element still traps, leaving vl alone
+        cpu.registers.set(11, 0x400);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0305e107)));
+        assert_eq!((cpu.pc, cpu.csrs.get(MCAUSE), cpu.csrs.get(VL)), (0x300, 5, 2));
+    }
+
+    #[test]
+    fn test_vector_masked_and_tail() {
+        let mut cpu = single_hart(0x400);
+        cpu.registers.set(11, 4);
+        cpu.vector_registers.set(0, 0, 8, 0b0101);
+        [1, 2, 3, 4].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(2, index, 32, value));
+        [9, 9, 9, 9].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(4, index, 32, value));
+        // vsetvli a0, a1, e32, m1, tu, mu; vadd.vi v4, v2, 10, v0.t leaves masked elements alone
+        for instruction in [0x0105f557, 0x00253257] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [11, 9, 13, 9]);
+
+        // vsetvli a0, a1, e32, m1, tu, ma; vadd.vi v4, v2, 10, v0.t; vle32.v v6, (a1), v0.t
+        cpu.vector_agnostic_ones = true;
+        cpu.bus.borrow_mut().set32(7, 0x100).unwrap();
+        cpu.bus.borrow_mut().set32(8, 0x108).unwrap();
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0905f557)));
+        cpu.registers.set(11, 0x100);
+        for instruction in [0x00253257, 0x0005e307] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [11, 0xFFFFFFFF, 13, 0xFFFFFFFF]);
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(6, index, 32)), [7, 0xFFFFFFFF, 8, 0xFFFFFFFF]);
+
+        cpu.vector_agnostic_ones = false;
+        cpu.registers.set(11, 2);
+        [9, 9, 9, 9].iter().enumerate().for_each(|(index, &value)| cpu.vector_registers.set(4, index, 32, value));
+        // vsetvli a0, a1, e32, m1, tu, mu; vadd.vi v4, v2, 1 leaves the tail alone
+        for instruction in [0x0105f557, 0x0220b257] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [2, 3, 9, 9]);
+
+        // vsetvli a0, a1, e32, m1, ta, mu; vadd.vi v4, v2, 1, which may leave an agnostic tail too
+        for instruction in [0x0505f557, 0x0220b257] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [2, 3, 9, 9]);
+
+        // vadd.vi v4, v2, 1; vmseq.vi v8, v2, 2 fill their tails with ones
+        cpu.vector_agnostic_ones = true;
+        for instruction in [0x0220b257, 0x62213457] {
+            cpu.execute_instruction(&Decoded::new(Instruction::from_u32(instruction)));
+        }
+        assert_eq!([0, 1, 2, 3].map(|index| cpu.vector_registers.get(4, index, 32)), [2, 3, 0xFFFFFFFF, 0xFFFFFFFF]);
+        assert_eq!([0, 1].map(|index| cpu.vector_registers.get(8, index, 8)), [0b11111110, 0xFF]);
+    }
+
+    #[test]
+    fn test_zkn() {
+        assert_eq!(execute(0x22c58533, 0x0, 0x53), 0xed);
+        assert_eq!(execute(0x2ac58533, 0x0, 0xed), 0x53);
+        assert_eq!(execute(0x10259513, 0x61626380, 0), 0x940e90ef);
+        assert_eq!(execute(0x70c58533, 0x0, 0x0), crate::crypto::sm4ed(0, 0, 1));
+    }
+
+    #[test]
+    fn test_smp() {
+        let bus = Rc::new(RefCell::new(Bus::new(Memory::new(1024), 2)));
+        let mut hart0 = CPU::new(0, bus.clone());
+        let mut hart1 = CPU::new(1, bus.clone());
+        hart1.execute_instruction(&Decoded::new(Instruction::from_u32(0xf1402573)));
+        assert_eq!(hart1.registers.get(10), 1);
+
+        hart0.registers.set(11, 0x100);
+        hart0.registers.set(12, 5);
+        hart0.execute_instruction(&Decoded::new(Instruction::from_u32(0x1005a52f)));
+        hart0.execute_instruction(&Decoded::new(Instruction::from_u32(0x18c5a52f)));
+        assert_eq!(hart0.registers.get(10), 0);
+        assert_eq!(bus.borrow().get32(0x100).unwrap(), 5);
+
+        hart0.execute_instruction(&Decoded::new(Instruction::from_u32(0x1005a52f)));
+        hart1.registers.set(11, 0x100);
+        hart1.registers.set(12, 2);
+        hart1.execute_instruction(&Decoded::new(Instruction::from_u32(0x00c5a52f)));
+        assert_eq!(hart1.registers.get(10), 5);
+        hart0.execute_instruction(&Decoded::new(Instruction::from_u32(0x18c5a52f)));
+        assert_eq!(hart0.registers.get(10), 1);
+        assert_eq!(bus.borrow().get32(0x100).unwrap(), 7);
+
+        hart0.csrs.set(MTVEC, 0x200);
+        hart0.csrs.set(MIE, MIP_MSIP);
+        hart0.csrs.set(MSTATUS, MSTATUS_MIE);
+        hart1.registers.set(11, CLINT_BASE as u32);
+        hart1.registers.set(12, 1);
+        hart1.execute_instruction(&Decoded::new(Instruction::from_u32(0x00c5a023)));
+        hart0.tick();
+        assert_eq!(hart0.pc, 0x200);
+        assert_eq!(hart0.csrs.get(MCAUSE), 0x80000003);
+    }
+
+    #[test]
+    fn test_misaligned() {
+        let mut cpu = single_hart(1024);
+        cpu.csrs.set(MTVEC, 0x300);
+        cpu.registers.set(11, 0x100);
+        cpu.bus.borrow_mut().set32(0x44332211, 0x100).unwrap();
+        cpu.bus.borrow_mut().set8(0x55, 0x104).unwrap();
+
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0015a503)));
+        assert_eq!(cpu.registers.get(10), 0x55443322);
+
+        cpu.misaligned = Misaligned::Trap;
+        cpu.pc = 0x10;
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00c5a123)));
+        assert_eq!(cpu.csrs.get(MCAUSE), 6);
+        assert_eq!(cpu.csrs.get(MTVAL), 0x102);
+        assert_eq!(cpu.csrs.get(MEPC), 0x10);
+
+        cpu.misaligned = Misaligned::Fault;
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0015a503)));
+        assert_eq!(cpu.csrs.get(MCAUSE), 5);
+
+        cpu.misaligned = Misaligned::Allow;
+        cpu.registers.set(11, 0x102);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x00c5a52f)));
+        assert_eq!(cpu.csrs.get(MCAUSE), 7);
+
+        cpu.pc = 0x20;
+        cpu.registers.set(11, 0x100);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x002580e7)));
+        assert_eq!(cpu.csrs.get(MCAUSE), 0);
+        assert_eq!(cpu.csrs.get(MEPC), 0x20);
+        assert_eq!(cpu.registers.get(1), 0);
+
+        cpu.pc = 0x20;
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0xfe000ce3)));
+        assert_eq!(cpu.pc, 0x18);
+    }
+
+    #[test]
+    fn unhandled_traps_stop_the_hart_with_a_fault() {
+        let mut cpu = single_hart(64);
+        cpu.bus.borrow_mut().set32(0x4002a303, 0x10).unwrap();
+        cpu.pc = 0x10;
+        cpu.registers.set(5, 0xFFFFFF00);
+        cpu.tick();
+        assert!(cpu.halted);
+        assert_eq!(
+            cpu.get_fault().unwrap().to_string(),
+            "hart 0: load access fault at 00000010 (lw    x6,0x400,x5), tval 0x300"
+        );
+
+        let mut cpu = single_hart(64);
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0xFFFFFFFF)));
+        assert_eq!(cpu.get_fault().unwrap().to_string(), "hart 0: illegal instruction ffffffff at 00000000");
+    }
+
+    #[test]
+    fn test_strict_fence_i() {
+        let mut cpu = single_hart(64);
+        cpu.bus.borrow_mut().set_strict_fetch();
+        cpu.bus.borrow_mut().set32(0x00c5a023, 0).unwrap();
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0000100f)));
+        cpu.pc = 0;
+        cpu.registers.set(11, 4);
+        cpu.registers.set(12, 0x00000013);
+
+        cpu.tick();
+        cpu.tick();
+        assert!(cpu.halted);
+        assert_eq!(cpu.pc, 4);
+        assert_eq!(
+            cpu.get_fault().unwrap().to_string(),
+            "hart 0: self-modifying code at 00000004 executed without FENCE.I"
+        );
+
+        cpu.fault = None;
+        cpu.halted = false;
+        cpu.pc = 0;
+        cpu.execute_instruction(&Decoded::new(Instruction::from_u32(0x0000100f)));
+        cpu.tick();
+        assert!(!cpu.halted);
+        assert_eq!(cpu.pc, 8);
+    }
+}
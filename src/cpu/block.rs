@@ -0,0 +1,358 @@
+use std::cell::Cell;
+#[cfg(feature = "jit")]
+use std::cell::{OnceCell, RefCell};
+use std::rc::Rc;
+#[cfg(feature = "jit")]
+use std::rc::Weak;
+
+use crate::cpu::hooks::{Action, InstructionEvent};
+use crate::cpu::CPU;
+use crate::decode_cache::{DecodeCache, Decoded};
+use crate::instruction::InstructionType;
+#[cfg(feature = "jit")]
+use crate::jit::NativeCode;
+use crate::pmp::Access;
+
+/// Times a block runs before the JIT compiles it.
+#[cfg(feature = "jit")]
+const HOT_BLOCK_RUNS: u32 = 16;
+
+/// A decoded instruction bound to the `execute_*` method that runs it.
+struct MicroOp {
+    handler: fn(&mut CPU, &Decoded),
+    decoded: Decoded,
+    /// Whether the instruction can see the counters or the CLINT, so the clock must be brought
+    /// up to date before it runs
+    syncs: bool,
+}
+
+/// A run of straight-line instructions ending at the first one that may leave it, translated
+/// once into micro-ops. Blocks never cross a page, and a store to any of their words
+/// invalidates them.
+pub struct Block {
+    ops: Vec<MicroOp>,
+    valid: Cell<bool>,
+    #[cfg(feature = "jit")]
+    runs: Cell<u32>,
+    #[cfg(feature = "jit")]
+    native: OnceCell<Option<NativeCode>>,
+    /// The blocks its native code went on to, by start address, so chaining into them again
+    /// needs no lookup
+    #[cfg(feature = "jit")]
+    links: RefCell<Vec<(usize, Weak<Block>)>>,
+}
+
+impl Block {
+    /// The number of bytes of code the block covers.
+    pub fn size(&self) -> usize {
+        self.ops.len() * 4
+    }
+
+    pub fn invalidate(&self) {
+        self.valid.set(false);
+    }
+}
+
+/// Whether an instruction of type `_type` can transfer control or change how the following
+/// instructions are fetched, so that it must end its block.
+fn ends_block(_type: Option<InstructionType>) -> bool {
+    match _type {
+        None => true,
+        Some(_type) => matches!(_type,
+            InstructionType::JAL | InstructionType::JALR | InstructionType::BEQ | InstructionType::BNE
+            | InstructionType::BLT | InstructionType::BGE | InstructionType::BLTU | InstructionType::BGEU
+            | InstructionType::ECALL | InstructionType::EBREAK | InstructionType::MRET | InstructionType::WFI
+            | InstructionType::FENCE_I | InstructionType::CSRRW | InstructionType::CSRRS | InstructionType::CSRRC
+            | InstructionType::CSRRWI | InstructionType::CSRRSI | InstructionType::CSRRCI),
+    }
+}
+
+/// Whether `decoded` accesses memory, which may be the CLINT, or ends its block, which covers
+/// every CSR access.
+fn syncs(decoded: &Decoded) -> bool {
+    ends_block(decoded._type)
+        || matches!(decoded.instruction.opcode(), 0b0000011 | 0b0000111 | 0b0001111 | 0b0100011 | 0b0100111 | 0b0101111)
+}
+
+impl CPU {
+    fn translate(&self, start: usize) -> Option<Block> {
+        let mut bus = self.bus.borrow_mut();
+        let mut ops = Vec::new();
+        let mut address = start;
+
+        while address < bus.len().saturating_sub(4) && DecodeCache::same_page(start, address) {
+            let Ok(decoded) = bus.fetch(address) else { break };
+            let handler = match decoded._type {
+                Some(_type) if self.isa.supports(_type) => Self::handler(_type),
+                _ => Self::illegal_instruction,
+            };
+            ops.push(MicroOp { handler, decoded, syncs: syncs(&decoded) });
+            if ends_block(decoded._type) {
+                break;
+            }
+            address += 4;
+        }
+
+        if ops.is_empty() {
+            return None;
+        }
+        Some(Block {
+            ops,
+            valid: Cell::new(true),
+            #[cfg(feature = "jit")]
+            runs: Cell::new(0),
+            #[cfg(feature = "jit")]
+            native: OnceCell::new(),
+            #[cfg(feature = "jit")]
+            links: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// The block starting at `pc`, translating it on first use. There is none when the block
+    /// could not run as a whole: `pc` is misaligned or outside main memory, or fetching part
+    /// of it would fault or trip the strict FENCE.I check.
+    fn block_at(&mut self, pc: usize) -> Option<Rc<Block>> {
+        if !pc.is_multiple_of(4) {
+            return None;
+        }
+
+        let cached = self.bus.borrow().get_block(pc);
+        let block = match cached {
+            Some(block) => block,
+            None => {
+                let block = Rc::new(self.translate(pc)?);
+                self.bus.borrow_mut().insert_block(pc, block.clone());
+                block
+            }
+        };
+
+        self.runnable(&block, pc).then_some(block)
+    }
+
+    /// Whether `block`, starting at `pc`, can be fetched as a whole without faulting or
+    /// tripping the strict FENCE.I check.
+    fn runnable(&self, block: &Block, pc: usize) -> bool {
+        let size = block.size();
+        self.access_allowed(pc, size, Access::Execute) && !self.bus.borrow().is_unfenced(self.hart_id, pc, size)
+    }
+
+    /// Runs at most `limit` instructions of the block at `pc` and returns how many ticks that
+    /// took. Interrupts are only taken between blocks. Whatever cannot run as a block is
+    /// single-stepped through `tick`, so instruction counts match one-at-a-time execution.
+    pub fn run_block(&mut self, limit: usize) -> usize {
+        if self.take_interrupt() {
+            return 1;
+        }
+        let Some(block) = self.block_at(self.pc) else {
+            return self.tick();
+        };
+
+        self.run_ops(&block, 0, limit)
+    }
+
+    /// Interprets at most `limit` micro-ops of `block` from `first`, returning how many ran.
+    /// Unless instructions are observed or counted by event, the clock and `minstret` are
+    /// advanced once for the whole run, and before any instruction that could see them.
+    fn run_ops(&mut self, block: &Block, first: usize, limit: usize) -> usize {
+        if self.observed() || self.counters.counts_events() {
+            return self.retire_ops(block, first, limit);
+        }
+
+        let (mut executed, mut ticks) = (0, 0);
+        for op in block.ops[first..].iter().take(limit) {
+            if op.syncs && ticks > 0 {
+                self.advance(ticks, ticks);
+                ticks = 0;
+            }
+            let pc = self.pc;
+            self.trapped = false;
+            (op.handler)(self, &op.decoded);
+            executed += 1;
+            ticks += 1;
+
+            if self.trapped || self.halted || self.pc != pc + 4 || !block.valid.get() {
+                break;
+            }
+        }
+        self.advance(ticks, ticks - self.trapped as u64);
+        executed
+    }
+
+    /// Advances the CLINT and the cycle counter by `instructions`, and `minstret` by `retired`.
+    fn advance(&mut self, instructions: u64, retired: u64) {
+        self.bus.borrow_mut().clint.advance(instructions);
+        self.counters.advance(instructions, retired);
+    }
+
+    /// Like `run_ops`, but retires each micro-op as it runs.
+    fn retire_ops(&mut self, block: &Block, first: usize, limit: usize) -> usize {
+        let mut executed = 0;
+        for op in block.ops[first..].iter().take(limit) {
+            if self.hook_instruction(InstructionEvent::Fetch, &op.decoded.instruction) == Action::Stop {
+                break;
+            }
+            let (pc, privilege) = (self.pc, self.privilege);
+            self.trapped = false;
+            (op.handler)(self, &op.decoded);
+            self.retire(&op.decoded, pc, privilege);
+            executed += 1;
+
+            if self.trapped || self.halted || self.pc != pc + 4 || !block.valid.get() {
+                break;
+            }
+        }
+        executed
+    }
+
+    /// Like `run_block`, but once a block is hot it runs as native code: all of it when its
+    /// jump or branch compiles along with everything before it, and otherwise its
+    /// register-only start, with the rest interpreted. A block run wholly natively chains into
+    /// the next one without returning to the scheduler, taking interrupts in between. The
+    /// clock is advanced once per native run. Native code cannot report its register writes,
+    /// run hooks or count events, so blocks are interpreted while tracing, checking, hooking
+    /// or counting events.
+    #[cfg(feature = "jit")]
+    pub fn run_native_block(&mut self, limit: usize) -> usize {
+        if self.observed() || self.counters.counts_events() {
+            return self.run_block(limit);
+        }
+        if self.take_interrupt() {
+            return 1;
+        }
+        let Some(mut block) = self.block_at(self.pc) else {
+            return self.tick();
+        };
+
+        let mut executed = 0;
+        loop {
+            let native = Self::native(&block, self.pc).filter(|native| native.instructions() <= limit - executed);
+            let Some(native) = native else {
+                return executed + self.run_ops(&block, 0, limit - executed);
+            };
+            let instructions = native.instructions();
+            self.pc = native.run(self.registers.as_mut_slice());
+            self.advance(instructions as u64, instructions as u64);
+            executed += instructions;
+            if !native.whole() {
+                return executed + self.run_ops(&block, instructions, limit - executed);
+            }
+
+            if executed == limit {
+                return executed;
+            }
+            if self.take_interrupt() {
+                return executed + 1;
+            }
+            match self.linked_block(&block, self.pc) {
+                Some(next) => block = next,
+                None => return executed,
+            }
+        }
+    }
+
+    /// The native code of `block`, which starts at `start`, once the block is hot.
+    #[cfg(feature = "jit")]
+    fn native(block: &Block, start: usize) -> Option<&NativeCode> {
+        block.runs.set(block.runs.get().saturating_add(1));
+        if block.runs.get() < HOT_BLOCK_RUNS {
+            return None;
+        }
+        block.native.get_or_init(|| NativeCode::compile(start, block.ops.iter().map(|op| &op.decoded))).as_ref()
+    }
+
+    /// The block at `pc` that `block` went on to, linking the two the first time.
+    #[cfg(feature = "jit")]
+    fn linked_block(&mut self, block: &Block, pc: usize) -> Option<Rc<Block>> {
+        let linked = block.links.borrow().iter()
+            .find(|(start, _)| *start == pc)
+            .and_then(|(_, next)| next.upgrade())
+            .filter(|next| next.valid.get());
+        if let Some(next) = linked {
+            return self.runnable(&next, pc).then_some(next);
+        }
+
+        let next = self.block_at(pc)?;
+        let mut links = block.links.borrow_mut();
+        links.retain(|(start, _)| *start != pc);
+        links.push((pc, Rc::downgrade(&next)));
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::bus::Bus;
+    use crate::counters::{CYCLE, INSTRET};
+    use crate::cpu::CPU;
+    use crate::memory::Memory;
+
+    fn hart_with_program(program: &[u32]) -> CPU {
+        let mut memory = Memory::new(256);
+        for (index, word) in program.iter().enumerate() {
+            memory.set32(*word, index * 4).unwrap();
+        }
+        CPU::new(0, Rc::new(RefCell::new(Bus::new(memory, 1))))
+    }
+
+    #[test]
+    fn blocks_match_single_stepping() {
+        // addi x12,x0,5; addi x11,x11,1; addi x10,x10,1; bne x10,x12,-8; ebreak
+        let program = [0x00500613, 0x00158593, 0x00150513, 0xfec51ce3, 0x00100073];
+        let mut stepped = hart_with_program(&program);
+        let mut blocked = hart_with_program(&program);
+
+        while stepped.running() {
+            stepped.tick();
+        }
+        let mut ticks = 0;
+        while blocked.running() {
+            ticks += blocked.run_block(usize::MAX);
+        }
+
+        assert_eq!(blocked.registers.get(11), 5);
+        assert_eq!(blocked.registers.get(11), stepped.registers.get(11));
+        assert_eq!(blocked.pc, stepped.pc);
+        assert_eq!(blocked.counters.get(INSTRET), stepped.counters.get(INSTRET));
+        assert_eq!(blocked.counters.get(CYCLE), stepped.counters.get(CYCLE));
+        assert_eq!(blocked.bus.borrow().clint.get_mtime(), stepped.bus.borrow().clint.get_mtime());
+        assert_eq!(ticks, 17);
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn native_blocks_match_single_stepping() {
+        // addi x12,x0,100; addi x11,x11,3; slli x13,x11,2; addi x10,x10,1; bne x10,x12,-12; ebreak
+        let program = [0x06400613, 0x00358593, 0x00259693, 0x00150513, 0xfec51ae3, 0x00100073];
+        let mut stepped = hart_with_program(&program);
+        let mut native = hart_with_program(&program);
+
+        while stepped.running() {
+            stepped.tick();
+        }
+        while native.running() {
+            native.run_native_block(7);
+        }
+
+        assert_eq!(native.registers.get(13), 1200);
+        assert_eq!(native.registers.get(13), stepped.registers.get(13));
+        assert_eq!(native.counters.get(INSTRET), stepped.counters.get(INSTRET));
+        assert_eq!(native.bus.borrow().clint.get_mtime(), stepped.bus.borrow().clint.get_mtime());
+        assert!(native.block_at(4).unwrap().native.get().unwrap().as_ref().is_some_and(|code| code.whole()));
+    }
+
+    #[test]
+    fn stores_end_the_running_block() {
+        // addi x10,x0,0x13; sw x10,8(x0); ebreak (overwritten with nop); addi x11,x0,1; ebreak
+        let program = [0x01300513, 0x00a02423, 0x00100073, 0x00100593, 0x00100073];
+        let mut cpu = hart_with_program(&program);
+
+        assert_eq!(cpu.run_block(usize::MAX), 2);
+        while cpu.running() {
+            cpu.run_block(usize::MAX);
+        }
+        assert_eq!(cpu.registers.get(11), 1);
+    }
+}
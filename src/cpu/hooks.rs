@@ -0,0 +1,331 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::cpu::CPU;
+use crate::error::Error;
+use crate::instruction::Instruction;
+use crate::trace::{MemoryAccess, Retired};
+use crate::trap::Privilege;
+
+/// What a hook asks the hart to do once it returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Carry on as if the hook were not there
+    Continue,
+    /// Stop the hart. Fetch, ECALL and EBREAK hooks stop it before the instruction takes
+    /// effect, the others once the current instruction has finished. `Machine::resume` lets
+    /// it run again
+    Stop,
+    /// For ECALL and EBREAK hooks: the hook has emulated the instruction, which retires and
+    /// moves on without trapping or halting. Other hooks treat it like `Continue`
+    Handled,
+}
+
+/// A CSR instruction's access to `csr`: the value it read, if any, and the value it wrote
+/// before any WARL masking, if any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsrAccess {
+    pub csr: u16,
+    pub read: Option<u32>,
+    pub written: Option<u32>,
+}
+
+/// A trap taken into machine mode: its `mcause`, `mtval` and the PC it interrupted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrapEntry {
+    pub cause: u32,
+    pub tval: u32,
+    pub epc: usize,
+}
+
+/// The hart a hook runs on. Hooks may read and change its state, but nothing they do through
+/// it runs other hooks.
+pub struct Hart<'a> {
+    cpu: &'a mut CPU,
+}
+
+impl Hart<'_> {
+    pub fn id(&self) -> usize {
+        self.cpu.hart_id
+    }
+
+    pub fn get_privilege(&self) -> Privilege {
+        self.cpu.privilege
+    }
+
+    pub fn get_pc(&self) -> usize {
+        self.cpu.pc
+    }
+
+    /// Moves the hart to `pc`. Instruction hooks that do this take over control flow, so the
+    /// instruction should also be `Handled` or have retired.
+    pub fn set_pc(&mut self, pc: usize) {
+        self.cpu.pc = pc;
+    }
+
+    pub fn get_register(&self, register: usize) -> u32 {
+        self.cpu.registers.get(register)
+    }
+
+    pub fn set_register(&mut self, register: usize, data: u32) {
+        self.cpu.registers.set(register, data)
+    }
+
+    pub fn get_csr(&self, csr: u16) -> u32 {
+        self.cpu.read_csr(csr)
+    }
+
+    pub fn set_csr(&mut self, csr: u16, data: u32) {
+        self.cpu.set_csr(csr, data)
+    }
+
+    /// Reads memory or device registers without PMP checks.
+    pub fn read_memory(&self, address: usize, data: &mut [u8]) -> Result<(), Error> {
+        let bus = self.cpu.bus.borrow();
+        for (index, byte) in data.iter_mut().enumerate() {
+            *byte = bus.get8(address.wrapping_add(index))?;
+        }
+        Ok(())
+    }
+
+    /// Writes memory or device registers without PMP checks, as a guest store would.
+    pub fn write_memory(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+        let mut bus = self.cpu.bus.borrow_mut();
+        for (index, byte) in data.iter().enumerate() {
+            bus.set8(*byte, address.wrapping_add(index))?;
+        }
+        Ok(())
+    }
+}
+
+type Hook<T> = Box<dyn FnMut(&mut Hart, &T) -> Action>;
+type RetireHook = Box<dyn FnMut(&mut Hart, &Retired) -> Action>;
+type MemoryHook = (Range<usize>, Box<dyn FnMut(&mut Hart, &mut MemoryAccess) -> Action>);
+
+/// Callbacks run as harts execute, for tools that observe or steer execution without changing
+/// the interpreter. Hooks of one kind run in the order they were added, and the hart stops if
+/// any asks it to.
+#[derive(Default)]
+pub struct Hooks {
+    fetch: Vec<Hook<Instruction>>,
+    retire: Vec<RetireHook>,
+    memory_read: Vec<MemoryHook>,
+    memory_write: Vec<MemoryHook>,
+    csr: Vec<Hook<CsrAccess>>,
+    trap_entry: Vec<Hook<TrapEntry>>,
+    trap_exit: Vec<Hook<Instruction>>,
+    ecall: Vec<Hook<Instruction>>,
+    ebreak: Vec<Hook<Instruction>>,
+}
+
+/// Combines the actions of several hooks: stopping wins over handling, which wins over
+/// continuing.
+fn combine(actions: impl Iterator<Item = Action>) -> Action {
+    actions.fold(Action::Continue, |combined, action| match (combined, action) {
+        (Action::Stop, _) | (_, Action::Stop) => Action::Stop,
+        (Action::Handled, _) | (_, Action::Handled) => Action::Handled,
+        _ => Action::Continue,
+    })
+}
+
+impl Hooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the hooks of `other`, to run after these.
+    pub fn append(&mut self, other: Hooks) {
+        let Hooks { fetch, retire, memory_read, memory_write, csr, trap_entry, trap_exit, ecall, ebreak } = other;
+        self.fetch.extend(fetch);
+        self.retire.extend(retire);
+        self.memory_read.extend(memory_read);
+        self.memory_write.extend(memory_write);
+        self.csr.extend(csr);
+        self.trap_entry.extend(trap_entry);
+        self.trap_exit.extend(trap_exit);
+        self.ecall.extend(ecall);
+        self.ebreak.extend(ebreak);
+    }
+
+    /// Runs before each instruction executes, with the PC still pointing at it.
+    pub fn on_fetch(mut self, hook: impl FnMut(&mut Hart, &Instruction) -> Action + 'static) -> Self {
+        self.fetch.push(Box::new(hook));
+        self
+    }
+
+    /// Runs after each instruction, trapped or not, with the registers, CSRs and memory it
+    /// touched.
+    pub fn on_retire(mut self, hook: impl FnMut(&mut Hart, &Retired) -> Action + 'static) -> Self {
+        self.retire.push(Box::new(hook));
+        self
+    }
+
+    /// Runs after each load touching `range`. The hook may change the data the load returns.
+    pub fn on_memory_read(
+        mut self, range: Range<usize>, hook: impl FnMut(&mut Hart, &mut MemoryAccess) -> Action + 'static,
+    ) -> Self {
+        self.memory_read.push((range, Box::new(hook)));
+        self
+    }
+
+    /// Runs before each store touching `range`. The hook may change the data stored.
+    pub fn on_memory_write(
+        mut self, range: Range<usize>, hook: impl FnMut(&mut Hart, &mut MemoryAccess) -> Action + 'static,
+    ) -> Self {
+        self.memory_write.push((range, Box::new(hook)));
+        self
+    }
+
+    /// Runs after each CSR instruction that reads or writes a CSR.
+    pub fn on_csr(mut self, hook: impl FnMut(&mut Hart, &CsrAccess) -> Action + 'static) -> Self {
+        self.csr.push(Box::new(hook));
+        self
+    }
+
+    /// Runs once an exception or interrupt has been taken, with the PC at the handler.
+    pub fn on_trap_entry(mut self, hook: impl FnMut(&mut Hart, &TrapEntry) -> Action + 'static) -> Self {
+        self.trap_entry.push(Box::new(hook));
+        self
+    }
+
+    /// Runs once MRET has returned from a trap, with the PC at the return address.
+    pub fn on_trap_exit(mut self, hook: impl FnMut(&mut Hart, &Instruction) -> Action + 'static) -> Self {
+        self.trap_exit.push(Box::new(hook));
+        self
+    }
+
+    /// Runs before ECALL traps. Returning `Handled` emulates the call instead, such as a
+    /// system call whose arguments and results the hook moves through the registers.
+    pub fn on_ecall(mut self, hook: impl FnMut(&mut Hart, &Instruction) -> Action + 'static) -> Self {
+        self.ecall.push(Box::new(hook));
+        self
+    }
+
+    /// Runs before EBREAK halts the hart. Returning `Handled` continues after it instead.
+    pub fn on_ebreak(mut self, hook: impl FnMut(&mut Hart, &Instruction) -> Action + 'static) -> Self {
+        self.ebreak.push(Box::new(hook));
+        self
+    }
+}
+
+/// Which instruction hooks to run.
+#[derive(Clone, Copy)]
+pub(crate) enum InstructionEvent {
+    Fetch,
+    TrapExit,
+    Ecall,
+    Ebreak,
+}
+
+impl CPU {
+    /// Runs execution hooks on this hart, shared with the other harts of the machine.
+    pub fn set_hooks(&mut self, hooks: Rc<RefCell<Hooks>>) {
+        self.hooks = Some(hooks);
+        self.registers.record_writes();
+    }
+
+    pub(crate) fn get_hooks(&self) -> Option<Rc<RefCell<Hooks>>> {
+        self.hooks.clone()
+    }
+
+    /// Runs `run` on the hooks with this hart, stopping it if any hook asked to.
+    fn run_hooks(&mut self, run: impl FnOnce(&mut Hooks, &mut Hart) -> Action) -> Action {
+        let Some(hooks) = self.hooks.clone() else {
+            return Action::Continue;
+        };
+        let action = run(&mut hooks.borrow_mut(), &mut Hart { cpu: self });
+        if action == Action::Stop {
+            self.halted = true;
+        }
+        action
+    }
+
+    pub(crate) fn hook_instruction(&mut self, event: InstructionEvent, instruction: &Instruction) -> Action {
+        self.run_hooks(|hooks, hart| {
+            let hooks = match event {
+                InstructionEvent::Fetch => &mut hooks.fetch,
+                InstructionEvent::TrapExit => &mut hooks.trap_exit,
+                InstructionEvent::Ecall => &mut hooks.ecall,
+                InstructionEvent::Ebreak => &mut hooks.ebreak,
+            };
+            combine(hooks.iter_mut().map(|hook| hook(hart, instruction)))
+        })
+    }
+
+    pub(crate) fn hook_retire(&mut self, retired: &Retired) {
+        self.run_hooks(|hooks, hart| combine(hooks.retire.iter_mut().map(|hook| hook(hart, retired))));
+    }
+
+    pub(crate) fn hook_memory(&mut self, access: &mut MemoryAccess) {
+        self.run_hooks(|hooks, hart| {
+            let hooks = if access.write { &mut hooks.memory_write } else { &mut hooks.memory_read };
+            let touched = access.address..access.address + access.size;
+            let hooks = hooks.iter_mut().filter(|(range, _)| range.start < touched.end && touched.start < range.end);
+            combine(hooks.map(|(_, hook)| hook(hart, access)))
+        });
+    }
+
+    pub(crate) fn hook_csr(&mut self, access: CsrAccess) {
+        self.run_hooks(|hooks, hart| combine(hooks.csr.iter_mut().map(|hook| hook(hart, &access))));
+    }
+
+    pub(crate) fn hook_trap_entry(&mut self, entry: TrapEntry) {
+        self.run_hooks(|hooks, hart| combine(hooks.trap_entry.iter_mut().map(|hook| hook(hart, &entry))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::cpu::hooks::{Action, CsrAccess, Hooks};
+    use crate::machine::{Engine, MachineBuilder};
+
+    #[test]
+    fn hooks_observe_and_steer_execution() {
+        // addi x17,x0,64; ecall; lw x11,256(x0); csrrw x0,mscratch,x10; ebreak
+        let program: Vec<u8> = [0x04000893u32, 0x00000073, 0x10002583, 0x34051073, 0x00100073]
+            .iter().flat_map(|word| word.to_le_bytes()).collect();
+        for engine in [Engine::Interp, Engine::Block] {
+            let mut machine = MachineBuilder::new(0x1000).engine(engine).program(0, &program).build().unwrap();
+
+            let csrs = Rc::new(RefCell::new(Vec::new()));
+            let recorded = csrs.clone();
+            let mut stopped = false;
+            machine.set_hooks(Hooks::new()
+                .on_ecall(|hart, _| {
+                    hart.set_register(10, hart.get_register(17) + 1);
+                    Action::Handled
+                })
+                .on_memory_read(0x100..0x104, |_, access| {
+                    access.data = 0x1234;
+                    Action::Continue
+                })
+                .on_fetch(move |_, instruction| match instruction.get_raw() {
+                    0x34051073 if !stopped => {
+                        stopped = true;
+                        Action::Stop
+                    }
+                    _ => Action::Continue,
+                })
+                .on_csr(move |_, access| {
+                    recorded.borrow_mut().push(*access);
+                    Action::Continue
+                }));
+
+            machine.run();
+            assert_eq!((machine.get_pc(0).unwrap(), machine.get_register(0, 10).unwrap(), machine.get_register(0, 11).unwrap()), (0xC, 65, 0x1234));
+            assert!(csrs.borrow().is_empty());
+            // The stopped instruction has not run yet
+            assert_eq!(machine.get_instructions(), 3);
+
+            machine.resume();
+            machine.set_register(0, 10, 66).unwrap();
+            machine.run();
+            assert_eq!((machine.get_pc(0).unwrap(), machine.get_instructions()), (0x10, 5));
+            assert_eq!(*csrs.borrow(), [CsrAccess { csr: 0x340, read: None, written: Some(66) }]);
+        }
+    }
+}
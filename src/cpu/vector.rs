@@ -0,0 +1,1270 @@
+use crate::cpu::CPU;
+use crate::csr::{VL, VSTART, VTYPE, VXRM, VXSAT};
+use crate::decode_cache::Decoded;
+use crate::instruction::vector::{OPFVV, OPIVI, OPIVV, OPMVV};
+use crate::instruction::InstructionType;
+use crate::pmp::Access;
+use crate::vector_registers::VectorRegisters;
+
+const VILL: u32 = 1 << 31;
+
+/// The active `vtype`/`vl` state, decoded once per vector instruction.
+#[derive(Clone, Copy)]
+pub struct VectorConfig {
+    pub sew: usize,
+    /// log2 of LMUL, from -3 (mf8) to 3 (m8).
+    pub lmul: i32,
+    pub vl: usize,
+    pub vstart: usize,
+    pub vta: bool,
+    pub vma: bool,
+    pub vlmax: usize,
+}
+
+/// Per-element state handed to the arithmetic closures.
+pub struct ElementContext {
+    pub sew: usize,
+    pub vxrm: u32,
+    pub v0: bool,
+    pub saturated: bool,
+}
+
+fn ones(bits: usize) -> u64 {
+    if bits >= 64 { u64::MAX } else { (1 << bits) - 1 }
+}
+
+fn sign_extend(value: u64, bits: usize) -> i64 {
+    let shift = 64 - bits;
+    ((value << shift) as i64) >> shift
+}
+
+fn scaled(sew: usize, scale: i32) -> usize {
+    if scale >= 0 { sew << scale } else { sew >> -scale }
+}
+
+fn roundoff(value: i128, shift: u32, vxrm: u32) -> i128 {
+    if shift == 0 {
+        return value;
+    }
+
+    let bit = |n: u32| (value >> n) & 1;
+    let below = |n: u32| value & ((1 << n) - 1) != 0;
+    let round = match vxrm & 0b11 {
+        0b00 => bit(shift - 1),
+        0b01 => bit(shift - 1) & (below(shift - 1) as i128 | bit(shift)),
+        0b10 => 0,
+        _ => (bit(shift) == 0 && below(shift)) as i128,
+    };
+
+    (value >> shift) + round
+}
+
+fn saturate_unsigned(value: i128, bits: usize, context: &mut ElementContext) -> u64 {
+    let max = ones(bits) as i128;
+    if value > max {
+        context.saturated = true;
+        max as u64
+    } else if value < 0 {
+        context.saturated = true;
+        0
+    } else {
+        value as u64
+    }
+}
+
+fn saturate_signed(value: i128, bits: usize, context: &mut ElementContext) -> u64 {
+    let max = (1i128 << (bits - 1)) - 1;
+    let min = -(1i128 << (bits - 1));
+    let clamped = if value > max {
+        context.saturated = true;
+        max
+    } else if value < min {
+        context.saturated = true;
+        min
+    } else {
+        value
+    };
+    clamped as u64 & ones(bits)
+}
+
+fn float_from_bits(bits: u64, sew: usize) -> f64 {
+    if sew == 32 { f32::from_bits(bits as u32) as f64 } else { f64::from_bits(bits) }
+}
+
+fn float_to_bits(value: f64, sew: usize) -> u64 {
+    match (sew, value.is_nan()) {
+        (32, true) => 0x7FC00000,
+        (32, false) => (value as f32).to_bits() as u64,
+        (_, true) => 0x7FF8000000000000,
+        (_, false) => value.to_bits(),
+    }
+}
+
+fn float_min(a: f64, b: f64) -> f64 {
+    if a == 0.0 && b == 0.0 {
+        if a.is_sign_negative() { a } else { b }
+    } else {
+        a.min(b)
+    }
+}
+
+fn float_max(a: f64, b: f64) -> f64 {
+    if a == 0.0 && b == 0.0 {
+        if a.is_sign_positive() { a } else { b }
+    } else {
+        a.max(b)
+    }
+}
+
+fn float_fused(a: u64, b: u64, c: u64, sew: usize, negate_product: bool, negate_addend: bool) -> u64 {
+    if sew == 32 {
+        let (a, b, c) = (f32::from_bits(a as u32), f32::from_bits(b as u32), f32::from_bits(c as u32));
+        let a = if negate_product { -a } else { a };
+        let c = if negate_addend { -c } else { c };
+        float_to_bits(a.mul_add(b, c) as f64, 32)
+    } else {
+        let (a, b, c) = (f64::from_bits(a), f64::from_bits(b), f64::from_bits(c));
+        let a = if negate_product { -a } else { a };
+        let c = if negate_addend { -c } else { c };
+        float_to_bits(a.mul_add(b, c), 64)
+    }
+}
+
+fn group_valid(register: u8, emul: i32) -> bool {
+    if emul > 3 {
+        return false;
+    }
+    emul <= 0 || (register as usize).is_multiple_of(1 << emul)
+}
+
+impl CPU {
+    /// Replaces the vector unit with one of `vlen` bits per register and elements of up to `elen` bits.
+    pub fn set_vector_config(&mut self, vlen: usize, elen: usize) {
+        self.vector_registers = VectorRegisters::new(vlen);
+        self.elen = elen;
+        self.csrs.set(VTYPE, VILL);
+        self.csrs.set(VL, 0);
+    }
+
+    pub fn dump_vector_registers(&self) {
+        self.vector_registers.dump();
+        println!(" vl  {:08x}  vtype {:08x}", self.csrs.get(VL), self.csrs.get(VTYPE));
+    }
+
+    fn decode_vtype(&self, vtype: u32) -> Option<(usize, i32, usize)> {
+        let vlmul = vtype & 0b111;
+        let vsew = vtype >> 3 & 0b111;
+        if vtype >> 8 != 0 || vsew > 3 || vlmul == 0b100 {
+            return None;
+        }
+
+        let sew = 8 << vsew;
+        let lmul = if vlmul < 4 { vlmul as i32 } else { vlmul as i32 - 8 };
+        if sew > self.elen || (lmul < 0 && sew > self.elen >> -lmul) {
+            return None;
+        }
+
+        let vlen = self.vector_registers.vlenb() * 8;
+        Some((sew, lmul, scaled(vlen, lmul) / sew))
+    }
+
+    pub(crate) fn vector_config(&self) -> Option<VectorConfig> {
+        let vtype = self.csrs.get(VTYPE);
+        if vtype & VILL != 0 {
+            return None;
+        }
+
+        let (sew, lmul, vlmax) = self.decode_vtype(vtype)?;
+        Some(VectorConfig {
+            sew,
+            lmul,
+            vl: self.csrs.get(VL) as usize,
+            vstart: self.csrs.get(VSTART) as usize,
+            vta: vtype >> 6 & 1 != 0,
+            vma: vtype >> 7 & 1 != 0,
+            vlmax,
+        })
+    }
+
+    fn vector_set_vtype(&mut self, decoded: &Decoded, vtype: u32, avl: u32) {
+        let rd = decoded.rd;
+
+        let vl = match self.decode_vtype(vtype) {
+            Some((_, _, vlmax)) => {
+                let vl = (avl as usize).min(vlmax) as u32;
+                self.csrs.set(VTYPE, vtype);
+                self.csrs.set(VL, vl);
+                vl
+            }
+            None => {
+                self.csrs.set(VTYPE, VILL);
+                self.csrs.set(VL, 0);
+                0
+            }
+        };
+
+        self.registers.set(rd as usize, vl);
+        self.csrs.set(VSTART, 0);
+        self.pc += 4;
+    }
+
+    fn vector_avl(&self, decoded: &Decoded) -> u32 {
+        let rd = decoded.rd;
+        let rs1 = decoded.rs1;
+
+        if rs1 != 0 {
+            self.registers.get(rs1 as usize)
+        } else if rd != 0 {
+            u32::MAX
+        } else {
+            self.csrs.get(VL)
+        }
+    }
+
+    pub fn execute_vsetvli(&mut self, decoded: &Decoded) {
+        let avl = self.vector_avl(decoded);
+        self.vector_set_vtype(decoded, decoded.instruction.get_zimm_vtype(), avl);
+    }
+
+    pub fn execute_vsetivli(&mut self, decoded: &Decoded) {
+        let avl = decoded.rs1 as u32;
+        self.vector_set_vtype(decoded, decoded.instruction.get_zimm_vtype(), avl);
+    }
+
+    pub fn execute_vsetvl(&mut self, decoded: &Decoded) {
+        let avl = self.vector_avl(decoded);
+        let vtype = self.registers.get(decoded.rs2 as usize);
+        self.vector_set_vtype(decoded, vtype, avl);
+    }
+
+    fn vector_active(&self, decoded: &Decoded, index: usize) -> bool {
+        decoded.instruction.get_vm() || self.vector_registers.get_mask(0, index)
+    }
+
+    /// The scalar operand of a `.vx`/`.vi` form, sign-extended and truncated to `sew` bits.
+    fn vector_scalar(&self, decoded: &Decoded, sew: usize) -> u64 {
+        let value = match decoded.instruction.get_funct3() {
+            OPIVI if decoded.instruction.has_vector_uimm() => decoded.rs1 as u32,
+            OPIVI => decoded.instruction.get_simm5(),
+            _ => self.registers.get(decoded.rs1 as usize),
+        };
+        value as i32 as i64 as u64 & ones(sew)
+    }
+
+    fn vector_operand(&self, decoded: &Decoded, index: usize, sew: usize) -> u64 {
+        match decoded.instruction.get_funct3() {
+            OPIVV | OPMVV | OPFVV => self.vector_registers.get(decoded.rs1 as usize, index, sew),
+            _ => self.vector_scalar(decoded, sew),
+        }
+    }
+
+    fn vector_fill_masked(&mut self, config: &VectorConfig, vd: usize, index: usize, eew: usize) {
+        if config.vma && self.vector_agnostic_ones {
+            self.vector_registers.set(vd, index, eew, ones(eew));
+        }
+    }
+
+    fn vector_fill_tail(&mut self, config: &VectorConfig, vd: usize, from: usize, eew: usize, emul: i32) {
+        if config.vta && self.vector_agnostic_ones {
+            let end = (1usize << emul.max(0)) * self.vector_registers.vlenb() * 8 / eew;
+            for index in from..end {
+                self.vector_registers.set(vd, index, eew, ones(eew));
+            }
+        }
+    }
+
+    fn vector_fill_mask_tail(&mut self, vd: usize, from: usize) {
+        if self.vector_agnostic_ones {
+            for index in from..self.vector_registers.vlenb() * 8 {
+                self.vector_registers.set_mask(vd, index, true);
+            }
+        }
+    }
+
+    fn vector_finish(&mut self) {
+        self.csrs.set(VSTART, 0);
+        self.pc += 4;
+    }
+
+    /// Runs `vd[i] = op(vs2[i], operand, vd[i])` over the body elements. `dest_scale` and
+    /// `source_scale` give the EEW of `vd` and `vs2` as log2 multiples of SEW, which covers
+    /// widening (1, 0), `.w` (1, 1), narrowing (0, 1) and extension (0, -n) forms.
+    /// With `uses_v0` the mask register is a carry/select input rather than an element mask.
+    fn vector_elementwise(
+        &mut self,
+        decoded: &Decoded,
+        dest_scale: i32,
+        source_scale: i32,
+        uses_v0: bool,
+        op: impl Fn(&mut ElementContext, u64, u64, u64) -> u64,
+    ) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd;
+        let vs2 = decoded.rs2;
+        let vs1 = decoded.rs1;
+        let dest_eew = scaled(config.sew, dest_scale);
+        let source_eew = scaled(config.sew, source_scale);
+        let vector_operand = matches!(decoded.instruction.get_funct3(), OPIVV | OPMVV | OPFVV);
+
+        if dest_eew > self.elen || source_eew > self.elen || source_eew < 8
+            || !group_valid(vd, config.lmul + dest_scale)
+            || !group_valid(vs2, config.lmul + source_scale)
+            || (vector_operand && !group_valid(vs1, config.lmul)) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let mut context = ElementContext { sew: config.sew, vxrm: self.csrs.get(VXRM), v0: false, saturated: false };
+        let mut results = Vec::with_capacity(config.vl);
+        for index in config.vstart..config.vl {
+            if uses_v0 {
+                context.v0 = self.vector_registers.get_mask(0, index);
+            } else if !self.vector_active(decoded, index) {
+                results.push((index, None));
+                continue;
+            }
+
+            let a = self.vector_registers.get(vs2 as usize, index, source_eew);
+            let b = self.vector_operand(decoded, index, config.sew);
+            let d = self.vector_registers.get(vd as usize, index, dest_eew);
+            results.push((index, Some(op(&mut context, a, b, d) & ones(dest_eew))));
+        }
+
+        for (index, result) in results {
+            match result {
+                Some(value) => self.vector_registers.set(vd as usize, index, dest_eew, value),
+                None => self.vector_fill_masked(&config, vd as usize, index, dest_eew),
+            }
+        }
+        self.vector_fill_tail(&config, vd as usize, config.vl, dest_eew, config.lmul + dest_scale);
+
+        if context.saturated {
+            self.csrs.set(VXSAT, 1);
+        }
+        self.vector_finish();
+    }
+
+    fn vector_binary(&mut self, decoded: &Decoded, op: impl Fn(&mut ElementContext, u64, u64) -> u64) {
+        self.vector_elementwise(decoded, 0, 0, false, |context, a, b, _| op(context, a, b));
+    }
+
+    fn vector_float(&mut self, decoded: &Decoded, op: impl Fn(f64, f64) -> f64) {
+        if !matches!(self.vector_config(), Some(VectorConfig { sew: 32 | 64, .. })) {
+            return self.illegal_instruction(decoded);
+        }
+        self.vector_binary(decoded, |context, a, b| {
+            let result = op(float_from_bits(a, context.sew), float_from_bits(b, context.sew));
+            float_to_bits(result, context.sew)
+        });
+    }
+
+    fn vector_float_fused(&mut self, decoded: &Decoded, multiplies_vd: bool, negate_product: bool, negate_addend: bool) {
+        if !matches!(self.vector_config(), Some(VectorConfig { sew: 32 | 64, .. })) {
+            return self.illegal_instruction(decoded);
+        }
+        self.vector_elementwise(decoded, 0, 0, false, |context, a, b, d| {
+            if multiplies_vd {
+                float_fused(b, d, a, context.sew, negate_product, negate_addend)
+            } else {
+                float_fused(b, a, d, context.sew, negate_product, negate_addend)
+            }
+        });
+    }
+
+    /// Writes `op(vs2[i], operand, v0[i])` as a mask bit. Carry forms (`uses_v0`) read `v0`
+    /// as an input and are never masked.
+    fn vector_compare(&mut self, decoded: &Decoded, uses_v0: bool, op: impl Fn(&ElementContext, u64, u64) -> bool) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd as usize;
+        let vs2 = decoded.rs2;
+        let vs1 = decoded.rs1;
+        let vector_operand = matches!(decoded.instruction.get_funct3(), OPIVV | OPMVV | OPFVV);
+
+        if !group_valid(vs2, config.lmul) || (vector_operand && !group_valid(vs1, config.lmul)) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let mut context = ElementContext { sew: config.sew, vxrm: 0, v0: false, saturated: false };
+        let mut results = Vec::with_capacity(config.vl);
+        for index in config.vstart..config.vl {
+            context.v0 = uses_v0 && !decoded.instruction.get_vm() && self.vector_registers.get_mask(0, index);
+            if !uses_v0 && !self.vector_active(decoded, index) {
+                results.push((index, config.vma && self.vector_agnostic_ones || self.vector_registers.get_mask(vd, index)));
+                continue;
+            }
+
+            let a = self.vector_registers.get(vs2 as usize, index, config.sew);
+            let b = self.vector_operand(decoded, index, config.sew);
+            results.push((index, op(&context, a, b)));
+        }
+
+        for (index, value) in results {
+            self.vector_registers.set_mask(vd, index, value);
+        }
+        self.vector_fill_mask_tail(vd, config.vl);
+        self.vector_finish();
+    }
+
+    fn vector_float_compare(&mut self, decoded: &Decoded, op: impl Fn(f64, f64) -> bool) {
+        if !matches!(self.vector_config(), Some(VectorConfig { sew: 32 | 64, .. })) {
+            return self.illegal_instruction(decoded);
+        }
+        self.vector_compare(decoded, false, |context, a, b| {
+            op(float_from_bits(a, context.sew), float_from_bits(b, context.sew))
+        });
+    }
+
+    /// Folds the active elements of `vs2` into `vs1[0]` and writes the result to `vd[0]`.
+    fn vector_reduction(&mut self, decoded: &Decoded, dest_scale: i32, op: impl Fn(&ElementContext, u64, u64) -> u64) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd as usize;
+        let vs2 = decoded.rs2;
+        let dest_eew = scaled(config.sew, dest_scale);
+
+        if config.vstart != 0 || dest_eew > self.elen || !group_valid(vs2, config.lmul) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let context = ElementContext { sew: config.sew, vxrm: 0, v0: false, saturated: false };
+        let mut accumulator = self.vector_registers.get(decoded.rs1 as usize, 0, dest_eew);
+        for index in 0..config.vl {
+            if self.vector_active(decoded, index) {
+                let element = self.vector_registers.get(vs2 as usize, index, config.sew);
+                accumulator = op(&context, accumulator, element) & ones(dest_eew);
+            }
+        }
+
+        if config.vl > 0 {
+            self.vector_registers.set(vd, 0, dest_eew, accumulator);
+            self.vector_fill_tail(&config, vd, 1, dest_eew, 0);
+        }
+        self.vector_finish();
+    }
+
+    fn vector_float_reduction(&mut self, decoded: &Decoded, op: impl Fn(f64, f64) -> f64) {
+        if !matches!(self.vector_config(), Some(VectorConfig { sew: 32 | 64, .. })) {
+            return self.illegal_instruction(decoded);
+        }
+        self.vector_reduction(decoded, 0, |context, accumulator, element| {
+            let result = op(float_from_bits(accumulator, context.sew), float_from_bits(element, context.sew));
+            float_to_bits(result, context.sew)
+        });
+    }
+
+    fn vector_mask_logical(&mut self, decoded: &Decoded, op: impl Fn(bool, bool) -> bool) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd as usize;
+        let vs2 = decoded.rs2 as usize;
+        let vs1 = decoded.rs1 as usize;
+
+        for index in config.vstart..config.vl {
+            let value = op(self.vector_registers.get_mask(vs2, index), self.vector_registers.get_mask(vs1, index));
+            self.vector_registers.set_mask(vd, index, value);
+        }
+        self.vector_fill_mask_tail(vd, config.vl);
+        self.vector_finish();
+    }
+
+    fn vector_read_memory(&mut self, address: usize, eew: usize) -> u64 {
+        match eew {
+            64 => self.load(address, 4, false) as u64 | (self.load(address + 4, 4, false) as u64) << 32,
+            _ => self.load(address, eew / 8, false) as u64,
+        }
+    }
+
+    fn vector_write_memory(&mut self, address: usize, eew: usize, data: u64) {
+        match eew {
+            64 => {
+                self.store(address, 4, data as u32);
+                self.store(address + 4, 4, (data >> 32) as u32);
+            }
+            _ => self.store(address, eew / 8, data as u32),
+        }
+    }
+
+    /// Whole-register and mask loads/stores, which ignore `vtype` and move bytes as-is.
+    fn vector_load_store_whole(&mut self, decoded: &Decoded, store: bool, evl: usize, eew: usize) {
+        let vd = decoded.rd as usize;
+        let base = self.registers.get(decoded.rs1 as usize);
+        let vstart = self.csrs.get(VSTART) as usize;
+
+        for index in vstart..evl {
+            let address = base.wrapping_add((index * eew / 8) as u32) as usize;
+            let access = if store { Access::Write } else { Access::Read };
+            if !self.check_access(address, eew / 8, access) {
+                self.csrs.set(VSTART, index as u32);
+                return;
+            }
+
+            if store {
+                let data = self.vector_registers.get(vd, index, eew);
+                self.vector_write_memory(address, eew, data);
+            } else {
+                let data = self.vector_read_memory(address, eew);
+                self.vector_registers.set(vd, index, eew, data);
+            }
+        }
+        self.vector_finish();
+    }
+
+    /// Unit-stride, strided and indexed loads and stores, including their segment forms.
+    fn vector_load_store(&mut self, decoded: &Decoded, store: bool) {
+        let Some(eew) = decoded.instruction.get_vector_eew() else { return self.illegal_instruction(decoded) };
+        let nf = decoded.instruction.get_nf() as usize + 1;
+        let vd = decoded.rd;
+
+        match decoded.instruction._type() {
+            Ok(InstructionType::VLR) | Ok(InstructionType::VSR) => {
+                if !nf.is_power_of_two() || !group_valid(vd, nf.trailing_zeros() as i32) || (store && eew != 8) {
+                    return self.illegal_instruction(decoded);
+                }
+                let evl = nf * self.vector_registers.vlenb() * 8 / eew;
+                return self.vector_load_store_whole(decoded, store, evl, eew);
+            }
+            Ok(InstructionType::VLM) | Ok(InstructionType::VSM) => {
+                if eew != 8 {
+                    return self.illegal_instruction(decoded);
+                }
+                let vl = self.csrs.get(VL) as usize;
+                return self.vector_load_store_whole(decoded, store, vl.div_ceil(8), 8);
+            }
+            _ => ()
+        }
+
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let indexed = matches!(decoded.instruction.get_mop(), 0b01 | 0b11);
+        let data_eew = if indexed { config.sew } else { eew };
+        let index_emul = config.lmul + eew.trailing_zeros() as i32 - config.sew.trailing_zeros() as i32;
+        let data_emul = if indexed { config.lmul } else { index_emul };
+        let registers_per_field = 1usize << data_emul.max(0);
+
+        if data_eew > self.elen || !(-3..=3).contains(&index_emul) || !group_valid(vd, data_emul)
+            || nf * registers_per_field > 8 || vd as usize + nf * registers_per_field > 32
+            || (indexed && !group_valid(decoded.rs2, index_emul)) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let base = self.registers.get(decoded.rs1 as usize);
+        let stride = self.registers.get(decoded.rs2 as usize);
+        let fault_only_first = matches!(decoded.instruction._type(), Ok(InstructionType::VLEFF));
+
+        for index in config.vstart..config.vl {
+            for field in 0..nf {
+                let register = vd as usize + field * registers_per_field;
+                if !self.vector_active(decoded, index) {
+                    if !store {
+                        self.vector_fill_masked(&config, register, index, data_eew);
+                    }
+                    continue;
+                }
+
+                let offset = match decoded.instruction.get_mop() {
+                    0b00 => ((index * nf + field) * data_eew / 8) as u32,
+                    0b10 => stride.wrapping_mul(index as u32).wrapping_add((field * data_eew / 8) as u32),
+                    _ => (self.vector_registers.get(decoded.rs2 as usize, index, eew) as u32)
+                        .wrapping_add((field * data_eew / 8) as u32),
+                };
+                let address = base.wrapping_add(offset) as usize;
+
+                let access = if store { Access::Write } else { Access::Read };
+                if fault_only_first && index > 0 && !self.access_allowed(address, data_eew / 8, access) {
+                    self.csrs.set(VL, index as u32);
+                    return self.vector_finish();
+                }
+                if !self.check_access(address, data_eew / 8, access) {
+                    self.csrs.set(VSTART, index as u32);
+                    return;
+                }
+
+                if store {
+                    let data = self.vector_registers.get(register, index, data_eew);
+                    self.vector_write_memory(address, data_eew, data);
+                } else {
+                    let data = self.vector_read_memory(address, data_eew);
+                    self.vector_registers.set(register, index, data_eew, data);
+                }
+            }
+        }
+
+        if !store {
+            for field in 0..nf {
+                self.vector_fill_tail(&config, vd as usize + field * registers_per_field, config.vl, data_eew, data_emul);
+            }
+        }
+        self.vector_finish();
+    }
+
+    pub fn execute_vle(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, false) }
+    pub fn execute_vleff(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, false) }
+    pub fn execute_vlm(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, false) }
+    pub fn execute_vlr(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, false) }
+    pub fn execute_vlse(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, false) }
+    pub fn execute_vluxei(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, false) }
+    pub fn execute_vloxei(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, false) }
+    pub fn execute_vse(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, true) }
+    pub fn execute_vsm(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, true) }
+    pub fn execute_vsr(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, true) }
+    pub fn execute_vsse(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, true) }
+    pub fn execute_vsuxei(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, true) }
+    pub fn execute_vsoxei(&mut self, decoded: &Decoded) { self.vector_load_store(decoded, true) }
+
+    pub fn execute_vadd(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a.wrapping_add(b));
+    }
+
+    pub fn execute_vsub(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a.wrapping_sub(b));
+    }
+
+    pub fn execute_vrsub(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| b.wrapping_sub(a));
+    }
+
+    pub fn execute_vminu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a.min(b));
+    }
+
+    pub fn execute_vmin(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| sign_extend(a, c.sew).min(sign_extend(b, c.sew)) as u64);
+    }
+
+    pub fn execute_vmaxu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a.max(b));
+    }
+
+    pub fn execute_vmax(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| sign_extend(a, c.sew).max(sign_extend(b, c.sew)) as u64);
+    }
+
+    pub fn execute_vand(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a & b);
+    }
+
+    pub fn execute_vor(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a | b);
+    }
+
+    pub fn execute_vxor(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a ^ b);
+    }
+
+    pub fn execute_vsll(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| a << (b & (c.sew as u64 - 1)));
+    }
+
+    pub fn execute_vsrl(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| a >> (b & (c.sew as u64 - 1)));
+    }
+
+    pub fn execute_vsra(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| (sign_extend(a, c.sew) >> (b & (c.sew as u64 - 1))) as u64);
+    }
+
+    pub fn execute_vnsrl(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 1, false, |c, a, b, _| a >> (b & (2 * c.sew as u64 - 1)));
+    }
+
+    pub fn execute_vnsra(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 1, false, |c, a, b, _| {
+            (sign_extend(a, 2 * c.sew) >> (b & (2 * c.sew as u64 - 1))) as u64
+        });
+    }
+
+    pub fn execute_vadc(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, true, |c, a, b, _| a.wrapping_add(b).wrapping_add(c.v0 as u64));
+    }
+
+    pub fn execute_vsbc(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, true, |c, a, b, _| a.wrapping_sub(b).wrapping_sub(c.v0 as u64));
+    }
+
+    pub fn execute_vmadc(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, true, |c, a, b| {
+            (a as u128 + b as u128 + c.v0 as u128) >> c.sew != 0
+        });
+    }
+
+    pub fn execute_vmsbc(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, true, |c, a, b| (a as i128 - b as i128 - c.v0 as i128) < 0);
+    }
+
+    pub fn execute_vmerge(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, true, |c, a, b, _| if c.v0 { b } else { a });
+    }
+
+    pub fn execute_vmv_v(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, _, b| b);
+    }
+
+    pub fn execute_vmseq(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |_, a, b| a == b);
+    }
+
+    pub fn execute_vmsne(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |_, a, b| a != b);
+    }
+
+    pub fn execute_vmsltu(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |_, a, b| a < b);
+    }
+
+    pub fn execute_vmslt(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |c, a, b| sign_extend(a, c.sew) < sign_extend(b, c.sew));
+    }
+
+    pub fn execute_vmsleu(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |_, a, b| a <= b);
+    }
+
+    pub fn execute_vmsle(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |c, a, b| sign_extend(a, c.sew) <= sign_extend(b, c.sew));
+    }
+
+    pub fn execute_vmsgtu(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |_, a, b| a > b);
+    }
+
+    pub fn execute_vmsgt(&mut self, decoded: &Decoded) {
+        self.vector_compare(decoded, false, |c, a, b| sign_extend(a, c.sew) > sign_extend(b, c.sew));
+    }
+
+    pub fn execute_vsaddu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| saturate_unsigned(a as i128 + b as i128, c.sew, c));
+    }
+
+    pub fn execute_vsadd(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            saturate_signed(sign_extend(a, c.sew) as i128 + sign_extend(b, c.sew) as i128, c.sew, c)
+        });
+    }
+
+    pub fn execute_vssubu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| saturate_unsigned(a as i128 - b as i128, c.sew, c));
+    }
+
+    pub fn execute_vssub(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            saturate_signed(sign_extend(a, c.sew) as i128 - sign_extend(b, c.sew) as i128, c.sew, c)
+        });
+    }
+
+    pub fn execute_vaaddu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| roundoff(a as i128 + b as i128, 1, c.vxrm) as u64);
+    }
+
+    pub fn execute_vaadd(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            roundoff(sign_extend(a, c.sew) as i128 + sign_extend(b, c.sew) as i128, 1, c.vxrm) as u64
+        });
+    }
+
+    pub fn execute_vasubu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| roundoff(a as i128 - b as i128, 1, c.vxrm) as u64);
+    }
+
+    pub fn execute_vasub(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            roundoff(sign_extend(a, c.sew) as i128 - sign_extend(b, c.sew) as i128, 1, c.vxrm) as u64
+        });
+    }
+
+    pub fn execute_vsmul(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            let product = sign_extend(a, c.sew) as i128 * sign_extend(b, c.sew) as i128;
+            saturate_signed(roundoff(product, c.sew as u32 - 1, c.vxrm), c.sew, c)
+        });
+    }
+
+    pub fn execute_vssrl(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| roundoff(a as i128, (b & (c.sew as u64 - 1)) as u32, c.vxrm) as u64);
+    }
+
+    pub fn execute_vssra(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            roundoff(sign_extend(a, c.sew) as i128, (b & (c.sew as u64 - 1)) as u32, c.vxrm) as u64
+        });
+    }
+
+    pub fn execute_vnclipu(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 1, false, |c, a, b, _| {
+            let shifted = roundoff(a as i128, (b & (2 * c.sew as u64 - 1)) as u32, c.vxrm);
+            saturate_unsigned(shifted, c.sew, c)
+        });
+    }
+
+    pub fn execute_vnclip(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 1, false, |c, a, b, _| {
+            let shifted = roundoff(sign_extend(a, 2 * c.sew) as i128, (b & (2 * c.sew as u64 - 1)) as u32, c.vxrm);
+            saturate_signed(shifted, c.sew, c)
+        });
+    }
+
+    pub fn execute_vmul(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a.wrapping_mul(b));
+    }
+
+    pub fn execute_vmulh(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            ((sign_extend(a, c.sew) as i128 * sign_extend(b, c.sew) as i128) >> c.sew) as u64
+        });
+    }
+
+    pub fn execute_vmulhu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| ((a as u128 * b as u128) >> c.sew) as u64);
+    }
+
+    pub fn execute_vmulhsu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| ((sign_extend(a, c.sew) as i128 * b as i128) >> c.sew) as u64);
+    }
+
+    pub fn execute_vdivu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a.checked_div(b).unwrap_or(u64::MAX));
+    }
+
+    pub fn execute_vdiv(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            let (a, b) = (sign_extend(a, c.sew), sign_extend(b, c.sew));
+            if b == 0 { u64::MAX } else { a.wrapping_div(b) as u64 }
+        });
+    }
+
+    pub fn execute_vremu(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |_, a, b| a.checked_rem(b).unwrap_or(a));
+    }
+
+    pub fn execute_vrem(&mut self, decoded: &Decoded) {
+        self.vector_binary(decoded, |c, a, b| {
+            let (a, b) = (sign_extend(a, c.sew), sign_extend(b, c.sew));
+            if b == 0 { a as u64 } else { a.wrapping_rem(b) as u64 }
+        });
+    }
+
+    pub fn execute_vmacc(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, false, |_, a, b, d| b.wrapping_mul(a).wrapping_add(d));
+    }
+
+    pub fn execute_vnmsac(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, false, |_, a, b, d| d.wrapping_sub(b.wrapping_mul(a)));
+    }
+
+    pub fn execute_vmadd(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, false, |_, a, b, d| b.wrapping_mul(d).wrapping_add(a));
+    }
+
+    pub fn execute_vnmsub(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, false, |_, a, b, d| a.wrapping_sub(b.wrapping_mul(d)));
+    }
+
+    pub fn execute_vwaddu(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |_, a, b, _| a + b);
+    }
+
+    pub fn execute_vwadd(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |c, a, b, _| {
+            sign_extend(a, c.sew).wrapping_add(sign_extend(b, c.sew)) as u64
+        });
+    }
+
+    pub fn execute_vwsubu(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |_, a, b, _| a.wrapping_sub(b));
+    }
+
+    pub fn execute_vwsub(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |c, a, b, _| {
+            sign_extend(a, c.sew).wrapping_sub(sign_extend(b, c.sew)) as u64
+        });
+    }
+
+    pub fn execute_vwaddu_w(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 1, false, |_, a, b, _| a.wrapping_add(b));
+    }
+
+    pub fn execute_vwadd_w(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 1, false, |c, a, b, _| a.wrapping_add(sign_extend(b, c.sew) as u64));
+    }
+
+    pub fn execute_vwsubu_w(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 1, false, |_, a, b, _| a.wrapping_sub(b));
+    }
+
+    pub fn execute_vwsub_w(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 1, false, |c, a, b, _| a.wrapping_sub(sign_extend(b, c.sew) as u64));
+    }
+
+    pub fn execute_vwmulu(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |_, a, b, _| a.wrapping_mul(b));
+    }
+
+    pub fn execute_vwmulsu(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |c, a, b, _| (sign_extend(a, c.sew) as u64).wrapping_mul(b));
+    }
+
+    pub fn execute_vwmul(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |c, a, b, _| {
+            sign_extend(a, c.sew).wrapping_mul(sign_extend(b, c.sew)) as u64
+        });
+    }
+
+    pub fn execute_vwmaccu(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |_, a, b, d| d.wrapping_add(a.wrapping_mul(b)));
+    }
+
+    pub fn execute_vwmacc(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |c, a, b, d| {
+            d.wrapping_add(sign_extend(a, c.sew).wrapping_mul(sign_extend(b, c.sew)) as u64)
+        });
+    }
+
+    pub fn execute_vwmaccsu(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |c, a, b, d| {
+            d.wrapping_add((sign_extend(b, c.sew) as u64).wrapping_mul(a))
+        });
+    }
+
+    pub fn execute_vwmaccus(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 1, 0, false, |c, a, b, d| {
+            d.wrapping_add((sign_extend(a, c.sew) as u64).wrapping_mul(b))
+        });
+    }
+
+    fn vector_extend(&mut self, decoded: &Decoded, factor: i32, signed: bool) {
+        self.vector_elementwise(decoded, 0, -factor, false, |c, a, _, _| {
+            if signed { sign_extend(a, c.sew >> factor) as u64 } else { a }
+        });
+    }
+
+    pub fn execute_vzext_vf2(&mut self, decoded: &Decoded) { self.vector_extend(decoded, 1, false) }
+    pub fn execute_vzext_vf4(&mut self, decoded: &Decoded) { self.vector_extend(decoded, 2, false) }
+    pub fn execute_vzext_vf8(&mut self, decoded: &Decoded) { self.vector_extend(decoded, 3, false) }
+    pub fn execute_vsext_vf2(&mut self, decoded: &Decoded) { self.vector_extend(decoded, 1, true) }
+    pub fn execute_vsext_vf4(&mut self, decoded: &Decoded) { self.vector_extend(decoded, 2, true) }
+    pub fn execute_vsext_vf8(&mut self, decoded: &Decoded) { self.vector_extend(decoded, 3, true) }
+
+    pub fn execute_vredsum(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |_, acc, e| acc.wrapping_add(e));
+    }
+
+    pub fn execute_vredand(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |_, acc, e| acc & e);
+    }
+
+    pub fn execute_vredor(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |_, acc, e| acc | e);
+    }
+
+    pub fn execute_vredxor(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |_, acc, e| acc ^ e);
+    }
+
+    pub fn execute_vredminu(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |_, acc, e| acc.min(e));
+    }
+
+    pub fn execute_vredmin(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |c, acc, e| sign_extend(acc, c.sew).min(sign_extend(e, c.sew)) as u64);
+    }
+
+    pub fn execute_vredmaxu(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |_, acc, e| acc.max(e));
+    }
+
+    pub fn execute_vredmax(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 0, |c, acc, e| sign_extend(acc, c.sew).max(sign_extend(e, c.sew)) as u64);
+    }
+
+    pub fn execute_vwredsumu(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 1, |_, acc, e| acc.wrapping_add(e));
+    }
+
+    pub fn execute_vwredsum(&mut self, decoded: &Decoded) {
+        self.vector_reduction(decoded, 1, |c, acc, e| acc.wrapping_add(sign_extend(e, c.sew) as u64));
+    }
+
+    pub fn execute_vmand(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| a & b) }
+    pub fn execute_vmnand(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| !(a & b)) }
+    pub fn execute_vmandn(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| a & !b) }
+    pub fn execute_vmxor(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| a ^ b) }
+    pub fn execute_vmor(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| a | b) }
+    pub fn execute_vmnor(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| !(a | b)) }
+    pub fn execute_vmorn(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| a | !b) }
+    pub fn execute_vmxnor(&mut self, decoded: &Decoded) { self.vector_mask_logical(decoded, |a, b| !(a ^ b)) }
+
+    pub fn execute_vcpop_m(&mut self, decoded: &Decoded) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vs2 = decoded.rs2 as usize;
+
+        let count = (0..config.vl)
+            .filter(|&index| self.vector_active(decoded, index) && self.vector_registers.get_mask(vs2, index))
+            .count();
+
+        self.registers.set(decoded.rd as usize, count as u32);
+        self.vector_finish();
+    }
+
+    pub fn execute_vfirst_m(&mut self, decoded: &Decoded) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vs2 = decoded.rs2 as usize;
+
+        let first = (0..config.vl)
+            .find(|&index| self.vector_active(decoded, index) && self.vector_registers.get_mask(vs2, index))
+            .map_or(u32::MAX, |index| index as u32);
+
+        self.registers.set(decoded.rd as usize, first);
+        self.vector_finish();
+    }
+
+    /// `vmsbf`, `vmsif` and `vmsof`: set bits before, up to and including, or only at the first set bit.
+    fn vector_set_first(&mut self, decoded: &Decoded, before: bool, at: bool, after: bool) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd as usize;
+        let vs2 = decoded.rs2 as usize;
+        if config.vstart != 0 || vd == vs2 {
+            return self.illegal_instruction(decoded);
+        }
+
+        let mut found = false;
+        for index in 0..config.vl {
+            if !self.vector_active(decoded, index) {
+                continue;
+            }
+            let bit = self.vector_registers.get_mask(vs2, index);
+            let value = match (found, bit) {
+                (false, false) => before,
+                (false, true) => at,
+                (true, _) => after,
+            };
+            found |= bit;
+            self.vector_registers.set_mask(vd, index, value);
+        }
+        self.vector_fill_mask_tail(vd, config.vl);
+        self.vector_finish();
+    }
+
+    pub fn execute_vmsbf_m(&mut self, decoded: &Decoded) { self.vector_set_first(decoded, true, false, false) }
+    pub fn execute_vmsif_m(&mut self, decoded: &Decoded) { self.vector_set_first(decoded, true, true, false) }
+    pub fn execute_vmsof_m(&mut self, decoded: &Decoded) { self.vector_set_first(decoded, false, true, false) }
+
+    pub fn execute_viota_m(&mut self, decoded: &Decoded) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd as usize;
+        let vs2 = decoded.rs2 as usize;
+        if config.vstart != 0 || !group_valid(vd as u8, config.lmul) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let mut count = 0;
+        for index in 0..config.vl {
+            if !self.vector_active(decoded, index) {
+                self.vector_fill_masked(&config, vd, index, config.sew);
+                continue;
+            }
+            let bit = self.vector_registers.get_mask(vs2, index);
+            self.vector_registers.set(vd, index, config.sew, count & ones(config.sew));
+            count += bit as u64;
+        }
+        self.vector_fill_tail(&config, vd, config.vl, config.sew, config.lmul);
+        self.vector_finish();
+    }
+
+    pub fn execute_vid_v(&mut self, decoded: &Decoded) {
+        self.vector_elementwise(decoded, 0, 0, false, |_, _, _, _| 0);
+        let Some(config) = self.vector_config() else { return };
+        let vd = decoded.rd as usize;
+        for index in config.vstart..config.vl {
+            if self.vector_active(decoded, index) {
+                self.vector_registers.set(vd, index, config.sew, index as u64 & ones(config.sew));
+            }
+        }
+    }
+
+    pub fn execute_vmv_x_s(&mut self, decoded: &Decoded) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let element = self.vector_registers.get(decoded.rs2 as usize, 0, config.sew);
+
+        self.registers.set(decoded.rd as usize, sign_extend(element, config.sew) as u32);
+        self.vector_finish();
+    }
+
+    pub fn execute_vmv_s_x(&mut self, decoded: &Decoded) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd as usize;
+
+        if config.vstart < config.vl {
+            let value = self.vector_scalar(decoded, config.sew);
+            self.vector_registers.set(vd, 0, config.sew, value);
+            self.vector_fill_tail(&config, vd, 1, config.sew, 0);
+        }
+        self.vector_finish();
+    }
+
+    pub fn execute_vmvr(&mut self, decoded: &Decoded) {
+        let count = decoded.instruction.get_simm5() as usize + 1;
+        let vd = decoded.rd;
+        let vs2 = decoded.rs2;
+        let emul = count.trailing_zeros() as i32;
+        if !count.is_power_of_two() || !group_valid(vd, emul) || !group_valid(vs2, emul) {
+            return self.illegal_instruction(decoded);
+        }
+
+        for index in 0..count * self.vector_registers.vlenb() {
+            let byte = self.vector_registers.get(vs2 as usize, index, 8);
+            self.vector_registers.set(vd as usize, index, 8, byte);
+        }
+        self.vector_finish();
+    }
+
+    /// Shared body of the slide and gather permutations: `source(i)` names the `vs2` element
+    /// (or a replacement value) that lands in `vd[i]`, and `first` skips elements left untouched.
+    fn vector_permute(&mut self, decoded: &Decoded, first: usize, index_eew: Option<usize>,
+                      source: impl Fn(&VectorConfig, usize, u64) -> Result<usize, u64>) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd;
+        let vs2 = decoded.rs2;
+        let index_emul = index_eew.map_or(config.lmul, |eew| {
+            config.lmul + eew.trailing_zeros() as i32 - config.sew.trailing_zeros() as i32
+        });
+        if vd == vs2 || !group_valid(vd, config.lmul) || !group_valid(vs2, config.lmul)
+            || (index_eew.is_some() && !group_valid(decoded.rs1, index_emul)) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let scalar = self.registers.get(decoded.rs1 as usize) as u64;
+        let mut results = Vec::with_capacity(config.vl);
+        for index in config.vstart.max(first)..config.vl {
+            if !self.vector_active(decoded, index) {
+                results.push((index, None));
+                continue;
+            }
+
+            let selector = match index_eew {
+                Some(eew) => self.vector_registers.get(decoded.rs1 as usize, index, eew),
+                None => scalar,
+            };
+            let value = match source(&config, index, selector) {
+                Ok(element) => self.vector_registers.get(vs2 as usize, element, config.sew),
+                Err(value) => value & ones(config.sew),
+            };
+            results.push((index, Some(value)));
+        }
+
+        for (index, result) in results {
+            match result {
+                Some(value) => self.vector_registers.set(vd as usize, index, config.sew, value),
+                None => self.vector_fill_masked(&config, vd as usize, index, config.sew),
+            }
+        }
+        self.vector_fill_tail(&config, vd as usize, config.vl, config.sew, config.lmul);
+        self.vector_finish();
+    }
+
+    fn vector_offset(&self, decoded: &Decoded) -> usize {
+        match decoded.instruction.get_funct3() {
+            OPIVI => decoded.rs1 as usize,
+            _ => self.registers.get(decoded.rs1 as usize) as usize,
+        }
+    }
+
+    pub fn execute_vslideup(&mut self, decoded: &Decoded) {
+        let offset = self.vector_offset(decoded);
+        self.vector_permute(decoded, offset, None, |_, index, _| Ok(index - offset));
+    }
+
+    pub fn execute_vslidedown(&mut self, decoded: &Decoded) {
+        let offset = self.vector_offset(decoded);
+        self.vector_permute(decoded, 0, None, |config, index, _| {
+            match index.checked_add(offset) {
+                Some(source) if source < config.vlmax => Ok(source),
+                _ => Err(0),
+            }
+        });
+    }
+
+    pub fn execute_vslide1up(&mut self, decoded: &Decoded) {
+        self.vector_permute(decoded, 0, None, |_, index, scalar| {
+            if index == 0 { Err(scalar as u32 as i32 as u64) } else { Ok(index - 1) }
+        });
+    }
+
+    pub fn execute_vslide1down(&mut self, decoded: &Decoded) {
+        self.vector_permute(decoded, 0, None, |config, index, scalar| {
+            if index + 1 < config.vl { Ok(index + 1) } else { Err(scalar as u32 as i32 as u64) }
+        });
+    }
+
+    pub fn execute_vrgather(&mut self, decoded: &Decoded) {
+        let (index_eew, offset) = match decoded.instruction.get_funct3() {
+            OPIVV => (self.vector_config().map(|config| config.sew), 0),
+            _ => (None, self.vector_offset(decoded) as u64),
+        };
+        self.vector_permute(decoded, 0, index_eew, |config, _, selector| {
+            let selector = if index_eew.is_some() { selector } else { offset };
+            if selector < config.vlmax as u64 { Ok(selector as usize) } else { Err(0) }
+        });
+    }
+
+    pub fn execute_vrgatherei16(&mut self, decoded: &Decoded) {
+        self.vector_permute(decoded, 0, Some(16), |config, _, selector| {
+            if selector < config.vlmax as u64 { Ok(selector as usize) } else { Err(0) }
+        });
+    }
+
+    pub fn execute_vcompress(&mut self, decoded: &Decoded) {
+        let Some(config) = self.vector_config() else { return self.illegal_instruction(decoded) };
+        let vd = decoded.rd as usize;
+        let vs2 = decoded.rs2 as usize;
+        let vs1 = decoded.rs1 as usize;
+        if config.vstart != 0 || vd == vs2 || vd == vs1 || !group_valid(vd as u8, config.lmul) {
+            return self.illegal_instruction(decoded);
+        }
+
+        let selected: Vec<u64> = (0..config.vl)
+            .filter(|&index| self.vector_registers.get_mask(vs1, index))
+            .map(|index| self.vector_registers.get(vs2, index, config.sew))
+            .collect();
+        for (index, value) in selected.iter().enumerate() {
+            self.vector_registers.set(vd, index, config.sew, *value);
+        }
+        self.vector_fill_tail(&config, vd, selected.len(), config.sew, config.lmul);
+        self.vector_finish();
+    }
+
+    pub fn execute_vfadd(&mut self, decoded: &Decoded) { self.vector_float(decoded, |a, b| a + b) }
+    pub fn execute_vfsub(&mut self, decoded: &Decoded) { self.vector_float(decoded, |a, b| a - b) }
+    pub fn execute_vfmul(&mut self, decoded: &Decoded) { self.vector_float(decoded, |a, b| a * b) }
+    pub fn execute_vfdiv(&mut self, decoded: &Decoded) { self.vector_float(decoded, |a, b| a / b) }
+    pub fn execute_vfmin(&mut self, decoded: &Decoded) { self.vector_float(decoded, float_min) }
+    pub fn execute_vfmax(&mut self, decoded: &Decoded) { self.vector_float(decoded, float_max) }
+    pub fn execute_vfsqrt_v(&mut self, decoded: &Decoded) { self.vector_float(decoded, |a, _| a.sqrt()) }
+
+    fn vector_sign_inject(&mut self, decoded: &Decoded, op: impl Fn(u64, u64) -> u64) {
+        if !matches!(self.vector_config(), Some(VectorConfig { sew: 32 | 64, .. })) {
+            return self.illegal_instruction(decoded);
+        }
+        self.vector_binary(decoded, |c, a, b| {
+            let sign = 1 << (c.sew - 1);
+            a & !sign | op(a, b) & sign
+        });
+    }
+
+    pub fn execute_vfsgnj(&mut self, decoded: &Decoded) { self.vector_sign_inject(decoded, |_, b| b) }
+    pub fn execute_vfsgnjn(&mut self, decoded: &Decoded) { self.vector_sign_inject(decoded, |_, b| !b) }
+    pub fn execute_vfsgnjx(&mut self, decoded: &Decoded) { self.vector_sign_inject(decoded, |a, b| a ^ b) }
+
+    pub fn execute_vfmacc(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, false, false, false) }
+    pub fn execute_vfnmacc(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, false, true, true) }
+    pub fn execute_vfmsac(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, false, false, true) }
+    pub fn execute_vfnmsac(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, false, true, false) }
+    pub fn execute_vfmadd(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, true, false, false) }
+    pub fn execute_vfnmadd(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, true, true, true) }
+    pub fn execute_vfmsub(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, true, false, true) }
+    pub fn execute_vfnmsub(&mut self, decoded: &Decoded) { self.vector_float_fused(decoded, true, true, false) }
+
+    pub fn execute_vmfeq(&mut self, decoded: &Decoded) { self.vector_float_compare(decoded, |a, b| a == b) }
+    pub fn execute_vmfne(&mut self, decoded: &Decoded) { self.vector_float_compare(decoded, |a, b| a != b) }
+    pub fn execute_vmflt(&mut self, decoded: &Decoded) { self.vector_float_compare(decoded, |a, b| a < b) }
+    pub fn execute_vmfle(&mut self, decoded: &Decoded) { self.vector_float_compare(decoded, |a, b| a <= b) }
+
+    pub fn execute_vfredusum(&mut self, decoded: &Decoded) { self.vector_float_reduction(decoded, |a, b| a + b) }
+    pub fn execute_vfredosum(&mut self, decoded: &Decoded) { self.vector_float_reduction(decoded, |a, b| a + b) }
+    pub fn execute_vfredmin(&mut self, decoded: &Decoded) { self.vector_float_reduction(decoded, float_min) }
+    pub fn execute_vfredmax(&mut self, decoded: &Decoded) { self.vector_float_reduction(decoded, float_max) }
+}
@@ -0,0 +1,255 @@
+const AES_SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const AES_INVERSE_SBOX: [u8; 256] = [
+    0x52, 0x09, 0x6a, 0xd5, 0x30, 0x36, 0xa5, 0x38, 0xbf, 0x40, 0xa3, 0x9e, 0x81, 0xf3, 0xd7, 0xfb,
+    0x7c, 0xe3, 0x39, 0x82, 0x9b, 0x2f, 0xff, 0x87, 0x34, 0x8e, 0x43, 0x44, 0xc4, 0xde, 0xe9, 0xcb,
+    0x54, 0x7b, 0x94, 0x32, 0xa6, 0xc2, 0x23, 0x3d, 0xee, 0x4c, 0x95, 0x0b, 0x42, 0xfa, 0xc3, 0x4e,
+    0x08, 0x2e, 0xa1, 0x66, 0x28, 0xd9, 0x24, 0xb2, 0x76, 0x5b, 0xa2, 0x49, 0x6d, 0x8b, 0xd1, 0x25,
+    0x72, 0xf8, 0xf6, 0x64, 0x86, 0x68, 0x98, 0x16, 0xd4, 0xa4, 0x5c, 0xcc, 0x5d, 0x65, 0xb6, 0x92,
+    0x6c, 0x70, 0x48, 0x50, 0xfd, 0xed, 0xb9, 0xda, 0x5e, 0x15, 0x46, 0x57, 0xa7, 0x8d, 0x9d, 0x84,
+    0x90, 0xd8, 0xab, 0x00, 0x8c, 0xbc, 0xd3, 0x0a, 0xf7, 0xe4, 0x58, 0x05, 0xb8, 0xb3, 0x45, 0x06,
+    0xd0, 0x2c, 0x1e, 0x8f, 0xca, 0x3f, 0x0f, 0x02, 0xc1, 0xaf, 0xbd, 0x03, 0x01, 0x13, 0x8a, 0x6b,
+    0x3a, 0x91, 0x11, 0x41, 0x4f, 0x67, 0xdc, 0xea, 0x97, 0xf2, 0xcf, 0xce, 0xf0, 0xb4, 0xe6, 0x73,
+    0x96, 0xac, 0x74, 0x22, 0xe7, 0xad, 0x35, 0x85, 0xe2, 0xf9, 0x37, 0xe8, 0x1c, 0x75, 0xdf, 0x6e,
+    0x47, 0xf1, 0x1a, 0x71, 0x1d, 0x29, 0xc5, 0x89, 0x6f, 0xb7, 0x62, 0x0e, 0xaa, 0x18, 0xbe, 0x1b,
+    0xfc, 0x56, 0x3e, 0x4b, 0xc6, 0xd2, 0x79, 0x20, 0x9a, 0xdb, 0xc0, 0xfe, 0x78, 0xcd, 0x5a, 0xf4,
+    0x1f, 0xdd, 0xa8, 0x33, 0x88, 0x07, 0xc7, 0x31, 0xb1, 0x12, 0x10, 0x59, 0x27, 0x80, 0xec, 0x5f,
+    0x60, 0x51, 0x7f, 0xa9, 0x19, 0xb5, 0x4a, 0x0d, 0x2d, 0xe5, 0x7a, 0x9f, 0x93, 0xc9, 0x9c, 0xef,
+    0xa0, 0xe0, 0x3b, 0x4d, 0xae, 0x2a, 0xf5, 0xb0, 0xc8, 0xeb, 0xbb, 0x3c, 0x83, 0x53, 0x99, 0x61,
+    0x17, 0x2b, 0x04, 0x7e, 0xba, 0x77, 0xd6, 0x26, 0xe1, 0x69, 0x14, 0x63, 0x55, 0x21, 0x0c, 0x7d,
+];
+
+const SM4_SBOX: [u8; 256] = [
+    0xd6, 0x90, 0xe9, 0xfe, 0xcc, 0xe1, 0x3d, 0xb7, 0x16, 0xb6, 0x14, 0xc2, 0x28, 0xfb, 0x2c, 0x05,
+    0x2b, 0x67, 0x9a, 0x76, 0x2a, 0xbe, 0x04, 0xc3, 0xaa, 0x44, 0x13, 0x26, 0x49, 0x86, 0x06, 0x99,
+    0x9c, 0x42, 0x50, 0xf4, 0x91, 0xef, 0x98, 0x7a, 0x33, 0x54, 0x0b, 0x43, 0xed, 0xcf, 0xac, 0x62,
+    0xe4, 0xb3, 0x1c, 0xa9, 0xc9, 0x08, 0xe8, 0x95, 0x80, 0xdf, 0x94, 0xfa, 0x75, 0x8f, 0x3f, 0xa6,
+    0x47, 0x07, 0xa7, 0xfc, 0xf3, 0x73, 0x17, 0xba, 0x83, 0x59, 0x3c, 0x19, 0xe6, 0x85, 0x4f, 0xa8,
+    0x68, 0x6b, 0x81, 0xb2, 0x71, 0x64, 0xda, 0x8b, 0xf8, 0xeb, 0x0f, 0x4b, 0x70, 0x56, 0x9d, 0x35,
+    0x1e, 0x24, 0x0e, 0x5e, 0x63, 0x58, 0xd1, 0xa2, 0x25, 0x22, 0x7c, 0x3b, 0x01, 0x21, 0x78, 0x87,
+    0xd4, 0x00, 0x46, 0x57, 0x9f, 0xd3, 0x27, 0x52, 0x4c, 0x36, 0x02, 0xe7, 0xa0, 0xc4, 0xc8, 0x9e,
+    0xea, 0xbf, 0x8a, 0xd2, 0x40, 0xc7, 0x38, 0xb5, 0xa3, 0xf7, 0xf2, 0xce, 0xf9, 0x61, 0x15, 0xa1,
+    0xe0, 0xae, 0x5d, 0xa4, 0x9b, 0x34, 0x1a, 0x55, 0xad, 0x93, 0x32, 0x30, 0xf5, 0x8c, 0xb1, 0xe3,
+    0x1d, 0xf6, 0xe2, 0x2e, 0x82, 0x66, 0xca, 0x60, 0xc0, 0x29, 0x23, 0xab, 0x0d, 0x53, 0x4e, 0x6f,
+    0xd5, 0xdb, 0x37, 0x45, 0xde, 0xfd, 0x8e, 0x2f, 0x03, 0xff, 0x6a, 0x72, 0x6d, 0x6c, 0x5b, 0x51,
+    0x8d, 0x1b, 0xaf, 0x92, 0xbb, 0xdd, 0xbc, 0x7f, 0x11, 0xd9, 0x5c, 0x41, 0x1f, 0x10, 0x5a, 0xd8,
+    0x0a, 0xc1, 0x31, 0x88, 0xa5, 0xcd, 0x7b, 0xbd, 0x2d, 0x74, 0xd0, 0x12, 0xb8, 0xe5, 0xb4, 0xb0,
+    0x89, 0x69, 0x97, 0x4a, 0x0c, 0x96, 0x77, 0x7e, 0x65, 0xb9, 0xf1, 0x09, 0xc5, 0x6e, 0xc6, 0x84,
+    0x18, 0xf0, 0x7d, 0xec, 0x3a, 0xdc, 0x4d, 0x20, 0x79, 0xee, 0x5f, 0x3e, 0xd7, 0xcb, 0x39, 0x48,
+];
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 { product ^= a; }
+        a = if a & 0x80 != 0 { a << 1 ^ 0x1b } else { a << 1 };
+        b >>= 1;
+    }
+    product
+}
+
+fn select_byte(value: u32, bs: u8) -> u8 {
+    (value >> (bs as u32 * 8)) as u8
+}
+
+pub fn aes32esi(rs1: u32, rs2: u32, bs: u8) -> u32 {
+    let so = AES_SBOX[select_byte(rs2, bs) as usize] as u32;
+    rs1 ^ so.rotate_left(bs as u32 * 8)
+}
+
+pub fn aes32esmi(rs1: u32, rs2: u32, bs: u8) -> u32 {
+    let so = AES_SBOX[select_byte(rs2, bs) as usize];
+    let mixed = (gf_mul(so, 3) as u32) << 24 | (so as u32) << 16 | (so as u32) << 8 | gf_mul(so, 2) as u32;
+    rs1 ^ mixed.rotate_left(bs as u32 * 8)
+}
+
+pub fn aes32dsi(rs1: u32, rs2: u32, bs: u8) -> u32 {
+    let so = AES_INVERSE_SBOX[select_byte(rs2, bs) as usize] as u32;
+    rs1 ^ so.rotate_left(bs as u32 * 8)
+}
+
+pub fn aes32dsmi(rs1: u32, rs2: u32, bs: u8) -> u32 {
+    let so = AES_INVERSE_SBOX[select_byte(rs2, bs) as usize];
+    let mixed = (gf_mul(so, 0xb) as u32) << 24 | (gf_mul(so, 0xd) as u32) << 16 | (gf_mul(so, 0x9) as u32) << 8 | gf_mul(so, 0xe) as u32;
+    rs1 ^ mixed.rotate_left(bs as u32 * 8)
+}
+
+pub fn sha256sig0(x: u32) -> u32 {
+    x.rotate_right(7) ^ x.rotate_right(18) ^ x >> 3
+}
+
+pub fn sha256sig1(x: u32) -> u32 {
+    x.rotate_right(17) ^ x.rotate_right(19) ^ x >> 10
+}
+
+pub fn sha256sum0(x: u32) -> u32 {
+    x.rotate_right(2) ^ x.rotate_right(13) ^ x.rotate_right(22)
+}
+
+pub fn sha256sum1(x: u32) -> u32 {
+    x.rotate_right(6) ^ x.rotate_right(11) ^ x.rotate_right(25)
+}
+
+pub fn sha512sig0h(rs1: u32, rs2: u32) -> u32 {
+    rs1 >> 1 ^ rs1 >> 7 ^ rs1 >> 8 ^ rs2 << 31 ^ rs2 << 24
+}
+
+pub fn sha512sig0l(rs1: u32, rs2: u32) -> u32 {
+    rs1 >> 1 ^ rs1 >> 7 ^ rs1 >> 8 ^ rs2 << 31 ^ rs2 << 25 ^ rs2 << 24
+}
+
+pub fn sha512sig1h(rs1: u32, rs2: u32) -> u32 {
+    rs1 << 3 ^ rs1 >> 6 ^ rs1 >> 19 ^ rs2 >> 29 ^ rs2 << 13
+}
+
+pub fn sha512sig1l(rs1: u32, rs2: u32) -> u32 {
+    rs1 << 3 ^ rs1 >> 6 ^ rs1 >> 19 ^ rs2 >> 29 ^ rs2 << 26 ^ rs2 << 13
+}
+
+pub fn sha512sum0r(rs1: u32, rs2: u32) -> u32 {
+    rs1 << 25 ^ rs1 << 30 ^ rs1 >> 28 ^ rs2 >> 7 ^ rs2 >> 2 ^ rs2 << 4
+}
+
+pub fn sha512sum1r(rs1: u32, rs2: u32) -> u32 {
+    rs1 << 23 ^ rs1 >> 14 ^ rs1 >> 18 ^ rs2 >> 9 ^ rs2 << 18 ^ rs2 << 14
+}
+
+pub fn sm4ed(rs1: u32, rs2: u32, bs: u8) -> u32 {
+    let x = SM4_SBOX[select_byte(rs2, bs) as usize] as u32;
+    let x = x ^ x << 8 ^ x << 2 ^ x << 18 ^ (x & 0x3f) << 26 ^ (x & 0xc0) << 10;
+    rs1 ^ x.rotate_left(bs as u32 * 8)
+}
+
+pub fn sm4ks(rs1: u32, rs2: u32, bs: u8) -> u32 {
+    let x = SM4_SBOX[select_byte(rs2, bs) as usize] as u32;
+    let x = x ^ (x & 0x07) << 29 ^ (x & 0xfe) << 7 ^ (x & 0x01) << 23 ^ (x & 0xf8) << 13;
+    rs1 ^ x.rotate_left(bs as u32 * 8)
+}
+
+pub fn sm3p0(x: u32) -> u32 {
+    x ^ x.rotate_left(9) ^ x.rotate_left(17)
+}
+
+pub fn sm3p1(x: u32) -> u32 {
+    x ^ x.rotate_left(15) ^ x.rotate_left(23)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::*;
+
+    fn words(hex: &str) -> Vec<u32> {
+        (0..hex.len() / 8)
+            .map(|i| u32::from_str_radix(&hex[i * 8..i * 8 + 8], 16).unwrap().swap_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_aes_sbox() {
+        assert_eq!(aes32esi(0, 0x53, 0), 0xed);
+        assert_eq!(aes32esi(0, 0x5300, 1), 0xed00);
+        assert_eq!(aes32dsi(0, 0xed000000, 3), 0x53000000);
+        assert_eq!(aes32dsi(0xFF, 0xed, 0), 0x53 ^ 0xFF);
+    }
+
+    #[test]
+    fn test_aes_encrypt_round() {
+        // FIPS-197 Appendix B, round 1 state and round key, expecting the start of round 2
+        let state = words("193de3bea0f4e22b9ac68d2ae9f84808");
+        let round_key = words("a0fafe1788542cb123a339392a6c7605");
+        let expected = words("a49c7ff2689f352b6b5bea43026a5049");
+
+        for column in 0..4 {
+            let mut t = round_key[column];
+            for bs in 0..4 {
+                t = aes32esmi(t, state[(column + bs) % 4], bs as u8);
+            }
+            assert_eq!(t, expected[column]);
+        }
+    }
+
+    #[test]
+    fn test_aes_decrypt_mix_column() {
+        // InvMixColumns(8e 4d a1 bc) = db 13 53 45, fed through the forward S-box first
+        let column = words("19e33265")[0];
+        let mut t = 0;
+        for bs in 0..4 {
+            t = aes32dsmi(t, column, bs);
+        }
+        assert_eq!(t, words("db135345")[0]);
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(sha256sig0(0x61626380), 0x940e90ef);
+        assert_eq!(sha256sig1(0x00000018), 0x000f0000);
+        assert_eq!(sha256sum0(0x6a09e667), 0xce20b47e);
+        assert_eq!(sha256sum1(0x510e527f), 0x3587272b);
+    }
+
+    #[test]
+    fn test_sha512_halves() {
+        fn sig0(x: u64) -> u64 { x.rotate_right(1) ^ x.rotate_right(8) ^ x >> 7 }
+        fn sig1(x: u64) -> u64 { x.rotate_right(19) ^ x.rotate_right(61) ^ x >> 6 }
+        fn sum0(x: u64) -> u64 { x.rotate_right(28) ^ x.rotate_right(34) ^ x.rotate_right(39) }
+        fn sum1(x: u64) -> u64 { x.rotate_right(14) ^ x.rotate_right(18) ^ x.rotate_right(41) }
+
+        for x in [0x6a09e667f3bcc908u64, 0xbb67ae8584caa73b, 0x8000000000000001, 0x0123456789abcdef] {
+            let (high, low) = ((x >> 32) as u32, x as u32);
+            assert_eq!(sha512sig0h(high, low), (sig0(x) >> 32) as u32);
+            assert_eq!(sha512sig0l(low, high), sig0(x) as u32);
+            assert_eq!(sha512sig1h(high, low), (sig1(x) >> 32) as u32);
+            assert_eq!(sha512sig1l(low, high), sig1(x) as u32);
+            assert_eq!(sha512sum0r(high, low), (sum0(x) >> 32) as u32);
+            assert_eq!(sha512sum0r(low, high), sum0(x) as u32);
+            assert_eq!(sha512sum1r(high, low), (sum1(x) >> 32) as u32);
+            assert_eq!(sha512sum1r(low, high), sum1(x) as u32);
+        }
+    }
+
+    #[test]
+    fn test_sm4_encrypt() {
+        // GB/T 32907-2016 example 1
+        let fk: [u32; 4] = [0xa3b1bac6, 0x56aa3350, 0x677d9197, 0xb27022dc];
+        let key: Vec<u32> = words("0123456789abcdeffedcba9876543210");
+
+        let mut k: Vec<u32> = (0..4).map(|i| key[i] ^ fk[i].swap_bytes()).collect();
+        for i in 0..32 {
+            let ck = (0..4).fold(0u32, |ck, j| ck | (((4 * i + j) * 7 % 256) as u32) << (j * 8));
+            let x = k[i + 1] ^ k[i + 2] ^ k[i + 3] ^ ck;
+            k.push((0..4).fold(k[i], |t, bs| sm4ks(t, x, bs)));
+        }
+        assert_eq!(k[4], 0xf12186f9u32.swap_bytes());
+
+        let mut x = key.clone();
+        for i in 0..32 {
+            let y = x[i + 1] ^ x[i + 2] ^ x[i + 3] ^ k[i + 4];
+            x.push((0..4).fold(x[i], |t, bs| sm4ed(t, y, bs)));
+        }
+        let ciphertext: Vec<u32> = x[32..36].iter().rev().copied().collect();
+        assert_eq!(ciphertext, words("681edf34d206965e86b3e94f536e4246"));
+    }
+
+    #[test]
+    fn test_sm3() {
+        assert_eq!(sm3p0(0x00000001), 0x00020201);
+        assert_eq!(sm3p1(0x00000001), 0x00808001);
+    }
+}
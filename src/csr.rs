@@ -0,0 +1,156 @@
+use crate::error::Error;
+use crate::snapshot::{Reader, Writer};
+
+pub const VSTART: u16 = 0x008;
+pub const VXSAT: u16 = 0x009;
+pub const VXRM: u16 = 0x00A;
+pub const VCSR: u16 = 0x00F;
+pub const SCOUNTEREN: u16 = 0x106;
+pub const SENVCFG: u16 = 0x10A;
+pub const MSTATUS: u16 = 0x300;
+pub const MISA: u16 = 0x301;
+pub const MIE: u16 = 0x304;
+pub const MTVEC: u16 = 0x305;
+pub const MCOUNTEREN: u16 = 0x306;
+pub const MENVCFG: u16 = 0x30A;
+pub const MCOUNTINHIBIT: u16 = 0x320;
+pub const MHPMEVENT3: u16 = 0x323;
+pub const MHPMEVENT31: u16 = 0x33F;
+pub const PMPCFG0: u16 = 0x3A0;
+pub const PMPCFG15: u16 = 0x3AF;
+pub const PMPADDR0: u16 = 0x3B0;
+pub const PMPADDR63: u16 = 0x3EF;
+pub const MEPC: u16 = 0x341;
+pub const MCAUSE: u16 = 0x342;
+pub const MTVAL: u16 = 0x343;
+pub const MIP: u16 = 0x344;
+pub const MCYCLE: u16 = 0xB00;
+pub const MHPMCOUNTER31: u16 = 0xB1F;
+pub const MCYCLEH: u16 = 0xB80;
+pub const MHPMCOUNTER31H: u16 = 0xB9F;
+pub const CYCLE: u16 = 0xC00;
+pub const TIME: u16 = 0xC01;
+pub const HPMCOUNTER31: u16 = 0xC1F;
+pub const VL: u16 = 0xC20;
+pub const VTYPE: u16 = 0xC21;
+pub const VLENB: u16 = 0xC22;
+pub const CYCLEH: u16 = 0xC80;
+pub const TIMEH: u16 = 0xC81;
+pub const HPMCOUNTER31H: u16 = 0xC9F;
+pub const MHARTID: u16 = 0xF14;
+
+/// The CSRs a debugger shows, in the order it shows them.
+pub const DUMPED: [u16; 18] = [
+    MSTATUS, MISA, MIE, MIP, MTVEC, 0x340, MEPC, MCAUSE, MTVAL, MCOUNTEREN, MENVCFG, MCYCLE, 0xB02,
+    VSTART, VCSR, VL, VTYPE, MHARTID,
+];
+
+pub const MSTATUS_MIE: u32 = 1 << 3;
+pub const MSTATUS_MPIE: u32 = 1 << 7;
+pub const MSTATUS_MPP_SHIFT: u32 = 11;
+pub const MSTATUS_MPRV: u32 = 1 << 17;
+
+pub const MCAUSE_INTERRUPT: u32 = 1 << 31;
+pub const MIP_MSIP: u32 = 1 << 3;
+pub const MIP_MTIP: u32 = 1 << 7;
+
+pub const ENVCFG_CBIE_SHIFT: u32 = 4;
+pub const ENVCFG_CBCFE: u32 = 1 << 6;
+pub const ENVCFG_CBZE: u32 = 1 << 7;
+
+/// The name Spike's commit log gives `csr`.
+pub fn csr_name(csr: u16) -> String {
+    let name = match csr {
+        0x001 => "fflags",
+        0x002 => "frm",
+        0x003 => "fcsr",
+        VSTART => "vstart",
+        VXSAT => "vxsat",
+        VXRM => "vxrm",
+        VCSR => "vcsr",
+        SCOUNTEREN => "scounteren",
+        SENVCFG => "senvcfg",
+        MSTATUS => "mstatus",
+        MISA => "misa",
+        MIE => "mie",
+        MTVEC => "mtvec",
+        MCOUNTEREN => "mcounteren",
+        MENVCFG => "menvcfg",
+        MCOUNTINHIBIT => "mcountinhibit",
+        MHPMEVENT3..=MHPMEVENT31 => return format!("mhpmevent{}", csr - MHPMEVENT3 + 3),
+        0x340 => "mscratch",
+        MEPC => "mepc",
+        MCAUSE => "mcause",
+        MTVAL => "mtval",
+        MIP => "mip",
+        PMPCFG0..=PMPCFG15 => return format!("pmpcfg{}", csr - PMPCFG0),
+        PMPADDR0..=PMPADDR63 => return format!("pmpaddr{}", csr - PMPADDR0),
+        MCYCLE => "mcycle",
+        0xB02 => "minstret",
+        0xB03..=MHPMCOUNTER31 => return format!("mhpmcounter{}", csr - MCYCLE),
+        MCYCLEH => "mcycleh",
+        0xB82 => "minstreth",
+        0xB83..=MHPMCOUNTER31H => return format!("mhpmcounter{}h", csr - MCYCLEH),
+        VL => "vl",
+        VTYPE => "vtype",
+        VLENB => "vlenb",
+        MHARTID => "mhartid",
+        _ => "unknown",
+    };
+    name.to_string()
+}
+
+pub struct Csrs {
+    csrs: Vec<u32>,
+}
+
+impl Csrs {
+    pub fn new() -> Self {
+        Self { csrs: vec![0; 4096] }
+    }
+
+    pub fn get(&self, csr: u16) -> u32 {
+        self.csrs[csr as usize & 0xFFF]
+    }
+
+    pub fn set(&mut self, csr: u16, data: u32) {
+        self.csrs[csr as usize & 0xFFF] = data
+    }
+
+    /// Saves the CSRs that are not zero, by address.
+    pub fn save(&self, snapshot: &mut Writer) {
+        let set: Vec<(usize, &u32)> = self.csrs.iter().enumerate().filter(|(_, data)| **data != 0).collect();
+        snapshot.write_u16(set.len() as u16);
+        for (csr, data) in set {
+            snapshot.write_u16(csr as u16);
+            snapshot.write_u32(*data);
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        self.csrs.fill(0);
+        for _ in 0..snapshot.read_u16()? {
+            let csr = snapshot.read_u16()?;
+            self.set(csr, snapshot.read_u32()?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::csr::{Csrs, VL, VTYPE};
+
+    #[test]
+    fn csrs_set_correctly() {
+        let mut csrs = Csrs::new();
+
+        assert_eq!(csrs.get(VL), 0);
+        csrs.set(VL, 4);
+        csrs.set(VTYPE, 0xD0);
+
+        assert_eq!(csrs.get(VL), 4);
+        assert_eq!(csrs.get(VTYPE), 0xD0);
+        assert_eq!(csrs.get(VL | 0x1000), 4);
+    }
+}
@@ -0,0 +1,656 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::cpu::hooks::{Action, Hooks};
+use crate::elf::Elf;
+use crate::instruction::{Instruction, InstructionType};
+use crate::machine::Machine;
+use crate::trace::MemoryAccess;
+
+const HELP: &str = "\
+step [n]                     run n instructions, 1 by default
+continue                     run until a breakpoint or watchpoint is hit or every hart stops
+until [addr]                 run until addr, or until the PC moves past the current one
+                             without entering calls
+finish                       run until the current function returns
+reverse-step [n]             go back n instructions
+reverse-continue             go back to the last breakpoint or change of a watched word
+break <addr> [if <reg> <op> <value>]
+                             stop before addr, if the register compares true: == != < <= > >=
+watch <addr> [r|w|rw]        stop after the word at addr is read or written, written by default
+delete [n]                   delete breakpoint or watchpoint n, or all of them
+x/<n><fmt> <addr>            examine n units: x hex, d signed or u unsigned words, h halfwords,
+                             b bytes, c characters, i instructions
+set reg <reg|pc> <value>     set a register of the current hart
+set mem[/b|/h|/w] <addr> <value>
+disas [addr] [n]             disassemble n instructions, 8 from the PC by default
+info regs|csrs|vregs|memory|breakpoints
+save [file]                  save a snapshot
+quit
+
+Numbers are decimal or 0x-prefixed hex. Addresses and values may also be symbols, registers or
+pc, read from the hart that runs next. An empty line repeats the last command.";
+
+/// The most units `x` and `disas` show at once.
+const EXAMINE_LIMIT: usize = 4096;
+
+/// ABI names of the integer registers, by number.
+const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// Looks up a register by number, such as `x10`, or ABI name, such as `a0`.
+fn register(name: &str) -> Option<usize> {
+    if name == "fp" {
+        return Some(8);
+    }
+    let numbered = name.strip_prefix('x').and_then(|number| number.parse().ok()).filter(|&register| register < 32);
+    numbered.or_else(|| ABI_NAMES.iter().position(|&abi_name| abi_name == name))
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    if let Some(negative) = text.strip_prefix('-') {
+        return negative.parse::<u32>().ok().map(u32::wrapping_neg);
+    }
+    text.parse().ok()
+}
+
+/// The hart commands act on: the one that runs next, or hart 0 once every hart has stopped.
+fn current_hart(machine: &Machine) -> usize {
+    machine.get_next_hart().unwrap_or(0)
+}
+
+/// Shows the instruction each hart runs next.
+fn show_position(machine: &Machine) {
+    for hart in 0..machine.harts() {
+        let Ok(pc) = machine.get_pc(hart) else { continue };
+        let instruction = machine.read32(pc).map(|word| Instruction::from_u32(word).to_string()).unwrap_or_default();
+        if machine.harts() > 1 {
+            print!("{} ", hart);
+        }
+        println!("{:08x}    {}", pc, instruction);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    const ALL: [Comparison; 6] = [Comparison::Equal, Comparison::NotEqual, Comparison::Less,
+        Comparison::LessOrEqual, Comparison::Greater, Comparison::GreaterOrEqual];
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+
+    /// Compares register values as unsigned numbers.
+    fn holds(self, left: u32, right: u32) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+/// What a conditional breakpoint requires of the hart that reaches it.
+struct Condition {
+    register: usize,
+    comparison: Comparison,
+    value: u32,
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "x{} {} {:#x}", self.register, self.comparison.symbol(), self.value)
+    }
+}
+
+struct Breakpoint {
+    number: usize,
+    address: usize,
+    condition: Option<Condition>,
+}
+
+struct Watch {
+    number: usize,
+    address: usize,
+    read: bool,
+    write: bool,
+}
+
+/// The watchpoints and the first of them hit since the debugger last ran the machine,
+/// shared with the memory hooks that find the hits.
+#[derive(Default)]
+struct Watches {
+    watches: Vec<Watch>,
+    hit: Option<(usize, usize, MemoryAccess)>,
+}
+
+impl Watches {
+    fn record(&mut self, hart: usize, access: &MemoryAccess) {
+        let hit = self.watches.iter().find(|watch| {
+            let kind = if access.write { watch.write } else { watch.read };
+            kind && watch.address < access.address + access.size && access.address < watch.address + 4
+        });
+        if let (Some(watch), None) = (hit, self.hit) {
+            self.hit = Some((watch.number, hart, *access));
+        }
+    }
+}
+
+/// How an instruction changes the depth of calls: `jal` and `jalr` linking `ra` call, and
+/// `jalr x0, 0(ra)` returns.
+fn call_depth_change(instruction: &Instruction) -> i64 {
+    let (rd, rs1) = (instruction.get_rd(), instruction.get_rs1());
+    match instruction._type() {
+        Ok(InstructionType::JAL | InstructionType::JALR) if rd == 1 => 1,
+        Ok(InstructionType::JALR) if rd == 0 && rs1 == 1 && instruction.get_imm_i() == 0 => -1,
+        _ => 0,
+    }
+}
+
+/// A line-oriented debugger driving a `Machine`: stepping, breakpoints and watchpoints, reverse
+/// execution, and commands that examine and change registers and memory. Reverse execution
+/// needs `Machine::record_history`.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watches: Rc<RefCell<Watches>>,
+    /// How many calls deep each hart is, counted from when the debugger was made
+    depths: Rc<RefCell<Vec<i64>>>,
+    next_number: usize,
+    symbols: HashMap<String, usize>,
+    snapshot_file: Option<String>,
+    interrupt: Option<&'static AtomicBool>,
+    last: String,
+}
+
+impl Debugger {
+    /// A debugger for `machine`, whose loads and stores it watches through hooks added after
+    /// any the machine already has. Hooks set with `Machine::set_hooks` afterwards replace them.
+    pub fn new(machine: &mut Machine) -> Self {
+        let watches = Rc::new(RefCell::new(Watches::default()));
+        let (reads, writes) = (watches.clone(), watches.clone());
+        let depths = Rc::new(RefCell::new(vec![0; machine.harts()]));
+        let calls = depths.clone();
+        machine.add_hooks(Hooks::new()
+            .on_retire(move |_, retired| {
+                if !retired.trapped {
+                    calls.borrow_mut()[retired.hart] += call_depth_change(retired.instruction);
+                }
+                Action::Continue
+            })
+            .on_memory_read(0..usize::MAX, move |hart, access| {
+                reads.borrow_mut().record(hart.id(), access);
+                Action::Continue
+            })
+            .on_memory_write(0..usize::MAX, move |hart, access| {
+                writes.borrow_mut().record(hart.id(), access);
+                Action::Continue
+            }));
+        Self {
+            breakpoints: Vec::new(),
+            watches,
+            depths,
+            next_number: 1,
+            symbols: HashMap::new(),
+            snapshot_file: None,
+            interrupt: None,
+            last: String::new(),
+        }
+    }
+
+    /// Lets addresses name the symbols of `elf`, loaded with `MachineBuilder::elf`.
+    pub fn symbols(mut self, elf: &Elf) -> Self {
+        let base = elf.base();
+        for (name, address) in elf.symbols().filter(|&(_, address)| address >= base) {
+            self.symbols.insert(name.to_string(), (address - base) as usize);
+        }
+        self
+    }
+
+    /// Where `save` writes snapshots when it is not given a file.
+    pub fn snapshot_file(mut self, file: &str) -> Self {
+        self.snapshot_file = Some(file.to_string());
+        self
+    }
+
+    /// Stops running commands once `interrupt` is set, such as by a SIGINT handler.
+    pub fn interrupt(mut self, interrupt: &'static AtomicBool) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Runs one command line, returning false once the user quits.
+    pub fn execute(&mut self, machine: &mut Machine, line: &str) -> Result<bool, String> {
+        let line = match line.trim() {
+            "" => self.last.clone(),
+            line => line.to_string(),
+        };
+        self.last = line.clone();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, arguments)) = words.split_first() else {
+            return Ok(true);
+        };
+        let (command, format) = command.split_once('/').unwrap_or((command, ""));
+
+        let hart = current_hart(machine);
+        match (command, arguments) {
+            ("step" | "s", []) => self.run(machine, Some(1), |_| false),
+            ("step" | "s", [count]) => {
+                let count = self.value(machine, count)?;
+                self.run(machine, Some(count as u64), |_| false)
+            }
+            ("continue" | "c", []) => self.run(machine, None, |_| false),
+            ("until" | "u", []) => {
+                let start = machine.get_pc(hart).map_err(|error| error.to_string())?;
+                let (depths, depth) = (self.depths.clone(), self.depths.borrow()[hart]);
+                self.run(machine, None, |machine| match depths.borrow()[hart] {
+                    now if now == depth => machine.get_pc(hart).is_ok_and(|pc| pc > start),
+                    now => now < depth,
+                })
+            }
+            ("until" | "u", [address]) => {
+                let address = self.value(machine, address)? as usize;
+                self.run(machine, None, |machine| {
+                    machine.get_next_hart().is_some_and(|hart| machine.get_pc(hart).is_ok_and(|pc| pc == address))
+                })
+            }
+            ("finish", []) => {
+                let (depths, depth) = (self.depths.clone(), self.depths.borrow()[hart]);
+                self.run(machine, None, |_| depths.borrow()[hart] < depth)
+            }
+            ("reverse-step" | "rs", []) => self.reverse_step(machine, 1)?,
+            ("reverse-step" | "rs", [count]) => {
+                let count = self.value(machine, count)?;
+                self.reverse_step(machine, count)?
+            }
+            ("reverse-continue" | "rc", []) => self.reverse_continue(machine)?,
+            ("break" | "b", arguments) => self.add_breakpoint(machine, arguments)?,
+            ("watch", arguments) => self.add_watch(machine, arguments)?,
+            ("delete" | "d", []) => {
+                self.breakpoints.clear();
+                self.watches.borrow_mut().watches.clear();
+            }
+            ("delete" | "d", [number]) => self.delete(machine, number)?,
+            ("x", [address]) => {
+                let address = self.value(machine, address)? as usize;
+                self.examine(machine, format, address)?
+            }
+            ("set", arguments) => self.set(machine, arguments)?,
+            ("disas", arguments) => {
+                let address = match arguments.first() {
+                    Some(address) => self.value(machine, address)? as usize,
+                    None => self.value(machine, "pc")? as usize,
+                };
+                let count = match arguments.get(1) {
+                    Some(count) => self.value(machine, count)?,
+                    None => 8,
+                };
+                self.examine(machine, &format!("{}i", count), address)?
+            }
+            ("info" | "i", ["regs" | "registers" | "r"]) => machine.dump_registers(),
+            ("info" | "i", ["csrs"]) => machine.dump_csrs(),
+            ("info" | "i", ["vregs"]) => machine.dump_vector_registers(),
+            ("info" | "i", ["memory" | "mem"]) => machine.dump_memory(),
+            ("info" | "i", ["breakpoints" | "break" | "watch" | "b"]) => self.list_breakpoints(),
+            ("save", arguments) => {
+                let Some(file) = arguments.first().copied().or(self.snapshot_file.as_deref()) else {
+                    return Err("save needs a file, or --save-snapshot".to_string());
+                };
+                fs::write(file, machine.save_snapshot()).map_err(|error| format!("{}: {}", file, error))?;
+                println!("saved snapshot to {} after {} instructions", file, machine.get_instructions());
+            }
+            ("help" | "h", []) => println!("{}", HELP),
+            ("quit" | "q", []) => return Ok(false),
+            _ => return Err(format!("cannot run `{}`, try help", line)),
+        }
+        Ok(true)
+    }
+
+    /// Evaluates a number, symbol, register or `pc` on the current hart.
+    fn value(&self, machine: &Machine, text: &str) -> Result<u32, String> {
+        let hart = current_hart(machine);
+        if text == "pc" {
+            return machine.get_pc(hart).map(|pc| pc as u32).map_err(|error| error.to_string());
+        }
+        if let Some(register) = register(text) {
+            return machine.get_register(hart, register).map_err(|error| error.to_string());
+        }
+        if let Some(&address) = self.symbols.get(text) {
+            return Ok(address as u32);
+        }
+        parse_number(text).ok_or_else(|| format!("{} is not a number, symbol or register", text))
+    }
+
+    /// The breakpoint `hart` has reached, if its condition holds.
+    fn breakpoint_at(&self, machine: &Machine, hart: usize) -> Option<&Breakpoint> {
+        let pc = machine.get_pc(hart).ok()?;
+        self.breakpoints.iter().find(|breakpoint| {
+            breakpoint.address == pc && breakpoint.condition.as_ref().is_none_or(|condition| {
+                machine.get_register(hart, condition.register)
+                    .is_ok_and(|data| condition.comparison.holds(data, condition.value))
+            })
+        })
+    }
+
+    /// Runs the machine an instruction at a time until `done` holds, a breakpoint or
+    /// watchpoint is hit, `limit` instructions have run or every hart stops, then shows where
+    /// the harts are. Breakpoints and `done` are only checked after the first instruction, so
+    /// that running again leaves a breakpoint.
+    fn run(&mut self, machine: &mut Machine, limit: Option<u64>, mut done: impl FnMut(&Machine) -> bool) {
+        self.watches.borrow_mut().hit = None;
+        if let Some(interrupt) = self.interrupt {
+            interrupt.store(false, Ordering::Relaxed);
+        }
+        let mut executed = 0;
+        while limit != Some(executed) {
+            let Some(hart) = machine.get_next_hart() else {
+                match machine.get_fault() {
+                    Some(fault) => println!("{}", fault),
+                    None => println!("all harts have stopped"),
+                }
+                break;
+            };
+            if executed > 0 {
+                if let Some(breakpoint) = self.breakpoint_at(machine, hart) {
+                    println!("breakpoint {}", breakpoint.number);
+                    break;
+                }
+                if done(machine) {
+                    break;
+                }
+            }
+            if self.interrupt.is_some_and(|interrupt| interrupt.load(Ordering::Relaxed)) {
+                println!("interrupted");
+                break;
+            }
+
+            machine.tick();
+            executed += 1;
+            if let Some((number, hart, access)) = self.watches.borrow_mut().hit.take() {
+                let kind = if access.write { "wrote" } else { "read" };
+                println!("watchpoint {}: hart {} {} {:x} at {:08x}", number, hart, kind, access.data, access.address);
+                break;
+            }
+        }
+        show_position(machine);
+    }
+
+    fn reverse_step(&mut self, machine: &mut Machine, count: u32) -> Result<(), String> {
+        for _ in 0..count {
+            if !machine.reverse_step().map_err(|error| error.to_string())? {
+                println!("no history before this point");
+                break;
+            }
+        }
+        self.watches.borrow_mut().hit = None;
+        show_position(machine);
+        Ok(())
+    }
+
+    /// Goes back to the last point at which a hart was about to run into a breakpoint, or
+    /// just before the last instruction that changed a word written watchpoints watch.
+    fn reverse_continue(&mut self, machine: &mut Machine) -> Result<(), String> {
+        let watched: Vec<(usize, u32)> = self.watches.borrow().watches.iter()
+            .filter(|watch| watch.write)
+            .map(|watch| (watch.address, machine.read32(watch.address).unwrap_or(0)))
+            .collect();
+        let found = machine.reverse_until(|machine| {
+            let at_breakpoint = machine.get_next_hart().is_some_and(|hart| self.breakpoint_at(machine, hart).is_some());
+            at_breakpoint || watched.iter().any(|&(address, data)| machine.read32(address).unwrap_or(0) != data)
+        }).map_err(|error| error.to_string())?;
+        if !found {
+            println!("reached the start of history");
+        }
+        self.watches.borrow_mut().hit = None;
+        show_position(machine);
+        Ok(())
+    }
+
+    fn add_breakpoint(&mut self, machine: &Machine, arguments: &[&str]) -> Result<(), String> {
+        let (address, condition) = match arguments {
+            [address] => (address, None),
+            [address, "if", name, comparison, value] => {
+                let condition = Condition {
+                    register: register(name).ok_or_else(|| format!("{} is not a register", name))?,
+                    comparison: Comparison::ALL.into_iter().find(|each| each.symbol() == *comparison)
+                        .ok_or_else(|| format!("{} is not a comparison", comparison))?,
+                    value: self.value(machine, value)?,
+                };
+                (address, Some(condition))
+            }
+            _ => return Err("usage: break <addr> [if <reg> <op> <value>]".to_string()),
+        };
+        let address = self.value(machine, address)? as usize;
+        println!("breakpoint {} at {:08x}", self.next_number, address);
+        self.breakpoints.push(Breakpoint { number: self.next_number, address, condition });
+        self.next_number += 1;
+        Ok(())
+    }
+
+    fn add_watch(&mut self, machine: &Machine, arguments: &[&str]) -> Result<(), String> {
+        let (address, read, write) = match arguments {
+            [address] | [address, "w"] => (address, false, true),
+            [address, "r"] => (address, true, false),
+            [address, "rw"] => (address, true, true),
+            _ => return Err("usage: watch <addr> [r|w|rw]".to_string()),
+        };
+        let address = self.value(machine, address)? as usize;
+        println!("watchpoint {} at {:08x}", self.next_number, address);
+        self.watches.borrow_mut().watches.push(Watch { number: self.next_number, address, read, write });
+        self.next_number += 1;
+        Ok(())
+    }
+
+    fn delete(&mut self, machine: &Machine, number: &str) -> Result<(), String> {
+        let number = self.value(machine, number)? as usize;
+        let (breakpoints, watches) = (self.breakpoints.len(), self.watches.borrow().watches.len());
+        self.breakpoints.retain(|breakpoint| breakpoint.number != number);
+        self.watches.borrow_mut().watches.retain(|watch| watch.number != number);
+        if self.breakpoints.len() == breakpoints && self.watches.borrow().watches.len() == watches {
+            return Err(format!("there is no breakpoint or watchpoint {}", number));
+        }
+        Ok(())
+    }
+
+    fn list_breakpoints(&self) {
+        for breakpoint in &self.breakpoints {
+            print!("{:<4}break     {:08x}", breakpoint.number, breakpoint.address);
+            match &breakpoint.condition {
+                Some(condition) => println!("  if {}", condition),
+                None => println!(),
+            }
+        }
+        for watch in &self.watches.borrow().watches {
+            let kind = match (watch.read, watch.write) {
+                (true, true) => "rw",
+                (true, false) => "r",
+                _ => "w",
+            };
+            println!("{:<4}watch {:<3} {:08x}", watch.number, kind, watch.address);
+        }
+    }
+
+    /// Prints memory from `address` in the `<n><fmt>` format of `x`.
+    fn examine(&self, machine: &Machine, format: &str, address: usize) -> Result<(), String> {
+        let digits = format.find(|character: char| !character.is_ascii_digit()).unwrap_or(format.len());
+        let count: usize = match &format[..digits] {
+            "" => 1,
+            count => count.parse().map_err(|_| format!("{} is too many", count))?,
+        };
+        let (size, per_line) = match &format[digits..] {
+            "" | "x" | "d" | "u" => (4, 4),
+            "i" => (4, 1),
+            "h" => (2, 8),
+            "b" | "c" => (1, 16),
+            other => return Err(format!("{} is not a format, try x, d, u, h, b, c or i", other)),
+        };
+
+        let bytes = count.checked_mul(size).filter(|_| count <= EXAMINE_LIMIT)
+            .ok_or_else(|| format!("{} is too many, the most is {}", count, EXAMINE_LIMIT))?;
+        let mut data = vec![0; bytes];
+        machine.read_memory(address, &mut data).map_err(|error| error.to_string())?;
+        for (line, units) in data.chunks(size * per_line).enumerate() {
+            print!("{:08x}:", address + line * size * per_line);
+            for unit in units.chunks(size) {
+                let mut bytes = [0; 4];
+                bytes[..size].copy_from_slice(unit);
+                let word = u32::from_le_bytes(bytes);
+                match &format[digits..] {
+                    "d" => print!(" {:>11}", word as i32),
+                    "u" => print!(" {:>10}", word),
+                    "i" => print!("    {}", Instruction::from_u32(word)),
+                    "h" => print!(" {:04x}", word),
+                    "b" => print!(" {:02x}", word),
+                    "c" => print!(" {}", if (0x20..0x7F).contains(&word) { word as u8 as char } else { '.' }),
+                    _ => print!(" {:08x}", word),
+                }
+            }
+            println!();
+        }
+        Ok(())
+    }
+
+    fn set(&self, machine: &mut Machine, arguments: &[&str]) -> Result<(), String> {
+        let hart = current_hart(machine);
+        match arguments {
+            ["reg", "pc", value] => {
+                let pc = self.value(machine, value)?;
+                machine.set_pc(hart, pc as usize).map_err(|error| error.to_string())?;
+            }
+            ["reg", name, value] => {
+                let register = register(name).ok_or_else(|| format!("{} is not a register", name))?;
+                let data = self.value(machine, value)?;
+                machine.set_register(hart, register, data).map_err(|error| error.to_string())?;
+            }
+            [memory, address, value] if memory.split('/').next() == Some("mem") => {
+                let size = match memory.split_once('/') {
+                    None | Some((_, "w")) => 4,
+                    Some((_, "h")) => 2,
+                    Some((_, "b")) => 1,
+                    Some((_, size)) => return Err(format!("{} is not a size, try b, h or w", size)),
+                };
+                let (address, data) = (self.value(machine, address)?, self.value(machine, value)?);
+                machine.write_memory(address as usize, &data.to_le_bytes()[..size]).map_err(|error| error.to_string())?;
+            }
+            _ => return Err("usage: set reg <reg|pc> <value> or set mem[/b|/h|/w] <addr> <value>".to_string()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::cpu::hooks::{Action, Hooks};
+    use crate::debugger::{parse_number, register, Debugger};
+    use crate::machine::{Machine, MachineBuilder};
+
+    #[test]
+    fn registers_and_numbers_parse() {
+        assert_eq!((register("x31"), register("a0"), register("fp"), register("zero")), (Some(31), Some(10), Some(8), Some(0)));
+        assert_eq!((register("x32"), register("pc")), (None, None));
+        assert_eq!((parse_number("0x1F"), parse_number("12"), parse_number("-1")), (Some(31), Some(12), Some(u32::MAX)));
+        assert_eq!(parse_number("twelve"), None);
+    }
+
+    #[test]
+    fn commands_drive_the_machine() {
+        // addi x10,x0,0; jal x1,12; sw x10,256(x0); ebreak
+        // f: addi x10,x10,1; addi x5,x0,5; blt x10,x5,-8; jalr x0,0(x1)
+        let program: Vec<u8> = [0x00000513u32, 0x00c000ef, 0x10a02023, 0x00100073, 0x00150513, 0x00500293, 0xfe554ce3, 0x00008067]
+            .iter().flat_map(|word| word.to_le_bytes()).collect();
+        let mut machine = MachineBuilder::new(0x1000).program(0, &program).build().unwrap();
+        machine.record_history(4);
+        // The debugger's hooks run alongside those already set
+        let stores = Rc::new(Cell::new(0));
+        let counted = stores.clone();
+        machine.set_hooks(Hooks::new().on_memory_write(0x100..0x104, move |_, _| {
+            counted.set(counted.get() + 1);
+            Action::Continue
+        }));
+        let mut debugger = Debugger::new(&mut machine);
+        let mut run = |machine: &mut Machine, line: &str| debugger.execute(machine, line).unwrap();
+
+        run(&mut machine, "break 0x10 if a0 == 3");
+        run(&mut machine, "continue");
+        assert_eq!((machine.get_pc(0).unwrap(), machine.get_register(0, 10).unwrap()), (0x10, 3));
+
+        run(&mut machine, "finish");
+        assert_eq!((machine.get_pc(0).unwrap(), machine.get_register(0, 10).unwrap()), (8, 5));
+
+        run(&mut machine, "watch 0x100");
+        run(&mut machine, "c");
+        assert_eq!((machine.get_pc(0).unwrap(), machine.read32(0x100).unwrap()), (0xC, 5));
+        assert!(stores.get() > 0);
+
+        run(&mut machine, "reverse-continue");
+        assert_eq!((machine.get_pc(0).unwrap(), machine.read32(0x100).unwrap()), (8, 0));
+
+        run(&mut machine, "set reg a0 0x2a");
+        run(&mut machine, "step");
+        assert_eq!(machine.read32(0x100).unwrap(), 0x2A);
+        run(&mut machine, "set mem/b 0x101 7");
+        assert_eq!(machine.read32(0x100).unwrap(), 0x72A);
+
+        run(&mut machine, "delete");
+        run(&mut machine, "until 0x10");
+        assert_eq!(machine.get_pc(0).unwrap(), 0xC);
+        assert!(!run(&mut machine, "quit"));
+        assert!(debugger.execute(&mut machine, "x/2q 0x100").is_err());
+        assert!(debugger.execute(&mut machine, "x/5000x 0").is_err());
+        assert!(debugger.execute(&mut machine, "disas 0 0xffffffff").is_err());
+        assert!(debugger.execute(&mut machine, "frobnicate").is_err());
+    }
+
+    #[test]
+    fn until_and_finish_run_over_calls() {
+        // addi x10,x0,0; jal x1,16; sw x10,256(x0); ebreak; nop
+        // f: addi sp,sp,-16; sw ra,0(sp); jal x1,20; lw ra,0(sp); addi sp,sp,16; jalr x0,0(x1); nop
+        // g: addi x10,x10,1; jalr x0,0(x1)
+        let program: Vec<u8> = [0x00000513u32, 0x010000ef, 0x10a02023, 0x00100073, 0x00000013, 0xff010113, 0x00112023,
+            0x014000ef, 0x00012083, 0x01010113, 0x00008067, 0x00000013, 0x00150513, 0x00008067]
+            .iter().flat_map(|word| word.to_le_bytes()).collect();
+        let mut machine = MachineBuilder::new(0x1000).program(0, &program).build().unwrap();
+        let mut debugger = Debugger::new(&mut machine);
+        let mut run = |machine: &mut Machine, line: &str| debugger.execute(machine, line).unwrap();
+
+        run(&mut machine, "set reg sp 0x800");
+        run(&mut machine, "break 0x1c");
+        run(&mut machine, "continue");
+        run(&mut machine, "until");
+        assert_eq!((machine.get_pc(0).unwrap(), machine.get_register(0, 10).unwrap()), (0x20, 1));
+
+        // ra has been saved and overwritten by the call to g
+        run(&mut machine, "finish");
+        assert_eq!((machine.get_pc(0).unwrap(), machine.get_register(0, 2).unwrap()), (8, 0x800));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::cpu::block::Block;
+use crate::instruction::{Instruction, InstructionType};
+
+pub(crate) const PAGE_SHIFT: usize = 12;
+const PAGE_WORDS: usize = 1 << (PAGE_SHIFT - 2);
+
+/// An instruction together with its decoded type, `None` when it is illegal, and the operands
+/// its `execute_*` method needs, extracted once rather than on every execution.
+#[derive(Clone, Copy)]
+pub struct Decoded {
+    pub instruction: Instruction,
+    pub _type: Option<InstructionType>,
+    pub rd: u8,
+    pub rs1: u8,
+    pub rs2: u8,
+    /// The sign-extended immediate of the instruction's format, already shifted for U-type
+    /// instructions, or the shift amount of a shift by an immediate
+    pub imm: u32,
+}
+
+impl Decoded {
+    pub fn new(instruction: Instruction) -> Self {
+        let _type = instruction._type().ok();
+        Self {
+            instruction,
+            _type,
+            rd: instruction.get_rd(),
+            rs1: instruction.get_rs1(),
+            rs2: instruction.get_rs2(),
+            imm: immediate(&instruction, _type),
+        }
+    }
+}
+
+fn immediate(instruction: &Instruction, _type: Option<InstructionType>) -> u32 {
+    if let Some(InstructionType::SLLI | InstructionType::SRLI | InstructionType::SRAI | InstructionType::RORI
+        | InstructionType::BSETI | InstructionType::BCLRI | InstructionType::BINVI | InstructionType::BEXTI) = _type
+    {
+        return instruction.get_shamt() as u32;
+    }
+    match instruction.opcode() {
+        0b0110111 | 0b0010111 => instruction.get_imm_u() << 12,
+        0b1101111 => instruction.get_imm_j(),
+        0b1100011 => instruction.get_imm_b(),
+        0b0100011 => instruction.get_imm_s(),
+        0b0000011 | 0b0001111 | 0b0010011 | 0b1100111 | 0b1110011 => instruction.get_imm_i(),
+        _ => 0,
+    }
+}
+
+/// The decoded words of one page and the blocks translated from them, keyed by address.
+struct Page {
+    decoded: Box<[Option<Decoded>]>,
+    blocks: HashMap<usize, Rc<Block>>,
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        self.blocks.values().for_each(|block| block.invalidate());
+    }
+}
+
+/// Decoded instructions and translated blocks of main memory, kept per 4 KiB page. A page is
+/// filled lazily as its words are fetched. A store drops the decoded words it touches and the
+/// blocks covering them, which also invalidates any of those blocks still running; stores to
+/// data next to code cost nothing more.
+pub struct DecodeCache {
+    pages: Vec<Option<Page>>,
+}
+
+impl DecodeCache {
+    pub fn new(memory_size: usize) -> Self {
+        Self { pages: (0..memory_size.div_ceil(1 << PAGE_SHIFT)).map(|_| None).collect() }
+    }
+
+    /// Whether `a` and `b` lie in the same page, so a block spanning them is dropped as one.
+    pub fn same_page(a: usize, b: usize) -> bool {
+        a >> PAGE_SHIFT == b >> PAGE_SHIFT
+    }
+
+    fn page_mut(&mut self, index: usize) -> Option<&mut Page> {
+        let page = self.pages.get_mut(index >> PAGE_SHIFT)?;
+        Some(page.get_or_insert_with(|| Page {
+            decoded: vec![None; PAGE_WORDS].into_boxed_slice(),
+            blocks: HashMap::new(),
+        }))
+    }
+
+    /// The cached decoding of the aligned word at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<Decoded> {
+        let page = self.pages.get(index >> PAGE_SHIFT)?.as_ref()?;
+        page.decoded[(index >> 2) % PAGE_WORDS]
+    }
+
+    pub fn insert(&mut self, index: usize, decoded: Decoded) {
+        if let Some(page) = self.page_mut(index) {
+            page.decoded[(index >> 2) % PAGE_WORDS] = Some(decoded);
+        }
+    }
+
+    pub fn get_block(&self, index: usize) -> Option<Rc<Block>> {
+        let page = self.pages.get(index >> PAGE_SHIFT)?.as_ref()?;
+        page.blocks.get(&index).cloned()
+    }
+
+    pub fn insert_block(&mut self, index: usize, block: Rc<Block>) {
+        if let Some(page) = self.page_mut(index) {
+            page.blocks.insert(index, block);
+        }
+    }
+
+    /// Drops the decoded words touched by a store to `index..index + size` and the blocks
+    /// covering them. Every word of a block is decoded while the block is valid, so a word
+    /// that is not decoded has no blocks to drop.
+    pub fn invalidate(&mut self, index: usize, size: usize) {
+        for word in (index & !3..index + size).step_by(4) {
+            let Some(Some(page)) = self.pages.get_mut(word >> PAGE_SHIFT) else { continue };
+            if page.decoded[(word >> 2) % PAGE_WORDS].take().is_none() {
+                continue;
+            }
+            page.blocks.retain(|&start, block| {
+                let covers = (start..start + block.size()).contains(&word);
+                if covers {
+                    block.invalidate();
+                }
+                !covers
+            });
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.pages.iter_mut().for_each(|page| *page = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode_cache::{DecodeCache, Decoded};
+    use crate::instruction::{Instruction, InstructionType};
+
+    #[test]
+    fn stores_invalidate_words() {
+        let mut cache = DecodeCache::new(0x3000);
+        cache.insert(0x1004, Decoded::new(Instruction::from_u32(0x00000013)));
+        cache.insert(0x1FFC, Decoded::new(Instruction::from_u32(0x00000013)));
+        cache.insert(0x2000, Decoded::new(Instruction::from_u32(0)));
+        assert_eq!(cache.get(0x1004).unwrap()._type, Some(InstructionType::ADDI));
+        assert_eq!(cache.get(0x2000).unwrap()._type, None);
+        assert!(cache.get(0x1008).is_none());
+
+        cache.invalidate(0x1FFE, 4);
+        assert!(cache.get(0x1004).is_some());
+        assert!(cache.get(0x1FFC).is_none());
+        assert!(cache.get(0x2000).is_none());
+
+        cache.insert(0x1004, Decoded::new(Instruction::from_u32(0x00000013)));
+        cache.flush();
+        assert!(cache.get(0x1004).is_none());
+    }
+}
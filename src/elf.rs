@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const EM_RISCV: u16 = 243;
+
+/// Bytes loaded at `address`, followed by zeros up to `size`.
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+    pub size: u32,
+}
+
+/// A little-endian 32-bit RISC-V ELF executable: its loadable segments, entry point and
+/// symbol table.
+pub struct Elf {
+    pub entry: u32,
+    pub segments: Vec<Segment>,
+    symbols: HashMap<String, u32>,
+}
+
+fn read16(bytes: &[u8], offset: usize) -> Result<u16, String> {
+    let field = bytes.get(offset..offset + 2).ok_or("ELF file is truncated")?;
+    Ok(u16::from_le_bytes(field.try_into().unwrap()))
+}
+
+fn read32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    let field = bytes.get(offset..offset + 4).ok_or("ELF file is truncated")?;
+    Ok(u32::from_le_bytes(field.try_into().unwrap()))
+}
+
+fn slice(bytes: &[u8], offset: u32, size: u32) -> Result<&[u8], String> {
+    let (offset, size) = (offset as usize, size as usize);
+    bytes.get(offset..offset + size).ok_or("ELF file is truncated".to_string())
+}
+
+impl Elf {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.get(0..4) != Some(b"\x7fELF") {
+            return Err("not an ELF file".to_string());
+        }
+        if bytes.get(4) != Some(&1) {
+            return Err("only 32-bit ELF files are supported".to_string());
+        }
+        if bytes.get(5) != Some(&1) || read16(bytes, 18)? != EM_RISCV {
+            return Err("not a little-endian RISC-V ELF file".to_string());
+        }
+
+        let entry = read32(bytes, 24)?;
+        let program_headers = read32(bytes, 28)? as usize;
+        let section_headers = read32(bytes, 32)? as usize;
+        let program_header_size = read16(bytes, 42)? as usize;
+        let section_header_size = read16(bytes, 46)? as usize;
+
+        let mut segments = Vec::new();
+        for index in 0..read16(bytes, 44)? as usize {
+            let header = program_headers + index * program_header_size;
+            if read32(bytes, header)? != PT_LOAD {
+                continue;
+            }
+            let data = slice(bytes, read32(bytes, header + 4)?, read32(bytes, header + 16)?)?;
+            segments.push(Segment {
+                address: read32(bytes, header + 12)?,
+                data: data.to_vec(),
+                size: read32(bytes, header + 20)?,
+            });
+        }
+        if segments.is_empty() {
+            return Err("ELF file has nothing to load".to_string());
+        }
+
+        let mut symbols = HashMap::new();
+        let section = |index: usize| section_headers + index * section_header_size;
+        for index in 0..read16(bytes, 48)? as usize {
+            if read32(bytes, section(index) + 4)? != SHT_SYMTAB {
+                continue;
+            }
+            let table = slice(bytes, read32(bytes, section(index) + 16)?, read32(bytes, section(index) + 20)?)?;
+            let strings = section(read32(bytes, section(index) + 24)? as usize);
+            let strings = slice(bytes, read32(bytes, strings + 16)?, read32(bytes, strings + 20)?)?;
+
+            for symbol in table.chunks_exact(16) {
+                let name = &strings[(read32(symbol, 0)? as usize).min(strings.len())..];
+                let name = &name[..name.iter().position(|&byte| byte == 0).unwrap_or(name.len())];
+                if !name.is_empty() {
+                    symbols.insert(String::from_utf8_lossy(name).into_owned(), read32(symbol, 4)?);
+                }
+            }
+        }
+
+        Ok(Self { entry, segments, symbols })
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.symbols.iter().map(|(name, address)| (name.as_str(), *address))
+    }
+
+    /// The lowest address loaded, which `MachineBuilder::elf` moves to address zero.
+    pub fn base(&self) -> u32 {
+        self.segments.iter().map(|segment| segment.address).min().unwrap()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::elf::Elf;
+
+    /// An executable with one segment of `code` at 0x80000000 and a `tohost` symbol at
+    /// 0x80001000, laid out by hand: headers, then the code, the symbols and their names.
+    pub(crate) fn executable(code: &[u32]) -> Vec<u8> {
+        let code: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let code_offset = 52 + 32;
+        let symbols_offset = code_offset + code.len();
+        let strings_offset = symbols_offset + 32;
+        let strings = b"\0tohost\0";
+        let sections_offset = strings_offset + strings.len();
+
+        let mut bytes = b"\x7fELF\x01\x01\x01".to_vec();
+        bytes.resize(16, 0);
+        let halves = |values: &[u16]| values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>();
+        let words = |values: &[u32]| values.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<_>>();
+        bytes.extend(halves(&[2, 243]));
+        bytes.extend(words(&[1, 0x80000000, 52, sections_offset as u32, 0]));
+        bytes.extend(halves(&[52, 32, 1, 40, 3, 0]));
+        bytes.extend(words(&[1, code_offset as u32, 0x80000000, 0x80000000, code.len() as u32, 0x1008, 5, 4]));
+        bytes.extend(&code);
+        bytes.extend(words(&[0, 0, 0, 0, 1, 0x80001000, 8, 0]));
+        bytes.extend(strings);
+        bytes.extend(words(&[0; 10]));
+        bytes.extend(words(&[0, 2, 0, 0, symbols_offset as u32, 32, 2, 1, 4, 16]));
+        bytes.extend(words(&[0, 3, 0, 0, strings_offset as u32, strings.len() as u32, 0, 0, 1, 0]));
+        bytes
+    }
+
+    #[test]
+    fn loads_segments_and_symbols() {
+        let elf = Elf::parse(&executable(&[0x00000013, 0x00100073])).unwrap();
+        assert_eq!(elf.entry, 0x80000000);
+        assert_eq!(elf.base(), 0x80000000);
+        assert_eq!(elf.symbol("tohost"), Some(0x80001000));
+        assert_eq!(elf.segments[0].data.len(), 8);
+        assert_eq!(elf.segments[0].size, 0x1008);
+
+        let mut rv64 = executable(&[]);
+        rv64[4] = 2;
+        assert!(Elf::parse(&rv64).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use crate::instruction::Instruction;
+use crate::trap::Exception;
+
+/// Everything that can go wrong setting up or driving a machine, including guest faults that
+/// stop a hart because no trap handler is installed.
+#[derive(Debug)]
+pub enum Error {
+    /// The machine was configured with values it cannot run with
+    Config(String),
+    /// A program or ELF executable could not be loaded
+    Load(String),
+    /// `size` bytes at `address` are not backed by memory or a device
+    Bus { address: usize, size: usize },
+    /// The machine has no hart with this index
+    NoHart(usize),
+    /// There is no integer register with this index
+    NoRegister(usize),
+    /// A snapshot could not be restored
+    Snapshot(String),
+    /// Hart `hart` fetched `instruction` at `pc`, which no enabled extension implements
+    IllegalInstruction { hart: usize, pc: usize, instruction: u32 },
+    /// Hart `hart` raised `exception` at `pc`. `instruction` is the word at `pc` when it could
+    /// be read
+    Fault { hart: usize, pc: usize, instruction: Option<u32>, exception: Exception, tval: u32 },
+    /// Hart `hart` fetched code at `pc` that was stored to since its last FENCE.I, in strict
+    /// FENCE.I mode
+    UnfencedFetch { hart: usize, pc: usize },
+    Io(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Config(message) => write!(f, "invalid configuration: {}", message),
+            Error::Load(message) => write!(f, "cannot load program: {}", message),
+            Error::Bus { address, size } => write!(f, "no memory at {:08x} ({} bytes)", address, size),
+            Error::NoHart(hart) => write!(f, "no hart {}", hart),
+            Error::NoRegister(register) => write!(f, "no register x{}", register),
+            Error::Snapshot(message) => write!(f, "cannot restore snapshot: {}", message),
+            Error::IllegalInstruction { hart, pc, instruction } => {
+                write!(f, "hart {}: illegal instruction {:08x} at {:08x}", hart, instruction, pc)?;
+                disassemble(f, *instruction)
+            }
+            Error::Fault { hart, pc, instruction, exception, tval } => {
+                write!(f, "hart {}: {} at {:08x}", hart, exception, pc)?;
+                if let Some(instruction) = instruction {
+                    disassemble(f, *instruction)?;
+                }
+                write!(f, ", tval {:#x}", tval)
+            }
+            Error::UnfencedFetch { hart, pc } => {
+                write!(f, "hart {}: self-modifying code at {:08x} executed without FENCE.I", hart, pc)
+            }
+            Error::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Writes the disassembly of `instruction` in parentheses, if it decodes.
+fn disassemble(f: &mut Formatter<'_>, instruction: u32) -> std::fmt::Result {
+    let instruction = Instruction::from_u32(instruction);
+    if instruction._type().is_err() {
+        return Ok(());
+    }
+    write!(f, " ({})", instruction.to_string().trim_end())
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,368 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::error::Error;
+use crate::machine::Machine;
+
+/// Instructions run between checks for an interrupt from GDB while continuing.
+const INTERRUPT_POLL: u64 = 10_000;
+
+/// The most bytes an `m` packet reads at once, which `PacketSize` keeps GDB within.
+const PACKET_SIZE: usize = 0x1000;
+
+/// The register numbers GDB uses: x0 to x31, then the PC.
+const PC_REGISTER: usize = 32;
+
+/// A connection to GDB that can be checked, without waiting, for the interrupt byte GDB sends
+/// when the user presses Ctrl-C.
+pub trait Connection: Read + Write {
+    fn interrupted(&mut self) -> io::Result<bool>;
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let mut byte = [0];
+        let interrupted = match self.read(&mut byte) {
+            Ok(read) => Ok(read == 1 && byte[0] == 0x03),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        };
+        self.set_nonblocking(false)?;
+        interrupted
+    }
+}
+
+/// The target description GDB asks for, naming the registers `g` packets carry.
+fn target_xml() -> String {
+    let mut registers: String = (0..32)
+        .map(|register| format!("<reg name=\"x{}\" bitsize=\"32\" regnum=\"{}\"/>", register, register))
+        .collect();
+    registers += &format!("<reg name=\"pc\" bitsize=\"32\" type=\"code_ptr\" regnum=\"{}\"/>", PC_REGISTER);
+    format!("<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\">\
+        <architecture>riscv:rv32</architecture><feature name=\"org.gnu.gdb.riscv.cpu\">{}</feature></target>", registers)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok()).collect()
+}
+
+fn parse_number(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// Splits `<address>,<length>`, as `m`, `M` and `qXfer` packets give them.
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let (address, length) = text.split_once(',')?;
+    Some((parse_number(address)?, parse_number(length)?))
+}
+
+/// Reads the next packet, acknowledging it, or None once GDB has hung up. Packets with a bad
+/// checksum are asked for again.
+fn read_packet(connection: &mut impl Connection) -> io::Result<Option<String>> {
+    let mut byte = [0];
+    loop {
+        // Acknowledgements and interrupts sent while stopped are skipped
+        loop {
+            if connection.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            if connection.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        connection.read_exact(&mut checksum)?;
+        let expected = std::str::from_utf8(&checksum).ok().and_then(|text| u8::from_str_radix(text, 16).ok());
+        if expected == Some(data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))) {
+            connection.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+        connection.write_all(b"-")?;
+    }
+}
+
+fn write_packet(connection: &mut impl Connection, data: &str) -> io::Result<()> {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    connection.write_all(format!("${}#{:02x}", data, checksum).as_bytes())?;
+    connection.flush()
+}
+
+/// The state of one GDB session over a machine.
+struct Session {
+    breakpoints: Vec<usize>,
+}
+
+impl Session {
+    /// Whether the hart that runs next is at a breakpoint.
+    fn at_breakpoint(&self, machine: &Machine) -> bool {
+        machine.get_next_hart().is_some_and(|hart| machine.get_pc(hart).is_ok_and(|pc| self.breakpoints.contains(&pc)))
+    }
+
+    /// Runs until a breakpoint, `limit` instructions or every hart stops, or GDB interrupts,
+    /// and returns the stop reply. Breakpoints are only checked after the first instruction,
+    /// so that running again leaves one.
+    fn resume(&self, machine: &mut Machine, connection: &mut impl Connection, limit: Option<u64>) -> io::Result<String> {
+        let mut executed = 0;
+        loop {
+            if limit == Some(executed) {
+                return Ok("S05".to_string());
+            }
+            if machine.get_next_hart().is_none() {
+                let signal = if machine.get_fault().is_some() { "S0b" } else { "S05" };
+                return Ok(signal.to_string());
+            }
+            if executed > 0 && self.at_breakpoint(machine) {
+                return Ok("T05swbreak:;".to_string());
+            }
+            if executed > 0 && executed % INTERRUPT_POLL == 0 && connection.interrupted()? {
+                return Ok("S02".to_string());
+            }
+            machine.tick();
+            executed += 1;
+        }
+    }
+
+    /// Answers one packet, or returns None once GDB detaches or kills the program.
+    fn reply(&mut self, machine: &mut Machine, connection: &mut impl Connection, packet: &str) -> Result<Option<String>, Error> {
+        let hart = machine.get_next_hart().unwrap_or(0);
+        let error = || "E01".to_string();
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match (command, arguments) {
+            ("?", _) => "S05".to_string(),
+            ("q", arguments) if arguments.starts_with("Supported") => {
+                format!("PacketSize={:x};qXfer:features:read+;swbreak+;ReverseStep+;ReverseContinue+", PACKET_SIZE)
+            }
+            ("q", arguments) if arguments.starts_with("Xfer:features:read:target.xml:") => {
+                let xml = target_xml();
+                match parse_range(&arguments["Xfer:features:read:target.xml:".len()..]) {
+                    Some((offset, length)) if offset < xml.len() => {
+                        let end = (offset + length).min(xml.len());
+                        format!("{}{}", if end == xml.len() { "l" } else { "m" }, &xml[offset..end])
+                    }
+                    Some(_) => "l".to_string(),
+                    None => error(),
+                }
+            }
+            ("q", "Attached") => "1".to_string(),
+            ("q", "C") => "QC1".to_string(),
+            ("H", _) => "OK".to_string(),
+            ("g", _) => {
+                let mut registers = Vec::new();
+                for register in 0..32 {
+                    registers.extend(machine.get_register(hart, register)?.to_le_bytes());
+                }
+                registers.extend((machine.get_pc(hart)? as u32).to_le_bytes());
+                hex(&registers)
+            }
+            ("G", data) => match parse_hex(data).filter(|data| data.len() == 4 * (PC_REGISTER + 1)) {
+                Some(data) => {
+                    let words: Vec<u32> = data.chunks(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect();
+                    for (register, &word) in words[..32].iter().enumerate() {
+                        machine.set_register(hart, register, word)?;
+                    }
+                    machine.set_pc(hart, words[PC_REGISTER] as usize)?;
+                    "OK".to_string()
+                }
+                None => error(),
+            },
+            ("p", number) => match parse_number(number) {
+                Some(PC_REGISTER) => hex(&(machine.get_pc(hart)? as u32).to_le_bytes()),
+                Some(register) if register < 32 => hex(&machine.get_register(hart, register)?.to_le_bytes()),
+                _ => error(),
+            },
+            ("P", assignment) => {
+                let parsed = assignment.split_once('=').and_then(|(number, data)| {
+                    let data: [u8; 4] = parse_hex(data)?.try_into().ok()?;
+                    Some((parse_number(number)?, u32::from_le_bytes(data)))
+                });
+                match parsed {
+                    Some((PC_REGISTER, data)) => {
+                        machine.set_pc(hart, data as usize)?;
+                        "OK".to_string()
+                    }
+                    Some((register, data)) if register < 32 => {
+                        machine.set_register(hart, register, data)?;
+                        "OK".to_string()
+                    }
+                    _ => error(),
+                }
+            }
+            ("m", range) => match parse_range(range) {
+                Some((address, length)) => {
+                    let mut data = vec![0; length.min(PACKET_SIZE / 2)];
+                    match machine.read_memory(address, &mut data) {
+                        Ok(()) => hex(&data),
+                        Err(_) => error(),
+                    }
+                }
+                None => error(),
+            },
+            ("M", arguments) => {
+                let parsed = arguments.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, parse_hex(data)?)));
+                match parsed {
+                    Some(((address, length), data)) if data.len() == length => match machine.write_memory(address, &data) {
+                        Ok(()) => "OK".to_string(),
+                        Err(_) => error(),
+                    },
+                    _ => error(),
+                }
+            }
+            ("Z" | "z", arguments) if arguments.starts_with("0,") => {
+                let Some(address) = arguments[2..].split(',').next().and_then(parse_number) else {
+                    return Ok(Some(error()));
+                };
+                self.breakpoints.retain(|&breakpoint| breakpoint != address);
+                if command == "Z" {
+                    self.breakpoints.push(address);
+                }
+                "OK".to_string()
+            }
+            ("c" | "s", address) => {
+                if let Some(address) = parse_number(address) {
+                    machine.set_pc(hart, address)?;
+                }
+                let limit = (command == "s").then_some(1);
+                self.resume(machine, connection, limit)?
+            }
+            ("b", "s") => match machine.reverse_step()? {
+                true => "S05".to_string(),
+                false => "T05replaylog:begin;".to_string(),
+            },
+            ("b", "c") => match machine.reverse_until(|machine| self.at_breakpoint(machine))? {
+                true => "T05swbreak:;".to_string(),
+                false => "T05replaylog:begin;".to_string(),
+            },
+            ("D", _) => {
+                write_packet(connection, "OK")?;
+                return Ok(None);
+            }
+            ("k", _) => return Ok(None),
+            // An empty reply tells GDB the packet is not supported
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+}
+
+/// Serves one GDB remote protocol session on `connection`, debugging `machine` until GDB
+/// detaches or hangs up. Commands act on the hart that runs next, as in interactive mode.
+/// Reverse execution needs `Machine::record_history`.
+pub fn serve(connection: &mut impl Connection, machine: &mut Machine) -> Result<(), Error> {
+    let mut session = Session { breakpoints: Vec::new() };
+    while let Some(packet) = read_packet(connection)? {
+        match session.reply(machine, connection, &packet)? {
+            Some(reply) => write_packet(connection, &reply)?,
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Waits for GDB to connect to `listener` and serves it.
+pub fn listen(listener: &TcpListener, machine: &mut Machine) -> Result<(), Error> {
+    let (mut stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    serve(&mut stream, machine)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use crate::gdb::{serve, Connection};
+    use crate::machine::MachineBuilder;
+
+    /// Reads the packets sent by GDB and collects the replies.
+    struct Session {
+        packets: Cursor<Vec<u8>>,
+        replies: Vec<u8>,
+    }
+
+    impl Session {
+        fn new(packets: &[&str]) -> Self {
+            let mut sent = Vec::new();
+            for packet in packets {
+                let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+                sent.extend(format!("${}#{:02x}", packet, checksum).bytes());
+            }
+            Self { packets: Cursor::new(sent), replies: Vec::new() }
+        }
+
+        /// The replies without their framing and acknowledgements.
+        fn replies(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.replies).split('$').skip(1)
+                .map(|reply| reply.split_once('#').unwrap().0.to_string())
+                .collect()
+        }
+    }
+
+    impl Read for Session {
+        fn read(&mut self, data: &mut [u8]) -> io::Result<usize> {
+            self.packets.read(data)
+        }
+    }
+
+    impl Write for Session {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.replies.write(data)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for Session {
+        fn interrupted(&mut self) -> io::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn packets_drive_the_machine() {
+        // addi x10,x0,0; addi x10,x10,1; sw x10,256(x0); jal x0,-8
+        let program: Vec<u8> = [0x00000513u32, 0x00150513, 0x10a02023, 0xff9ff06f]
+            .iter().flat_map(|word| word.to_le_bytes()).collect();
+        let mut machine = MachineBuilder::new(0x1000).program(0, &program).build().unwrap();
+        machine.record_history(4);
+
+        let mut session = Session::new(&["qSupported:swbreak+", "?", "Z0,8,4", "c", "c", "p20", "p0a", "m100,4",
+            "bs", "p20", "bc", "p0a", "bc", "s", "M100,4:2a000000", "m100,4", "P0a=07000000", "p0a", "z0,8,4", "D"]);
+        serve(&mut session, &mut machine).unwrap();
+
+        let replies = session.replies();
+        assert!(replies[0].contains("ReverseStep+;ReverseContinue+"));
+        assert_eq!(&replies[1..8], ["S05", "OK", "T05swbreak:;", "T05swbreak:;", "08000000", "02000000", "01000000"]);
+        // Back one instruction, back to the breakpoint hit before, then back to the start
+        assert_eq!(&replies[8..13], ["S05", "04000000", "T05swbreak:;", "01000000", "T05replaylog:begin;"]);
+        assert_eq!(&replies[13..], ["S05", "OK", "2a000000", "OK", "07000000", "OK", "OK"]);
+        assert_eq!(machine.get_pc(0).unwrap(), 4);
+    }
+
+    #[test]
+    fn bad_checksums_are_asked_for_again() {
+        let mut machine = MachineBuilder::new(0x100).build().unwrap();
+        let mut session = Session::new(&["?"]);
+        session.packets = Cursor::new([b"$?#00".as_slice(), session.packets.get_ref()].concat());
+        serve(&mut session, &mut machine).unwrap();
+        assert_eq!(String::from_utf8_lossy(&session.replies), "-+$S05#b8");
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::memory::{Memory, SNAPSHOT_PAGE};
+
+/// Checkpoints kept before the oldest is dropped.
+const HISTORY_LIMIT: usize = 1000;
+
+/// The state of a machine after `taken` instructions. Memory is kept as the pages written
+/// since the checkpoint before, so a checkpoint costs what the program stored rather than
+/// the size of memory.
+struct Checkpoint {
+    taken: u64,
+    /// A snapshot of everything but memory
+    state: Vec<u8>,
+    /// The pages written since the checkpoint before, as they were when this one was taken
+    pages: HashMap<usize, Vec<u8>>,
+}
+
+/// Checkpoints of a machine taken every `interval` instructions. Execution is deterministic,
+/// so any earlier point is rebuilt by restoring the checkpoint before it and running forward.
+pub struct History {
+    interval: u64,
+    /// Memory as it was at the oldest checkpoint
+    base: Vec<Vec<u8>>,
+    /// Checkpoints, oldest first
+    checkpoints: VecDeque<Checkpoint>,
+}
+
+impl History {
+    pub fn new(interval: u64) -> Self {
+        Self { interval: interval.max(1), base: Vec::new(), checkpoints: VecDeque::new() }
+    }
+
+    /// Whether a checkpoint should be taken after `instructions` instructions.
+    pub fn due(&self, instructions: u64) -> bool {
+        self.checkpoints.back().is_none_or(|checkpoint| instructions >= checkpoint.taken + self.interval)
+    }
+
+    /// Records a checkpoint of `state` and `memory` taken after `instructions` instructions,
+    /// replacing any taken at or after that point, since the state may have been changed
+    /// from outside. Pages of `memory` written since the last checkpoint are the ones kept.
+    pub fn record(&mut self, instructions: u64, state: Vec<u8>, memory: &mut Memory) {
+        while self.checkpoints.back().is_some_and(|checkpoint| checkpoint.taken >= instructions) {
+            // The pages it changed may differ from the checkpoint now before this one
+            if let Some(checkpoint) = self.checkpoints.pop_back() {
+                for &page in checkpoint.pages.keys() {
+                    memory.mark_dirty(page);
+                }
+            }
+        }
+        let dirty = memory.take_dirty();
+        let pages = if self.checkpoints.is_empty() {
+            let pages = memory.len().div_ceil(SNAPSHOT_PAGE);
+            self.base = (0..pages).map(|page| memory.page(page).to_vec()).collect();
+            HashMap::new()
+        } else {
+            dirty.into_iter().map(|page| (page, memory.page(page).to_vec())).collect()
+        };
+        self.checkpoints.push_back(Checkpoint { taken: instructions, state, pages });
+        if self.checkpoints.len() > HISTORY_LIMIT {
+            self.checkpoints.pop_front();
+            if let Some(oldest) = self.checkpoints.front_mut() {
+                for (page, data) in oldest.pages.drain() {
+                    self.base[page] = data;
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+    }
+
+    /// Puts `memory` back as it was at the latest checkpoint taken at or before `instructions`
+    /// instructions, returning when it was taken and the rest of its state to restore.
+    pub fn rewind(&self, instructions: u64, memory: &mut Memory) -> Option<(u64, &[u8])> {
+        let index = self.checkpoints.iter().rposition(|checkpoint| checkpoint.taken <= instructions)?;
+        // Pages written since the checkpoint are still written as far as the next one goes
+        let mut pages = memory.take_dirty();
+        for checkpoint in self.checkpoints.range(index + 1..) {
+            pages.extend(checkpoint.pages.keys());
+        }
+        pages.sort_unstable();
+        pages.dedup();
+        for page in pages {
+            let data = self.checkpoints.range(..=index).rev()
+                .find_map(|checkpoint| checkpoint.pages.get(&page))
+                .unwrap_or(&self.base[page]);
+            memory.set_page(page, data);
+            memory.mark_dirty(page);
+        }
+        let checkpoint = &self.checkpoints[index];
+        Some((checkpoint.taken, checkpoint.state.as_slice()))
+    }
+
+    /// The instruction count of the oldest checkpoint, the furthest back execution can go.
+    pub fn start(&self) -> Option<u64> {
+        self.checkpoints.front().map(|checkpoint| checkpoint.taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::History;
+    use crate::memory::{Memory, SNAPSHOT_PAGE};
+
+    #[test]
+    fn checkpoints_are_found_by_instruction_count() {
+        let mut memory = Memory::new(2 * SNAPSHOT_PAGE);
+        let mut history = History::new(10);
+        assert!(history.due(0));
+        history.record(0, vec![0], &mut memory);
+        assert!(!history.due(9));
+        memory.set32(1, 0).unwrap();
+        history.record(10, vec![1], &mut memory);
+        memory.set32(2, SNAPSHOT_PAGE).unwrap();
+        history.record(20, vec![2], &mut memory);
+        memory.set32(3, 0).unwrap();
+
+        assert_eq!(history.rewind(19, &mut memory), Some((10, &[1][..])));
+        assert_eq!((memory.get32(0).unwrap(), memory.get32(SNAPSHOT_PAGE).unwrap()), (1, 0));
+        assert_eq!(history.rewind(20, &mut memory), Some((20, &[2][..])));
+        assert_eq!((memory.get32(0).unwrap(), memory.get32(SNAPSHOT_PAGE).unwrap()), (1, 2));
+        assert_eq!(history.rewind(5, &mut memory), Some((0, &[0][..])));
+        assert_eq!((memory.get32(0).unwrap(), memory.get32(SNAPSHOT_PAGE).unwrap()), (0, 0));
+        assert_eq!(history.start(), Some(0));
+
+        // Replacing the later checkpoints keeps the pages they changed
+        memory.set32(4, 4).unwrap();
+        history.record(15, vec![3], &mut memory);
+        assert_eq!(history.rewind(100, &mut memory), Some((15, &[3][..])));
+        assert_eq!((memory.get32(0).unwrap(), memory.get32(4).unwrap(), memory.get32(SNAPSHOT_PAGE).unwrap()), (0, 4, 0));
+        assert!(history.due(25));
+    }
+}
@@ -1,10 +1,14 @@
 use std::fmt::{Display, Formatter};
 
+pub mod vector;
+
+#[derive(Clone, Copy)]
 pub struct Instruction {
     instruction: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
 pub enum InstructionType {
     LUI,
     AUIPC,
@@ -45,13 +49,243 @@ pub enum InstructionType {
     AND,
     ECALL,
     EBREAK,
+    MRET,
+    WFI,
     CSRRW,
     CSRRS,
     CSRRC,
     CSRRWI,
     CSRRSI,
     CSRRCI,
-
+    SH1ADD,
+    SH2ADD,
+    SH3ADD,
+    ANDN,
+    ORN,
+    XNOR,
+    CLZ,
+    CTZ,
+    CPOP,
+    MIN,
+    MAX,
+    MINU,
+    MAXU,
+    SEXT_B,
+    SEXT_H,
+    ZEXT_H,
+    ROL,
+    ROR,
+    RORI,
+    ORC_B,
+    REV8,
+    CLMUL,
+    CLMULH,
+    CLMULR,
+    BSET,
+    BCLR,
+    BINV,
+    BEXT,
+    BSETI,
+    BCLRI,
+    BINVI,
+    BEXTI,
+    PACK,
+    PACKH,
+    BREV8,
+    ZIP,
+    UNZIP,
+    XPERM4,
+    XPERM8,
+    AES32ESI,
+    AES32ESMI,
+    AES32DSI,
+    AES32DSMI,
+    SHA256SIG0,
+    SHA256SIG1,
+    SHA256SUM0,
+    SHA256SUM1,
+    SHA512SIG0H,
+    SHA512SIG0L,
+    SHA512SIG1H,
+    SHA512SIG1L,
+    SHA512SUM0R,
+    SHA512SUM1R,
+    SM4ED,
+    SM4KS,
+    SM3P0,
+    SM3P1,
+    CZERO_EQZ,
+    CZERO_NEZ,
+    CBO_CLEAN,
+    CBO_FLUSH,
+    CBO_INVAL,
+    CBO_ZERO,
+    PAUSE,
+    FENCE,
+    FENCE_I,
+    LR_W,
+    SC_W,
+    AMOSWAP_W,
+    AMOADD_W,
+    AMOXOR_W,
+    AMOAND_W,
+    AMOOR_W,
+    AMOMIN_W,
+    AMOMAX_W,
+    AMOMINU_W,
+    AMOMAXU_W,
+    VSETVLI,
+    VSETIVLI,
+    VSETVL,
+    VLE,
+    VLEFF,
+    VLM,
+    VLR,
+    VLSE,
+    VLUXEI,
+    VLOXEI,
+    VSE,
+    VSM,
+    VSR,
+    VSSE,
+    VSUXEI,
+    VSOXEI,
+    VADD,
+    VSUB,
+    VRSUB,
+    VMINU,
+    VMIN,
+    VMAXU,
+    VMAX,
+    VAND,
+    VOR,
+    VXOR,
+    VRGATHER,
+    VRGATHEREI16,
+    VSLIDEUP,
+    VSLIDEDOWN,
+    VADC,
+    VMADC,
+    VSBC,
+    VMSBC,
+    VMERGE,
+    VMV_V,
+    VMSEQ,
+    VMSNE,
+    VMSLTU,
+    VMSLT,
+    VMSLEU,
+    VMSLE,
+    VMSGTU,
+    VMSGT,
+    VSADDU,
+    VSADD,
+    VSSUBU,
+    VSSUB,
+    VSLL,
+    VSMUL,
+    VMVR,
+    VSRL,
+    VSRA,
+    VSSRL,
+    VSSRA,
+    VNSRL,
+    VNSRA,
+    VNCLIPU,
+    VNCLIP,
+    VWREDSUMU,
+    VWREDSUM,
+    VREDSUM,
+    VREDAND,
+    VREDOR,
+    VREDXOR,
+    VREDMINU,
+    VREDMIN,
+    VREDMAXU,
+    VREDMAX,
+    VAADDU,
+    VAADD,
+    VASUBU,
+    VASUB,
+    VSLIDE1UP,
+    VSLIDE1DOWN,
+    VMV_X_S,
+    VCPOP_M,
+    VFIRST_M,
+    VMV_S_X,
+    VZEXT_VF8,
+    VSEXT_VF8,
+    VZEXT_VF4,
+    VSEXT_VF4,
+    VZEXT_VF2,
+    VSEXT_VF2,
+    VMSBF_M,
+    VMSOF_M,
+    VMSIF_M,
+    VIOTA_M,
+    VID_V,
+    VCOMPRESS,
+    VMANDN,
+    VMAND,
+    VMOR,
+    VMXOR,
+    VMORN,
+    VMNAND,
+    VMNOR,
+    VMXNOR,
+    VDIVU,
+    VDIV,
+    VREMU,
+    VREM,
+    VMULHU,
+    VMUL,
+    VMULHSU,
+    VMULH,
+    VMADD,
+    VNMSUB,
+    VMACC,
+    VNMSAC,
+    VWADDU,
+    VWADD,
+    VWSUBU,
+    VWSUB,
+    VWADDU_W,
+    VWADD_W,
+    VWSUBU_W,
+    VWSUB_W,
+    VWMULU,
+    VWMULSU,
+    VWMUL,
+    VWMACCU,
+    VWMACC,
+    VWMACCUS,
+    VWMACCSU,
+    VFADD,
+    VFREDUSUM,
+    VFSUB,
+    VFREDOSUM,
+    VFMIN,
+    VFREDMIN,
+    VFMAX,
+    VFREDMAX,
+    VFSGNJ,
+    VFSGNJN,
+    VFSGNJX,
+    VFSQRT_V,
+    VMFEQ,
+    VMFLE,
+    VMFLT,
+    VMFNE,
+    VFDIV,
+    VFMUL,
+    VFMADD,
+    VFNMADD,
+    VFMSUB,
+    VFNMSUB,
+    VFMACC,
+    VFNMACC,
+    VFMSAC,
+    VFNMSAC,
 }
 
 impl Instruction {
@@ -105,33 +339,139 @@ impl Instruction {
                 0b100 => Ok(InstructionType::XORI),
                 0b110 => Ok(InstructionType::ORI),
                 0b111 => Ok(InstructionType::ANDI),
-                0b001 => Ok(InstructionType::SLLI),
+                0b001 => match self.get_funct7() {
+                    0b0000000 => Ok(InstructionType::SLLI),
+                    0b0010100 => Ok(InstructionType::BSETI),
+                    0b0100100 => Ok(InstructionType::BCLRI),
+                    0b0110100 => Ok(InstructionType::BINVI),
+                    0b0000100 if self.get_rs2() == 0b01111 => Ok(InstructionType::ZIP),
+                    0b0001000 => match self.get_rs2() {
+                        0b00000 => Ok(InstructionType::SHA256SUM0),
+                        0b00001 => Ok(InstructionType::SHA256SUM1),
+                        0b00010 => Ok(InstructionType::SHA256SIG0),
+                        0b00011 => Ok(InstructionType::SHA256SIG1),
+                        0b01000 => Ok(InstructionType::SM3P0),
+                        0b01001 => Ok(InstructionType::SM3P1),
+                        _ => error
+                    }
+                    0b0110000 => match self.get_rs2() {
+                        0b00000 => Ok(InstructionType::CLZ),
+                        0b00001 => Ok(InstructionType::CTZ),
+                        0b00010 => Ok(InstructionType::CPOP),
+                        0b00100 => Ok(InstructionType::SEXT_B),
+                        0b00101 => Ok(InstructionType::SEXT_H),
+                        _ => error
+                    }
+                    _ => error
+                }
                 0b101 => match self.get_funct7() {
                     0b0000000 => Ok(InstructionType::SRLI),
                     0b0100000 => Ok(InstructionType::SRAI),
+                    0b0100100 => Ok(InstructionType::BEXTI),
+                    0b0110000 => Ok(InstructionType::RORI),
+                    0b0010100 if self.get_rs2() == 0b00111 => Ok(InstructionType::ORC_B),
+                    0b0110100 if self.get_rs2() == 0b11000 => Ok(InstructionType::REV8),
+                    0b0110100 if self.get_rs2() == 0b00111 => Ok(InstructionType::BREV8),
+                    0b0000100 if self.get_rs2() == 0b01111 => Ok(InstructionType::UNZIP),
                     _ => error
                 }
                 _ => error
             }
 
-            0b0110011 => match self.get_funct3() {
-                0b000 => match self.get_funct7() {
-                    0b0000000 => Ok(InstructionType::ADD),
-                    0b0100000 => Ok(InstructionType::SUB),
+            0b0110011 => match self.get_funct7() {
+                0b0000000 => match self.get_funct3() {
+                    0b000 => Ok(InstructionType::ADD),
+                    0b001 => Ok(InstructionType::SLL),
+                    0b010 => Ok(InstructionType::SLT),
+                    0b011 => Ok(InstructionType::SLTU),
+                    0b100 => Ok(InstructionType::XOR),
+                    0b101 => Ok(InstructionType::SRL),
+                    0b110 => Ok(InstructionType::OR),
+                    0b111 => Ok(InstructionType::AND),
                     _ => error
                 }
 
-                0b001 => Ok(InstructionType::SLL),
-                0b010 => Ok(InstructionType::SLT),
-                0b011 => Ok(InstructionType::SLTU),
-                0b100 => Ok(InstructionType::XOR),
-                0b101 => match self.get_funct7() {
-                    0b0000000 => Ok(InstructionType::SRL),
-                    0b0100000 => Ok(InstructionType::SRA),
+                0b0100000 => match self.get_funct3() {
+                    0b000 => Ok(InstructionType::SUB),
+                    0b100 => Ok(InstructionType::XNOR),
+                    0b101 => Ok(InstructionType::SRA),
+                    0b110 => Ok(InstructionType::ORN),
+                    0b111 => Ok(InstructionType::ANDN),
+                    _ => error
+                }
+
+                0b0010000 => match self.get_funct3() {
+                    0b010 => Ok(InstructionType::SH1ADD),
+                    0b100 => Ok(InstructionType::SH2ADD),
+                    0b110 => Ok(InstructionType::SH3ADD),
+                    _ => error
+                }
+
+                0b0000101 => match self.get_funct3() {
+                    0b001 => Ok(InstructionType::CLMUL),
+                    0b010 => Ok(InstructionType::CLMULR),
+                    0b011 => Ok(InstructionType::CLMULH),
+                    0b100 => Ok(InstructionType::MIN),
+                    0b101 => Ok(InstructionType::MINU),
+                    0b110 => Ok(InstructionType::MAX),
+                    0b111 => Ok(InstructionType::MAXU),
+                    _ => error
+                }
+
+                0b0000100 => match self.get_funct3() {
+                    0b100 if self.get_rs2() == 0 => Ok(InstructionType::ZEXT_H),
+                    0b100 => Ok(InstructionType::PACK),
+                    0b111 => Ok(InstructionType::PACKH),
+                    _ => error
+                }
+
+                0b0110000 => match self.get_funct3() {
+                    0b001 => Ok(InstructionType::ROL),
+                    0b101 => Ok(InstructionType::ROR),
+                    _ => error
+                }
+
+                0b0010100 => match self.get_funct3() {
+                    0b001 => Ok(InstructionType::BSET),
+                    0b010 => Ok(InstructionType::XPERM4),
+                    0b100 => Ok(InstructionType::XPERM8),
+                    _ => error
+                }
+
+                0b0101000 if self.get_funct3() == 0 => Ok(InstructionType::SHA512SUM0R),
+                0b0101001 if self.get_funct3() == 0 => Ok(InstructionType::SHA512SUM1R),
+                0b0101010 if self.get_funct3() == 0 => Ok(InstructionType::SHA512SIG0L),
+                0b0101011 if self.get_funct3() == 0 => Ok(InstructionType::SHA512SIG1L),
+                0b0101110 if self.get_funct3() == 0 => Ok(InstructionType::SHA512SIG0H),
+                0b0101111 if self.get_funct3() == 0 => Ok(InstructionType::SHA512SIG1H),
+
+                0b0100100 => match self.get_funct3() {
+                    0b001 => Ok(InstructionType::BCLR),
+                    0b101 => Ok(InstructionType::BEXT),
+                    _ => error
+                }
+
+                0b0110100 => match self.get_funct3() {
+                    0b001 => Ok(InstructionType::BINV),
                     _ => error
                 }
-                0b110 => Ok(InstructionType::OR),
-                0b111 => Ok(InstructionType::AND),
+
+                0b0000111 => match self.get_funct3() {
+                    0b101 => Ok(InstructionType::CZERO_EQZ),
+                    0b111 => Ok(InstructionType::CZERO_NEZ),
+                    _ => error
+                }
+
+                funct7 if self.get_funct3() == 0 => match funct7 & 0b11111 {
+                    0b10001 => Ok(InstructionType::AES32ESI),
+                    0b10011 => Ok(InstructionType::AES32ESMI),
+                    0b10101 => Ok(InstructionType::AES32DSI),
+                    0b10111 => Ok(InstructionType::AES32DSMI),
+                    0b11000 => Ok(InstructionType::SM4ED),
+                    0b11010 => Ok(InstructionType::SM4KS),
+                    _ => error
+                }
+
                 _ => error
             }
 
@@ -139,6 +479,8 @@ impl Instruction {
                 0b000 => match self.get_imm_i() {
                     0 => Ok(InstructionType::ECALL),
                     1 => Ok(InstructionType::EBREAK),
+                    0x302 if self.get_rd() == 0 && self.get_rs1() == 0 => Ok(InstructionType::MRET),
+                    0x105 if self.get_rd() == 0 && self.get_rs1() == 0 => Ok(InstructionType::WFI),
                     _ => error
                 }
                 0b001 => Ok(InstructionType::CSRRW),
@@ -150,17 +492,60 @@ impl Instruction {
                 _ => error
             }
 
+            0b0001111 => match self.get_funct3() {
+                0b000 if self.instruction == 0x0100000F => Ok(InstructionType::PAUSE),
+                0b000 => Ok(InstructionType::FENCE),
+                0b001 => Ok(InstructionType::FENCE_I),
+                0b010 if self.get_rd() == 0 => match self.get_imm_i() {
+                    0 => Ok(InstructionType::CBO_INVAL),
+                    1 => Ok(InstructionType::CBO_CLEAN),
+                    2 => Ok(InstructionType::CBO_FLUSH),
+                    4 => Ok(InstructionType::CBO_ZERO),
+                    _ => error
+                }
+                _ => error
+            }
+
+            0b0101111 if self.get_funct3() == 0b010 => match self.get_funct7() >> 2 {
+                0b00010 if self.get_rs2() == 0 => Ok(InstructionType::LR_W),
+                0b00011 => Ok(InstructionType::SC_W),
+                0b00001 => Ok(InstructionType::AMOSWAP_W),
+                0b00000 => Ok(InstructionType::AMOADD_W),
+                0b00100 => Ok(InstructionType::AMOXOR_W),
+                0b01100 => Ok(InstructionType::AMOAND_W),
+                0b01000 => Ok(InstructionType::AMOOR_W),
+                0b10000 => Ok(InstructionType::AMOMIN_W),
+                0b10100 => Ok(InstructionType::AMOMAX_W),
+                0b11000 => Ok(InstructionType::AMOMINU_W),
+                0b11100 => Ok(InstructionType::AMOMAXU_W),
+                _ => error
+            }
+
+            0b1010111 => self.vector_type().map_or(error, Ok),
+            0b0000111 => self.vector_load_store_type(false).map_or(error, Ok),
+            0b0100111 => self.vector_load_store_type(true).map_or(error, Ok),
+
             _ => error
         };
     }
 
     pub fn get_mnemonic(&self) -> Option<String> {
         match self._type() {
-            Ok(t) => Some(format!("{:?}", t).to_lowercase()),
+            Ok(t) => {
+                let mnemonic = format!("{:?}", t).to_lowercase().replace('_', ".");
+                match self.opcode() {
+                    0b1010111 | 0b0000111 | 0b0100111 => Some(self.vector_mnemonic(&t, mnemonic)),
+                    _ => Some(mnemonic)
+                }
+            }
             _ => None
         }
     }
 
+    pub fn get_raw(&self) -> u32 {
+        self.instruction
+    }
+
     pub fn get_rd(&self) -> u8 {
         return (self.instruction << 20 >> 27) as u8;
     }
@@ -181,6 +566,14 @@ impl Instruction {
         return (self.instruction >> 25) as u8;
     }
 
+    pub fn get_csr(&self) -> u16 {
+        return (self.instruction >> 20) as u16;
+    }
+
+    pub fn get_bs(&self) -> u8 {
+        return (self.instruction >> 30) as u8;
+    }
+
     pub fn get_imm_i(&self) -> u32 {
         let mut insn = self.instruction;
         insn >>= 20;
@@ -277,7 +670,12 @@ impl Display for Instruction {
 
                     InstructionType::SLLI |
                     InstructionType::SRLI |
-                    InstructionType::SRAI
+                    InstructionType::SRAI |
+                    InstructionType::RORI |
+                    InstructionType::BSETI |
+                    InstructionType::BCLRI |
+                    InstructionType::BINVI |
+                    InstructionType::BEXTI
                     => write!(f, "x{},x{},{:#x}", self.get_rd(), self.get_rs1(), self.get_shamt()),
 
                     InstructionType::ADD |
@@ -289,20 +687,116 @@ impl Display for Instruction {
                     InstructionType::SRL |
                     InstructionType::SRA |
                     InstructionType::OR |
-                    InstructionType::AND
+                    InstructionType::AND |
+                    InstructionType::SH1ADD |
+                    InstructionType::SH2ADD |
+                    InstructionType::SH3ADD |
+                    InstructionType::ANDN |
+                    InstructionType::ORN |
+                    InstructionType::XNOR |
+                    InstructionType::MIN |
+                    InstructionType::MAX |
+                    InstructionType::MINU |
+                    InstructionType::MAXU |
+                    InstructionType::ROL |
+                    InstructionType::ROR |
+                    InstructionType::CLMUL |
+                    InstructionType::CLMULH |
+                    InstructionType::CLMULR |
+                    InstructionType::BSET |
+                    InstructionType::BCLR |
+                    InstructionType::BINV |
+                    InstructionType::BEXT |
+                    InstructionType::PACK |
+                    InstructionType::PACKH |
+                    InstructionType::XPERM4 |
+                    InstructionType::XPERM8 |
+                    InstructionType::SHA512SIG0H |
+                    InstructionType::SHA512SIG0L |
+                    InstructionType::SHA512SIG1H |
+                    InstructionType::SHA512SIG1L |
+                    InstructionType::SHA512SUM0R |
+                    InstructionType::SHA512SUM1R |
+                    InstructionType::CZERO_EQZ |
+                    InstructionType::CZERO_NEZ
                     => write!(f, "x{},x{},x{}", self.get_rd(), self.get_rs1(), self.get_rs2()),
 
+                    InstructionType::CLZ |
+                    InstructionType::CTZ |
+                    InstructionType::CPOP |
+                    InstructionType::SEXT_B |
+                    InstructionType::SEXT_H |
+                    InstructionType::ZEXT_H |
+                    InstructionType::ORC_B |
+                    InstructionType::REV8 |
+                    InstructionType::BREV8 |
+                    InstructionType::ZIP |
+                    InstructionType::UNZIP |
+                    InstructionType::SHA256SIG0 |
+                    InstructionType::SHA256SIG1 |
+                    InstructionType::SHA256SUM0 |
+                    InstructionType::SHA256SUM1 |
+                    InstructionType::SM3P0 |
+                    InstructionType::SM3P1
+                    => write!(f, "x{},x{}", self.get_rd(), self.get_rs1()),
+
+                    InstructionType::AES32ESI |
+                    InstructionType::AES32ESMI |
+                    InstructionType::AES32DSI |
+                    InstructionType::AES32DSMI |
+                    InstructionType::SM4ED |
+                    InstructionType::SM4KS
+                    => write!(f, "x{},x{},x{},{}", self.get_rd(), self.get_rs1(), self.get_rs2(), self.get_bs()),
+
                     InstructionType::ECALL |
-                    InstructionType::EBREAK
+                    InstructionType::EBREAK |
+                    InstructionType::MRET |
+                    InstructionType::WFI |
+                    InstructionType::PAUSE |
+                    InstructionType::FENCE_I
                     => write!(f, ""),
 
+                    InstructionType::FENCE => {
+                        let set = |bits: u32| "iorw".chars()
+                            .enumerate()
+                            .filter(|(index, _)| bits >> (3 - index) & 1 != 0)
+                            .map(|(_, c)| c)
+                            .collect::<String>();
+                        write!(f, "{},{}", set(self.instruction >> 24 & 0xF), set(self.instruction >> 20 & 0xF))
+                    }
+
+                    InstructionType::LR_W
+                    => write!(f, "x{},(x{})", self.get_rd(), self.get_rs1()),
+
+                    InstructionType::SC_W |
+                    InstructionType::AMOSWAP_W |
+                    InstructionType::AMOADD_W |
+                    InstructionType::AMOXOR_W |
+                    InstructionType::AMOAND_W |
+                    InstructionType::AMOOR_W |
+                    InstructionType::AMOMIN_W |
+                    InstructionType::AMOMAX_W |
+                    InstructionType::AMOMINU_W |
+                    InstructionType::AMOMAXU_W
+                    => write!(f, "x{},x{},(x{})", self.get_rd(), self.get_rs2(), self.get_rs1()),
+
+                    InstructionType::CBO_CLEAN |
+                    InstructionType::CBO_FLUSH |
+                    InstructionType::CBO_INVAL |
+                    InstructionType::CBO_ZERO
+                    => write!(f, "(x{})", self.get_rs1()),
+
                     InstructionType::CSRRW |
                     InstructionType::CSRRS |
-                    InstructionType::CSRRC |
+                    InstructionType::CSRRC
+                    => write!(f, "x{},{:#x},x{}", self.get_rd(), self.get_csr(), self.get_rs1()),
+
                     InstructionType::CSRRWI |
                     InstructionType::CSRRSI |
                     InstructionType::CSRRCI
-                    => write!(f, "x{},{:#x},x{}", self.get_rd(), self.get_imm_i(), self.get_rs1()),
+                    => write!(f, "x{},{:#x},{}", self.get_rd(), self.get_csr(), self.get_rs1()),
+
+                    _ => self.fmt_vector(f, &_type),
                 }
             }
             Err(e) => write!(f, "{}", e)
@@ -330,4 +824,67 @@ mod tests {
         assert_eq!(Instruction::from_u32(0x000002ef).get_imm_i(), 0x0);
         assert_eq!(Instruction::from_u32(0x008002ef).get_imm_i(), 0x08);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_bitmanip_mnemonics() {
+        assert_eq!(Instruction::from_u32(0x20c5a533).get_mnemonic().unwrap(), "sh1add");
+        assert_eq!(Instruction::from_u32(0x40c5f533).get_mnemonic().unwrap(), "andn");
+        assert_eq!(Instruction::from_u32(0x60059513).get_mnemonic().unwrap(), "clz");
+        assert_eq!(Instruction::from_u32(0x60459513).get_mnemonic().unwrap(), "sext.b");
+        assert_eq!(Instruction::from_u32(0x0805c533).get_mnemonic().unwrap(), "zext.h");
+        assert_eq!(Instruction::from_u32(0x2875d513).get_mnemonic().unwrap(), "orc.b");
+        assert_eq!(Instruction::from_u32(0x6985d513).get_mnemonic().unwrap(), "rev8");
+        assert_eq!(Instruction::from_u32(0x60c5d513).get_mnemonic().unwrap(), "rori");
+        assert_eq!(Instruction::from_u32(0x0ac5b533).get_mnemonic().unwrap(), "clmulh");
+        assert_eq!(Instruction::from_u32(0x48c5d533).get_mnemonic().unwrap(), "bext");
+        assert_eq!(Instruction::from_u32(0x28359513).get_mnemonic().unwrap(), "bseti");
+    }
+
+    #[test]
+    fn test_crypto_mnemonics() {
+        assert_eq!(Instruction::from_u32(0x08c5c533).get_mnemonic().unwrap(), "pack");
+        assert_eq!(Instruction::from_u32(0x0805c533).get_mnemonic().unwrap(), "zext.h");
+        assert_eq!(Instruction::from_u32(0x08f59513).get_mnemonic().unwrap(), "zip");
+        assert_eq!(Instruction::from_u32(0x08f5d513).get_mnemonic().unwrap(), "unzip");
+        assert_eq!(Instruction::from_u32(0x6875d513).get_mnemonic().unwrap(), "brev8");
+        assert_eq!(Instruction::from_u32(0x28c5c533).get_mnemonic().unwrap(), "xperm8");
+        assert_eq!(Instruction::from_u32(0xe6c58533).get_mnemonic().unwrap(), "aes32esmi");
+        assert_eq!(Instruction::from_u32(0x10259513).get_mnemonic().unwrap(), "sha256sig0");
+        assert_eq!(Instruction::from_u32(0x5cc58533).get_mnemonic().unwrap(), "sha512sig0h");
+        assert_eq!(Instruction::from_u32(0x70c58533).get_mnemonic().unwrap(), "sm4ed");
+        assert_eq!(format!("{}", Instruction::from_u32(0xe6c58533)), "aes32esmi x10,x11,x12,3");
+    }
+
+    #[test]
+    fn test_zicond_zicbo_mnemonics() {
+        assert_eq!(Instruction::from_u32(0x0ec5d533).get_mnemonic().unwrap(), "czero.eqz");
+        assert_eq!(Instruction::from_u32(0x0ec5f533).get_mnemonic().unwrap(), "czero.nez");
+        assert_eq!(Instruction::from_u32(0x0025200f).get_mnemonic().unwrap(), "cbo.flush");
+        assert_eq!(Instruction::from_u32(0x0045200f).get_mnemonic().unwrap(), "cbo.zero");
+        assert_eq!(Instruction::from_u32(0x0100000f).get_mnemonic().unwrap(), "pause");
+        assert_eq!(format!("{}", Instruction::from_u32(0x0015200f)), "cbo.clean (x10)");
+        assert_eq!(format!("{}", Instruction::from_u32(0x0ff0000f)), "fence iorw,iorw");
+        assert_eq!(format!("{}", Instruction::from_u32(0x0310000f)), "fence rw,w");
+        assert_eq!(Instruction::from_u32(0x0000100f).get_mnemonic().unwrap(), "fence.i");
+    }
+
+    #[test]
+    fn test_atomic_mnemonics() {
+        assert_eq!(format!("{}", Instruction::from_u32(0x1005a52f)), "lr.w  x10,(x11)");
+        assert_eq!(format!("{}", Instruction::from_u32(0x18c5a52f)), "sc.w  x10,x12,(x11)");
+        assert_eq!(format!("{}", Instruction::from_u32(0xe0c5a52f)), "amomaxu.w x10,x12,(x11)");
+    }
+
+    #[test]
+    fn test_vector_mnemonics() {
+        assert_eq!(format!("{}", Instruction::from_u32(0x0d05f557)), "vsetvli x10,x11,e32,m1,ta,ma");
+        assert_eq!(format!("{}", Instruction::from_u32(0x02066087)), "vle32.v v1,(x12)");
+        assert_eq!(format!("{}", Instruction::from_u32(0x02108157)), "vadd.vv v2,v1,v1");
+        assert_eq!(format!("{}", Instruction::from_u32(0x00153357)), "vadd.vi v6,v1,10,v0.t");
+        assert_eq!(format!("{}", Instruction::from_u32(0x021021d7)), "vredsum.vs v3,v1,v0");
+        assert_eq!(format!("{}", Instruction::from_u32(0xba80b157)), "vnclipu.wi v2,v8,1");
+        assert_eq!(format!("{}", Instruction::from_u32(0x22055107)), "vlseg2e16.v v2,(x10)");
+        assert_eq!(format!("{}", Instruction::from_u32(0x962fb0d7)), "vsll.vi v1,v2,31");
+        assert_eq!(Instruction::from_u32(0x5e1024d7).get_mnemonic().unwrap(), "vcompress.vm");
+    }
+}
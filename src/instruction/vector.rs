@@ -0,0 +1,439 @@
+use std::fmt::{Formatter, Result as FmtResult};
+
+use crate::instruction::{Instruction, InstructionType};
+
+pub const OPIVV: u8 = 0b000;
+pub const OPFVV: u8 = 0b001;
+pub const OPMVV: u8 = 0b010;
+pub const OPIVI: u8 = 0b011;
+pub const OPIVX: u8 = 0b100;
+pub const OPFVF: u8 = 0b101;
+pub const OPMVX: u8 = 0b110;
+pub const OPCFG: u8 = 0b111;
+
+impl Instruction {
+    pub fn get_funct6(&self) -> u8 {
+        (self.instruction >> 26) as u8
+    }
+
+    /// `true` when the instruction is unmasked (`vm` bit set).
+    pub fn get_vm(&self) -> bool {
+        self.instruction >> 25 & 1 != 0
+    }
+
+    pub fn get_nf(&self) -> u8 {
+        (self.instruction >> 29) as u8
+    }
+
+    pub fn get_mop(&self) -> u8 {
+        (self.instruction >> 26 & 0b11) as u8
+    }
+
+    pub fn get_simm5(&self) -> u32 {
+        let imm = self.get_rs1() as u32;
+        if imm & 0x10 != 0 { imm | 0xFFFFFFE0 } else { imm }
+    }
+
+    /// Shifts, slides and gathers read their `.vi` immediate as unsigned.
+    pub fn has_vector_uimm(&self) -> bool {
+        matches!(self.get_funct6(), 0b001100 | 0b001110 | 0b001111 | 0b100101 | 0b101000..=0b101111)
+    }
+
+    pub fn get_zimm_vtype(&self) -> u32 {
+        if self.instruction >> 30 == 0b11 {
+            self.instruction >> 20 & 0x3FF
+        } else {
+            self.instruction >> 20 & 0x7FF
+        }
+    }
+
+    /// Element width in bits encoded in the `width` field of vector loads and stores.
+    pub fn get_vector_eew(&self) -> Option<usize> {
+        match self.get_funct3() {
+            0b000 => Some(8),
+            0b101 => Some(16),
+            0b110 => Some(32),
+            0b111 => Some(64),
+            _ => None
+        }
+    }
+
+    pub(super) fn vector_load_store_type(&self, store: bool) -> Option<InstructionType> {
+        self.get_vector_eew()?;
+        if self.instruction >> 28 & 1 != 0 {
+            return None;
+        }
+
+        match (self.get_mop(), store) {
+            (0b00, false) => match self.get_rs2() {
+                0b00000 => Some(InstructionType::VLE),
+                0b01000 => Some(InstructionType::VLR),
+                0b01011 => Some(InstructionType::VLM),
+                0b10000 => Some(InstructionType::VLEFF),
+                _ => None
+            }
+            (0b00, true) => match self.get_rs2() {
+                0b00000 => Some(InstructionType::VSE),
+                0b01000 => Some(InstructionType::VSR),
+                0b01011 => Some(InstructionType::VSM),
+                _ => None
+            }
+            (0b01, false) => Some(InstructionType::VLUXEI),
+            (0b10, false) => Some(InstructionType::VLSE),
+            (0b11, false) => Some(InstructionType::VLOXEI),
+            (0b01, true) => Some(InstructionType::VSUXEI),
+            (0b10, true) => Some(InstructionType::VSSE),
+            (0b11, true) => Some(InstructionType::VSOXEI),
+            _ => None
+        }
+    }
+
+    pub(super) fn vector_type(&self) -> Option<InstructionType> {
+        let funct3 = self.get_funct3();
+        let funct6 = self.get_funct6();
+        let vv = funct3 == OPIVV;
+        let vi = funct3 == OPIVI;
+
+        let _type = match funct3 {
+            OPCFG => match self.instruction >> 30 {
+                0b00 | 0b01 => InstructionType::VSETVLI,
+                0b11 => InstructionType::VSETIVLI,
+                _ if self.get_funct7() == 0b1000000 => InstructionType::VSETVL,
+                _ => return None
+            }
+
+            OPIVV | OPIVX | OPIVI => match funct6 {
+                0b000000 => InstructionType::VADD,
+                0b000010 if !vi => InstructionType::VSUB,
+                0b000011 if !vv => InstructionType::VRSUB,
+                0b000100 if !vi => InstructionType::VMINU,
+                0b000101 if !vi => InstructionType::VMIN,
+                0b000110 if !vi => InstructionType::VMAXU,
+                0b000111 if !vi => InstructionType::VMAX,
+                0b001001 => InstructionType::VAND,
+                0b001010 => InstructionType::VOR,
+                0b001011 => InstructionType::VXOR,
+                0b001100 => InstructionType::VRGATHER,
+                0b001110 if vv => InstructionType::VRGATHEREI16,
+                0b001110 => InstructionType::VSLIDEUP,
+                0b001111 if !vv => InstructionType::VSLIDEDOWN,
+                0b010000 if !self.get_vm() => InstructionType::VADC,
+                0b010001 => InstructionType::VMADC,
+                0b010010 if !vi && !self.get_vm() => InstructionType::VSBC,
+                0b010011 if !vi => InstructionType::VMSBC,
+                0b010111 if self.get_vm() && self.get_rs2() == 0 => InstructionType::VMV_V,
+                0b010111 if !self.get_vm() => InstructionType::VMERGE,
+                0b011000 => InstructionType::VMSEQ,
+                0b011001 => InstructionType::VMSNE,
+                0b011010 if !vi => InstructionType::VMSLTU,
+                0b011011 if !vi => InstructionType::VMSLT,
+                0b011100 => InstructionType::VMSLEU,
+                0b011101 => InstructionType::VMSLE,
+                0b011110 if !vv => InstructionType::VMSGTU,
+                0b011111 if !vv => InstructionType::VMSGT,
+                0b100000 => InstructionType::VSADDU,
+                0b100001 => InstructionType::VSADD,
+                0b100010 if !vi => InstructionType::VSSUBU,
+                0b100011 if !vi => InstructionType::VSSUB,
+                0b100101 => InstructionType::VSLL,
+                0b100111 if vi && self.get_vm() => InstructionType::VMVR,
+                0b100111 if !vi => InstructionType::VSMUL,
+                0b101000 => InstructionType::VSRL,
+                0b101001 => InstructionType::VSRA,
+                0b101010 => InstructionType::VSSRL,
+                0b101011 => InstructionType::VSSRA,
+                0b101100 => InstructionType::VNSRL,
+                0b101101 => InstructionType::VNSRA,
+                0b101110 => InstructionType::VNCLIPU,
+                0b101111 => InstructionType::VNCLIP,
+                0b110000 if vv => InstructionType::VWREDSUMU,
+                0b110001 if vv => InstructionType::VWREDSUM,
+                _ => return None
+            }
+
+            OPMVV | OPMVX => {
+                let vv = funct3 == OPMVV;
+                match funct6 {
+                    0b000000 if vv => InstructionType::VREDSUM,
+                    0b000001 if vv => InstructionType::VREDAND,
+                    0b000010 if vv => InstructionType::VREDOR,
+                    0b000011 if vv => InstructionType::VREDXOR,
+                    0b000100 if vv => InstructionType::VREDMINU,
+                    0b000101 if vv => InstructionType::VREDMIN,
+                    0b000110 if vv => InstructionType::VREDMAXU,
+                    0b000111 if vv => InstructionType::VREDMAX,
+                    0b001000 => InstructionType::VAADDU,
+                    0b001001 => InstructionType::VAADD,
+                    0b001010 => InstructionType::VASUBU,
+                    0b001011 => InstructionType::VASUB,
+                    0b001110 if !vv => InstructionType::VSLIDE1UP,
+                    0b001111 if !vv => InstructionType::VSLIDE1DOWN,
+                    0b010000 if vv => match self.get_rs1() {
+                        0b00000 if self.get_vm() => InstructionType::VMV_X_S,
+                        0b10000 => InstructionType::VCPOP_M,
+                        0b10001 => InstructionType::VFIRST_M,
+                        _ => return None
+                    }
+                    0b010000 if self.get_rs2() == 0 && self.get_vm() => InstructionType::VMV_S_X,
+                    0b010010 if vv => match self.get_rs1() {
+                        0b00010 => InstructionType::VZEXT_VF8,
+                        0b00011 => InstructionType::VSEXT_VF8,
+                        0b00100 => InstructionType::VZEXT_VF4,
+                        0b00101 => InstructionType::VSEXT_VF4,
+                        0b00110 => InstructionType::VZEXT_VF2,
+                        0b00111 => InstructionType::VSEXT_VF2,
+                        _ => return None
+                    }
+                    0b010100 if vv => match self.get_rs1() {
+                        0b00001 => InstructionType::VMSBF_M,
+                        0b00010 => InstructionType::VMSOF_M,
+                        0b00011 => InstructionType::VMSIF_M,
+                        0b10000 => InstructionType::VIOTA_M,
+                        0b10001 if self.get_rs2() == 0 => InstructionType::VID_V,
+                        _ => return None
+                    }
+                    0b010111 if vv && self.get_vm() => InstructionType::VCOMPRESS,
+                    0b011000 if vv && self.get_vm() => InstructionType::VMANDN,
+                    0b011001 if vv && self.get_vm() => InstructionType::VMAND,
+                    0b011010 if vv && self.get_vm() => InstructionType::VMOR,
+                    0b011011 if vv && self.get_vm() => InstructionType::VMXOR,
+                    0b011100 if vv && self.get_vm() => InstructionType::VMORN,
+                    0b011101 if vv && self.get_vm() => InstructionType::VMNAND,
+                    0b011110 if vv && self.get_vm() => InstructionType::VMNOR,
+                    0b011111 if vv && self.get_vm() => InstructionType::VMXNOR,
+                    0b100000 => InstructionType::VDIVU,
+                    0b100001 => InstructionType::VDIV,
+                    0b100010 => InstructionType::VREMU,
+                    0b100011 => InstructionType::VREM,
+                    0b100100 => InstructionType::VMULHU,
+                    0b100101 => InstructionType::VMUL,
+                    0b100110 => InstructionType::VMULHSU,
+                    0b100111 => InstructionType::VMULH,
+                    0b101001 => InstructionType::VMADD,
+                    0b101011 => InstructionType::VNMSUB,
+                    0b101101 => InstructionType::VMACC,
+                    0b101111 => InstructionType::VNMSAC,
+                    0b110000 => InstructionType::VWADDU,
+                    0b110001 => InstructionType::VWADD,
+                    0b110010 => InstructionType::VWSUBU,
+                    0b110011 => InstructionType::VWSUB,
+                    0b110100 => InstructionType::VWADDU_W,
+                    0b110101 => InstructionType::VWADD_W,
+                    0b110110 => InstructionType::VWSUBU_W,
+                    0b110111 => InstructionType::VWSUB_W,
+                    0b111000 => InstructionType::VWMULU,
+                    0b111010 => InstructionType::VWMULSU,
+                    0b111011 => InstructionType::VWMUL,
+                    0b111100 => InstructionType::VWMACCU,
+                    0b111101 => InstructionType::VWMACC,
+                    0b111110 if !vv => InstructionType::VWMACCUS,
+                    0b111111 => InstructionType::VWMACCSU,
+                    _ => return None
+                }
+            }
+
+            // Vector-scalar floating-point forms need the F register file, which this core does not have.
+            OPFVV => match funct6 {
+                0b000000 => InstructionType::VFADD,
+                0b000001 => InstructionType::VFREDUSUM,
+                0b000010 => InstructionType::VFSUB,
+                0b000011 => InstructionType::VFREDOSUM,
+                0b000100 => InstructionType::VFMIN,
+                0b000101 => InstructionType::VFREDMIN,
+                0b000110 => InstructionType::VFMAX,
+                0b000111 => InstructionType::VFREDMAX,
+                0b001000 => InstructionType::VFSGNJ,
+                0b001001 => InstructionType::VFSGNJN,
+                0b001010 => InstructionType::VFSGNJX,
+                0b010011 if self.get_rs1() == 0 => InstructionType::VFSQRT_V,
+                0b011000 => InstructionType::VMFEQ,
+                0b011001 => InstructionType::VMFLE,
+                0b011011 => InstructionType::VMFLT,
+                0b011100 => InstructionType::VMFNE,
+                0b100000 => InstructionType::VFDIV,
+                0b100100 => InstructionType::VFMUL,
+                0b101000 => InstructionType::VFMADD,
+                0b101001 => InstructionType::VFNMADD,
+                0b101010 => InstructionType::VFMSUB,
+                0b101011 => InstructionType::VFNMSUB,
+                0b101100 => InstructionType::VFMACC,
+                0b101101 => InstructionType::VFNMACC,
+                0b101110 => InstructionType::VFMSAC,
+                0b101111 => InstructionType::VFNMSAC,
+                _ => return None
+            }
+
+            _ => return None
+        };
+
+        Some(_type)
+    }
+
+    fn vector_operand_letter(&self) -> &'static str {
+        match self.get_funct3() {
+            OPIVX | OPMVX => "x",
+            OPIVI => "i",
+            OPFVF => "f",
+            _ => "v"
+        }
+    }
+
+    fn vector_memory_mnemonic(&self, _type: &InstructionType) -> String {
+        let eew = self.get_vector_eew().unwrap_or(8);
+        let nf = self.get_nf() + 1;
+        let segment = if nf > 1 { format!("seg{}", nf) } else { String::new() };
+
+        match _type {
+            InstructionType::VLE => format!("vl{}e{}.v", segment, eew),
+            InstructionType::VLEFF => format!("vl{}e{}ff.v", segment, eew),
+            InstructionType::VSE => format!("vs{}e{}.v", segment, eew),
+            InstructionType::VLSE => format!("vls{}e{}.v", segment, eew),
+            InstructionType::VSSE => format!("vss{}e{}.v", segment, eew),
+            InstructionType::VLUXEI => format!("vlux{}ei{}.v", segment, eew),
+            InstructionType::VLOXEI => format!("vlox{}ei{}.v", segment, eew),
+            InstructionType::VSUXEI => format!("vsux{}ei{}.v", segment, eew),
+            InstructionType::VSOXEI => format!("vsox{}ei{}.v", segment, eew),
+            InstructionType::VLR => format!("vl{}re{}.v", nf, eew),
+            InstructionType::VSR => format!("vs{}r.v", nf),
+            InstructionType::VLM => "vlm.v".to_string(),
+            InstructionType::VSM => "vsm.v".to_string(),
+            _ => String::new()
+        }
+    }
+
+    /// Vector mnemonics carry their operand kinds as suffixes (`vadd.vx`, `vredsum.vs`, `vnsrl.wi`).
+    pub(super) fn vector_mnemonic(&self, _type: &InstructionType, base: String) -> String {
+        let letter = self.vector_operand_letter();
+
+        match _type {
+            InstructionType::VLE | InstructionType::VLEFF | InstructionType::VSE |
+            InstructionType::VLSE | InstructionType::VSSE |
+            InstructionType::VLUXEI | InstructionType::VLOXEI |
+            InstructionType::VSUXEI | InstructionType::VSOXEI |
+            InstructionType::VLR | InstructionType::VSR |
+            InstructionType::VLM | InstructionType::VSM
+            => self.vector_memory_mnemonic(_type),
+
+            InstructionType::VSETVLI | InstructionType::VSETIVLI | InstructionType::VSETVL |
+            InstructionType::VMV_X_S | InstructionType::VMV_S_X |
+            InstructionType::VCPOP_M | InstructionType::VFIRST_M |
+            InstructionType::VZEXT_VF2 | InstructionType::VZEXT_VF4 | InstructionType::VZEXT_VF8 |
+            InstructionType::VSEXT_VF2 | InstructionType::VSEXT_VF4 | InstructionType::VSEXT_VF8 |
+            InstructionType::VMSBF_M | InstructionType::VMSOF_M | InstructionType::VMSIF_M |
+            InstructionType::VIOTA_M | InstructionType::VID_V | InstructionType::VFSQRT_V
+            => base,
+
+            InstructionType::VMVR => format!("vmv{}r.v", self.get_simm5() + 1),
+
+            InstructionType::VREDSUM | InstructionType::VREDAND | InstructionType::VREDOR |
+            InstructionType::VREDXOR | InstructionType::VREDMINU | InstructionType::VREDMIN |
+            InstructionType::VREDMAXU | InstructionType::VREDMAX |
+            InstructionType::VWREDSUMU | InstructionType::VWREDSUM |
+            InstructionType::VFREDUSUM | InstructionType::VFREDOSUM |
+            InstructionType::VFREDMIN | InstructionType::VFREDMAX
+            => format!("{}.vs", base),
+
+            InstructionType::VMANDN | InstructionType::VMAND | InstructionType::VMOR |
+            InstructionType::VMXOR | InstructionType::VMORN | InstructionType::VMNAND |
+            InstructionType::VMNOR | InstructionType::VMXNOR
+            => format!("{}.mm", base),
+
+            InstructionType::VCOMPRESS => format!("{}.vm", base),
+
+            InstructionType::VADC | InstructionType::VSBC | InstructionType::VMERGE
+            => format!("{}.v{}m", base, letter),
+
+            InstructionType::VMADC | InstructionType::VMSBC
+            => format!("{}.v{}{}", base, letter, if self.get_vm() { "" } else { "m" }),
+
+            InstructionType::VMV_V => format!("{}.{}", base, letter),
+
+            InstructionType::VWADDU_W | InstructionType::VWADD_W |
+            InstructionType::VWSUBU_W | InstructionType::VWSUB_W
+            => format!("{}{}", base, letter),
+
+            InstructionType::VNSRL | InstructionType::VNSRA |
+            InstructionType::VNCLIPU | InstructionType::VNCLIP
+            => format!("{}.w{}", base, letter),
+
+            _ => format!("{}.v{}", base, letter)
+        }
+    }
+
+    fn vector_scalar_operand(&self) -> String {
+        match self.get_funct3() {
+            OPIVX | OPMVX => format!("x{}", self.get_rs1()),
+            OPIVI if self.has_vector_uimm() => format!("{}", self.get_rs1()),
+            OPIVI => format!("{}", self.get_simm5() as i32),
+            OPFVF => format!("f{}", self.get_rs1()),
+            _ => format!("v{}", self.get_rs1())
+        }
+    }
+
+    fn vector_vtype_string(vtype: u32) -> String {
+        let sew = 8 << (vtype >> 3 & 0b111);
+        let lmul = match vtype & 0b111 {
+            0b101 => "mf8".to_string(),
+            0b110 => "mf4".to_string(),
+            0b111 => "mf2".to_string(),
+            lmul => format!("m{}", 1 << lmul),
+        };
+        let ta = if vtype >> 6 & 1 != 0 { "ta" } else { "tu" };
+        let ma = if vtype >> 7 & 1 != 0 { "ma" } else { "mu" };
+        format!("e{},{},{},{}", sew, lmul, ta, ma)
+    }
+
+    pub(super) fn fmt_vector(&self, f: &mut Formatter<'_>, _type: &InstructionType) -> FmtResult {
+        let mask = if self.get_vm() { "" } else { ",v0.t" };
+        let vd = self.get_rd();
+        let vs2 = self.get_rs2();
+
+        match _type {
+            InstructionType::VSETVLI
+            => write!(f, "x{},x{},{}", vd, self.get_rs1(), Self::vector_vtype_string(self.get_zimm_vtype())),
+            InstructionType::VSETIVLI
+            => write!(f, "x{},{},{}", vd, self.get_rs1(), Self::vector_vtype_string(self.get_zimm_vtype())),
+            InstructionType::VSETVL
+            => write!(f, "x{},x{},x{}", vd, self.get_rs1(), vs2),
+
+            InstructionType::VLE | InstructionType::VLEFF | InstructionType::VSE |
+            InstructionType::VLM | InstructionType::VSM |
+            InstructionType::VLR | InstructionType::VSR
+            => write!(f, "v{},(x{}){}", vd, self.get_rs1(), mask),
+            InstructionType::VLSE | InstructionType::VSSE
+            => write!(f, "v{},(x{}),x{}{}", vd, self.get_rs1(), vs2, mask),
+            InstructionType::VLUXEI | InstructionType::VLOXEI |
+            InstructionType::VSUXEI | InstructionType::VSOXEI
+            => write!(f, "v{},(x{}),v{}{}", vd, self.get_rs1(), vs2, mask),
+
+            InstructionType::VMV_X_S | InstructionType::VCPOP_M | InstructionType::VFIRST_M
+            => write!(f, "x{},v{}{}", vd, vs2, mask),
+            InstructionType::VMV_S_X | InstructionType::VMV_V
+            => write!(f, "v{},{}", vd, self.vector_scalar_operand()),
+            InstructionType::VID_V
+            => write!(f, "v{}{}", vd, mask),
+            InstructionType::VZEXT_VF2 | InstructionType::VZEXT_VF4 | InstructionType::VZEXT_VF8 |
+            InstructionType::VSEXT_VF2 | InstructionType::VSEXT_VF4 | InstructionType::VSEXT_VF8 |
+            InstructionType::VMSBF_M | InstructionType::VMSOF_M | InstructionType::VMSIF_M |
+            InstructionType::VIOTA_M | InstructionType::VFSQRT_V | InstructionType::VMVR
+            => write!(f, "v{},v{}{}", vd, vs2, mask),
+
+            InstructionType::VADC | InstructionType::VSBC | InstructionType::VMERGE
+            => write!(f, "v{},v{},{},v0", vd, vs2, self.vector_scalar_operand()),
+            InstructionType::VMADC | InstructionType::VMSBC if !self.get_vm()
+            => write!(f, "v{},v{},{},v0", vd, vs2, self.vector_scalar_operand()),
+
+            InstructionType::VMACC | InstructionType::VNMSAC |
+            InstructionType::VMADD | InstructionType::VNMSUB |
+            InstructionType::VWMACCU | InstructionType::VWMACC |
+            InstructionType::VWMACCUS | InstructionType::VWMACCSU |
+            InstructionType::VFMACC | InstructionType::VFNMACC |
+            InstructionType::VFMSAC | InstructionType::VFNMSAC |
+            InstructionType::VFMADD | InstructionType::VFNMADD |
+            InstructionType::VFMSUB | InstructionType::VFNMSUB
+            => write!(f, "v{},{},v{}{}", vd, self.vector_scalar_operand(), vs2, mask),
+
+            _ => write!(f, "v{},v{},{}{}", vd, vs2, self.vector_scalar_operand(), mask)
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::{Display, Formatter};
+
+use crate::error::Error;
+use crate::instruction::InstructionType;
+
+/// Extensions the emulator implements, in canonical ISA string order.
+const EXTENSIONS: [&str; 21] = [
+    "i", "a", "v", "zicbom", "zicboz", "zicond", "zicsr", "zifencei", "zihintpause",
+    "zba", "zbb", "zbc", "zbkb", "zbkc", "zbkx", "zbs", "zknd", "zkne", "zknh", "zksed", "zksh",
+];
+
+/// Shorthands that stand for several extensions.
+const GROUPS: [(&str, &[&str]); 2] = [
+    ("zkn", &["zbkb", "zbkc", "zbkx", "zkne", "zknd", "zknh"]),
+    ("zks", &["zbkb", "zbkc", "zbkx", "zksed", "zksh"]),
+];
+
+const MISA_MXL_32: u32 = 1 << 30;
+const MISA_USER: u32 = 1 << 20;
+
+/// The extensions `_type` belongs to. Instructions shared by several extensions are enabled
+/// by any of them.
+fn extensions_of(_type: InstructionType) -> &'static [&'static str] {
+    use InstructionType::*;
+
+    match _type {
+        CSRRW | CSRRS | CSRRC | CSRRWI | CSRRSI | CSRRCI => &["zicsr"],
+        FENCE_I => &["zifencei"],
+        PAUSE => &["zihintpause"],
+        SH1ADD | SH2ADD | SH3ADD => &["zba"],
+        ANDN | ORN | XNOR | ROL | ROR | RORI | REV8 => &["zbb", "zbkb"],
+        CLZ | CTZ | CPOP | MIN | MAX | MINU | MAXU | SEXT_B | SEXT_H | ZEXT_H | ORC_B => &["zbb"],
+        CLMUL | CLMULH => &["zbc", "zbkc"],
+        CLMULR => &["zbc"],
+        BSET | BCLR | BINV | BEXT | BSETI | BCLRI | BINVI | BEXTI => &["zbs"],
+        PACK | PACKH | BREV8 | ZIP | UNZIP => &["zbkb"],
+        XPERM4 | XPERM8 => &["zbkx"],
+        AES32DSI | AES32DSMI => &["zknd"],
+        AES32ESI | AES32ESMI => &["zkne"],
+        SHA256SIG0 | SHA256SIG1 | SHA256SUM0 | SHA256SUM1 | SHA512SIG0H | SHA512SIG0L | SHA512SIG1H
+            | SHA512SIG1L | SHA512SUM0R | SHA512SUM1R => &["zknh"],
+        SM4ED | SM4KS => &["zksed"],
+        SM3P0 | SM3P1 => &["zksh"],
+        CZERO_EQZ | CZERO_NEZ => &["zicond"],
+        CBO_CLEAN | CBO_FLUSH | CBO_INVAL => &["zicbom"],
+        CBO_ZERO => &["zicboz"],
+        LR_W | SC_W | AMOSWAP_W | AMOADD_W | AMOXOR_W | AMOAND_W | AMOOR_W | AMOMIN_W | AMOMAX_W
+            | AMOMINU_W | AMOMAXU_W => &["a"],
+        LUI | AUIPC | JAL | JALR | BEQ | BNE | BLT | BGE | BLTU | BGEU | LB | LH | LW | LBU | LHU | SB
+            | SH | SW | ADDI | SLTI | SLTIU | XORI | ORI | ANDI | SLLI | SRLI | SRAI | ADD | SUB | SLL | SLT
+            | SLTU | XOR | SRL | SRA | OR | AND | ECALL | EBREAK | MRET | WFI | FENCE => &["i"],
+        VSETVLI | VSETIVLI | VSETVL | VLE | VLEFF | VLM | VLR | VLSE | VLUXEI | VLOXEI | VSE | VSM
+            | VSR | VSSE | VSUXEI | VSOXEI | VADD | VSUB | VRSUB | VMINU | VMIN | VMAXU | VMAX
+            | VAND | VOR | VXOR | VRGATHER | VRGATHEREI16 | VSLIDEUP | VSLIDEDOWN | VADC | VMADC
+            | VSBC | VMSBC | VMERGE | VMV_V | VMSEQ | VMSNE | VMSLTU | VMSLT | VMSLEU | VMSLE
+            | VMSGTU | VMSGT | VSADDU | VSADD | VSSUBU | VSSUB | VSLL | VSMUL | VMVR | VSRL | VSRA
+            | VSSRL | VSSRA | VNSRL | VNSRA | VNCLIPU | VNCLIP | VWREDSUMU | VWREDSUM | VREDSUM
+            | VREDAND | VREDOR | VREDXOR | VREDMINU | VREDMIN | VREDMAXU | VREDMAX | VAADDU | VAADD
+            | VASUBU | VASUB | VSLIDE1UP | VSLIDE1DOWN | VMV_X_S | VCPOP_M | VFIRST_M | VMV_S_X
+            | VZEXT_VF8 | VSEXT_VF8 | VZEXT_VF4 | VSEXT_VF4 | VZEXT_VF2 | VSEXT_VF2 | VMSBF_M
+            | VMSOF_M | VMSIF_M | VIOTA_M | VID_V | VCOMPRESS | VMANDN | VMAND | VMOR | VMXOR
+            | VMORN | VMNAND | VMNOR | VMXNOR | VDIVU | VDIV | VREMU | VREM | VMULHU | VMUL
+            | VMULHSU | VMULH | VMADD | VNMSUB | VMACC | VNMSAC | VWADDU | VWADD | VWSUBU | VWSUB
+            | VWADDU_W | VWADD_W | VWSUBU_W | VWSUB_W | VWMULU | VWMULSU | VWMUL | VWMACCU | VWMACC
+            | VWMACCUS | VWMACCSU | VFADD | VFREDUSUM | VFSUB | VFREDOSUM | VFMIN | VFREDMIN | VFMAX
+            | VFREDMAX | VFSGNJ | VFSGNJN | VFSGNJX | VFSQRT_V | VMFEQ | VMFLE | VMFLT | VMFNE
+            | VFDIV | VFMUL | VFMADD | VFNMADD | VFMSUB | VFNMSUB | VFMACC | VFNMACC | VFMSAC
+            | VFNMSAC => &["v"],
+    }
+}
+
+/// The extensions a machine implements, parsed from an ISA string like `rv32ia_zicsr_zba`.
+/// Instructions of the other extensions are illegal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Isa {
+    extensions: u32,
+}
+
+impl Isa {
+    /// Every extension the emulator implements.
+    pub fn all() -> Self {
+        Self { extensions: (1 << EXTENSIONS.len()) - 1 }
+    }
+
+    fn bit(extension: &str) -> Option<u32> {
+        EXTENSIONS.iter().position(|&name| name == extension).map(|index| 1 << index)
+    }
+
+    pub fn parse(isa: &str) -> Result<Self, Error> {
+        let isa = isa.to_lowercase();
+        let Some(rest) = isa.strip_prefix("rv32") else {
+            return Err(Error::Config(format!("ISA string {} does not start with rv32", isa)));
+        };
+        let mut parts = rest.split('_');
+        let letters = parts.next().unwrap_or("");
+        if !letters.starts_with('i') {
+            return Err(Error::Config(format!("ISA string {} lacks the I base", isa)));
+        }
+
+        let mut extensions = 0;
+        let names = letters.chars().map(|letter| letter.to_string()).chain(parts.map(str::to_string));
+        for name in names.filter(|name| !name.is_empty()) {
+            if let Some((_, group)) = GROUPS.iter().find(|(group, _)| *group == name) {
+                extensions |= group.iter().map(|extension| Self::bit(extension).unwrap()).fold(0, |a, b| a | b);
+                continue;
+            }
+            match Self::bit(&name) {
+                Some(bit) => extensions |= bit,
+                None => return Err(Error::Config(format!("extension {} is not supported", name))),
+            }
+        }
+        Ok(Self { extensions })
+    }
+
+    pub fn has(&self, extension: &str) -> bool {
+        Self::bit(extension).is_some_and(|bit| self.extensions & bit != 0)
+    }
+
+    pub fn supports(&self, _type: InstructionType) -> bool {
+        extensions_of(_type).iter().any(|extension| self.has(extension))
+    }
+
+    /// The value of `misa`: XLEN, user mode and the single-letter extensions.
+    pub fn misa(&self) -> u32 {
+        let letters = EXTENSIONS.iter().filter(|extension| extension.len() == 1 && self.has(extension));
+        letters.fold(MISA_MXL_32 | MISA_USER, |misa, letter| misa | 1 << (letter.as_bytes()[0] - b'a'))
+    }
+}
+
+impl Display for Isa {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rv32")?;
+        for extension in EXTENSIONS.iter().filter(|extension| self.has(extension)) {
+            if extension.len() > 1 {
+                write!(f, "_")?;
+            }
+            write!(f, "{}", extension)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::InstructionType;
+    use crate::isa::Isa;
+
+    #[test]
+    fn isa_strings_select_extensions() {
+        let isa = Isa::parse("RV32IA_Zicsr_zkn").unwrap();
+        assert_eq!(isa.to_string(), "rv32ia_zicsr_zbkb_zbkc_zbkx_zknd_zkne_zknh");
+        assert_eq!(isa.misa(), 0x40100101);
+        assert!(isa.supports(InstructionType::AMOADD_W));
+        assert!(isa.supports(InstructionType::ROR));
+        assert!(!isa.supports(InstructionType::CLZ));
+        assert!(!isa.supports(InstructionType::VADD));
+
+        assert_eq!(Isa::parse(&Isa::all().to_string()).unwrap(), Isa::all());
+        assert!(Isa::parse("rv64i").is_err());
+        assert!(Isa::parse("rv32im").is_err());
+        assert!(Isa::parse("rv32a").is_err());
+    }
+}
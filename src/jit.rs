@@ -0,0 +1,304 @@
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+compile_error!("the jit feature is only supported on x86-64 Linux");
+
+use crate::decode_cache::Decoded;
+use crate::instruction::InstructionType;
+
+const EAX: u8 = 0;
+const ECX: u8 = 1;
+
+/// Emits x86-64 code working on the guest registers, which live in a `[u32; 32]` whose
+/// address is passed in `rdi`. `eax` holds the result and `ecx` the second operand, and the
+/// code returns the guest PC to continue at in `eax`.
+struct Assembler {
+    code: Vec<u8>,
+    /// Whether the last instruction emitted left the code, so nothing may follow it
+    exited: bool,
+}
+
+impl Assembler {
+    /// `mov host, [rdi + 4 * guest]`
+    fn load(&mut self, host: u8, guest: u8) {
+        self.code.extend([0x8B, 0x47 | host << 3, guest * 4]);
+    }
+
+    /// `mov [rdi + 4 * guest], eax`
+    fn store(&mut self, guest: u8) {
+        self.code.extend([0x89, 0x47, guest * 4]);
+    }
+
+    /// `mov eax, imm`
+    fn load_immediate(&mut self, imm: u32) {
+        self.code.push(0xB8);
+        self.code.extend(imm.to_le_bytes());
+    }
+
+    /// `op eax, ecx` for an ALU opcode taking r/m32, r32.
+    fn alu(&mut self, opcode: u8) {
+        self.code.extend([opcode, 0xC0 | ECX << 3 | EAX]);
+    }
+
+    /// `op eax, imm` for the short form of an ALU opcode taking eax, imm32.
+    fn alu_immediate(&mut self, opcode: u8, imm: u32) {
+        self.code.push(opcode);
+        self.code.extend(imm.to_le_bytes());
+    }
+
+    /// `shl`, `shr` or `sar` of eax by cl, which x86 masks to five bits like RISC-V.
+    fn shift(&mut self, digit: u8) {
+        self.code.extend([0xD3, 0xC0 | digit << 3]);
+    }
+
+    fn shift_immediate(&mut self, digit: u8, shamt: u8) {
+        self.code.extend([0xC1, 0xC0 | digit << 3, shamt]);
+    }
+
+    /// `setcc al; movzx eax, al`
+    fn set(&mut self, condition: u8) {
+        self.code.extend([0x0F, condition, 0xC0, 0x0F, 0xB6, 0xC0]);
+    }
+
+    /// Returns `next`.
+    fn exit(&mut self, next: u32) {
+        self.load_immediate(next);
+        self.code.push(0xC3);
+        self.exited = true;
+    }
+
+    /// Returns `taken` when the condition code `condition` holds and `next` otherwise:
+    /// `mov eax, next; mov edx, taken; cmovcc eax, edx; ret`.
+    fn exit_if(&mut self, condition: u8, taken: u32, next: u32) {
+        self.load_immediate(next);
+        self.code.push(0xBA);
+        self.code.extend(taken.to_le_bytes());
+        self.code.extend([0x0F, 0x40 | condition, 0xC2, 0xC3]);
+        self.exited = true;
+    }
+
+    /// Emits `decoded`, fetched from `pc`, returning false when it needs the interpreter.
+    /// Jumps and branches to misaligned targets are left to it to raise the exception.
+    fn emit(&mut self, decoded: &Decoded, pc: u32) -> bool {
+        let Decoded { rd, rs1, rs2, imm, .. } = *decoded;
+
+        match decoded._type {
+            Some(InstructionType::JAL) => {
+                let target = pc.wrapping_add(imm);
+                if !target.is_multiple_of(4) {
+                    return false;
+                }
+                if rd != 0 {
+                    self.load_immediate(pc.wrapping_add(4));
+                    self.store(rd);
+                }
+                self.exit(target);
+                return true;
+            }
+            Some(InstructionType::BEQ | InstructionType::BNE | InstructionType::BLT | InstructionType::BGE
+                | InstructionType::BLTU | InstructionType::BGEU) => {
+                let target = pc.wrapping_add(imm);
+                if !target.is_multiple_of(4) {
+                    return false;
+                }
+                let condition = match decoded._type {
+                    Some(InstructionType::BEQ) => 0x4,
+                    Some(InstructionType::BNE) => 0x5,
+                    Some(InstructionType::BLT) => 0xC,
+                    Some(InstructionType::BGE) => 0xD,
+                    Some(InstructionType::BLTU) => 0x2,
+                    _ => 0x3,
+                };
+                self.load(EAX, rs1);
+                self.load(ECX, rs2);
+                self.alu(0x39);
+                self.exit_if(condition, target, pc.wrapping_add(4));
+                return true;
+            }
+            Some(InstructionType::LUI) => self.load_immediate(imm),
+            Some(InstructionType::AUIPC) => self.load_immediate(imm.wrapping_add(pc)),
+            Some(InstructionType::ADDI | InstructionType::XORI | InstructionType::ORI | InstructionType::ANDI) => {
+                let opcode = match decoded._type {
+                    Some(InstructionType::ADDI) => 0x05,
+                    Some(InstructionType::XORI) => 0x35,
+                    Some(InstructionType::ORI) => 0x0D,
+                    _ => 0x25,
+                };
+                self.load(EAX, rs1);
+                self.alu_immediate(opcode, imm);
+            }
+            Some(InstructionType::SLLI | InstructionType::SRLI | InstructionType::SRAI) => {
+                let digit = match decoded._type {
+                    Some(InstructionType::SLLI) => 4,
+                    Some(InstructionType::SRLI) => 5,
+                    _ => 7,
+                };
+                self.load(EAX, rs1);
+                self.shift_immediate(digit, imm as u8);
+            }
+            Some(InstructionType::ADD | InstructionType::SUB | InstructionType::XOR | InstructionType::OR
+                | InstructionType::AND) => {
+                let opcode = match decoded._type {
+                    Some(InstructionType::ADD) => 0x01,
+                    Some(InstructionType::SUB) => 0x29,
+                    Some(InstructionType::XOR) => 0x31,
+                    Some(InstructionType::OR) => 0x09,
+                    _ => 0x21,
+                };
+                self.load(EAX, rs1);
+                self.load(ECX, rs2);
+                self.alu(opcode);
+            }
+            Some(InstructionType::SLL | InstructionType::SRL | InstructionType::SRA) => {
+                let digit = match decoded._type {
+                    Some(InstructionType::SLL) => 4,
+                    Some(InstructionType::SRL) => 5,
+                    _ => 7,
+                };
+                self.load(EAX, rs1);
+                self.load(ECX, rs2);
+                self.shift(digit);
+            }
+            Some(InstructionType::SLT | InstructionType::SLTU) => {
+                self.load(EAX, rs1);
+                self.load(ECX, rs2);
+                self.alu(0x39);
+                self.set(if decoded._type == Some(InstructionType::SLT) { 0x9C } else { 0x92 });
+            }
+            _ => return false,
+        }
+
+        if rd != 0 {
+            self.store(rd);
+        }
+        true
+    }
+}
+
+/// Native code for the register-only instructions at the start of a block, including the
+/// jump or branch that ends it when everything before it compiles, mapped read and execute
+/// only. Loads, stores, CSR accesses and anything else that can trap or touch a device are
+/// left to the interpreter.
+pub struct NativeCode {
+    code: *mut libc::c_void,
+    size: usize,
+    instructions: usize,
+    whole: bool,
+}
+
+impl NativeCode {
+    /// Translates the longest register-only prefix of `ops`, the first of which is fetched
+    /// from `start`. There is nothing to run natively when the prefix is empty.
+    pub fn compile<'a>(start: usize, ops: impl Iterator<Item = &'a Decoded>) -> Option<Self> {
+        let mut assembler = Assembler { code: Vec::new(), exited: false };
+        let mut instructions = 0;
+        for decoded in ops {
+            if !assembler.emit(decoded, (start + instructions * 4) as u32) {
+                break;
+            }
+            instructions += 1;
+            if assembler.exited {
+                break;
+            }
+        }
+        if instructions == 0 {
+            return None;
+        }
+        let whole = assembler.exited;
+        if !whole {
+            assembler.exit((start + instructions * 4) as u32);
+        }
+
+        let size = assembler.code.len().next_multiple_of(4096);
+        // SAFETY: a fresh private anonymous mapping is written before it is made executable.
+        unsafe {
+            let code = libc::mmap(std::ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0);
+            if code == libc::MAP_FAILED {
+                return None;
+            }
+            std::ptr::copy_nonoverlapping(assembler.code.as_ptr(), code as *mut u8, assembler.code.len());
+            if libc::mprotect(code, size, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                libc::munmap(code, size);
+                return None;
+            }
+            Some(Self { code, size, instructions, whole })
+        }
+    }
+
+    /// How many guest instructions one call executes.
+    pub fn instructions(&self) -> usize {
+        self.instructions
+    }
+
+    /// Whether the translation covers its whole block, jump or branch included.
+    pub fn whole(&self) -> bool {
+        self.whole
+    }
+
+    /// Runs the translation against the 32 guest registers, returning the PC to continue at.
+    pub fn run(&self, registers: &mut [u32]) -> usize {
+        assert_eq!(registers.len(), 32);
+        // SAFETY: the code only accesses the 32 registers behind its argument and returns.
+        unsafe {
+            let function: extern "sysv64" fn(*mut u32) -> u32 = std::mem::transmute(self.code);
+            function(registers.as_mut_ptr()) as usize
+        }
+    }
+}
+
+impl Drop for NativeCode {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is owned by this translation and no longer running.
+        unsafe {
+            libc::munmap(self.code, self.size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode_cache::Decoded;
+    use crate::instruction::Instruction;
+    use crate::jit::NativeCode;
+
+    #[test]
+    fn native_code_matches_interpreter() {
+        // lui x5,0x12345; addi x6,x5,-1; sub x7,x0,x6; srai x28,x7,4; sltu x29,x6,x5;
+        // sll x30,x6,x29; auipc x31,1; lw x1,0(x0)
+        let ops = [0x123452b7, 0xfff28313, 0x406003b3, 0x4043de13, 0x00533eb3, 0x01d31f33, 0x00001f97, 0x00002083]
+            .map(|raw| Decoded::new(Instruction::from_u32(raw)));
+        let native = NativeCode::compile(0x100, ops.iter()).unwrap();
+        assert_eq!(native.instructions(), 7);
+        assert!(!native.whole());
+
+        let mut registers = [0; 32];
+        assert_eq!(native.run(&mut registers), 0x11C);
+        assert_eq!(registers[5], 0x12345000);
+        assert_eq!(registers[6], 0x12344fff);
+        assert_eq!(registers[7], 0xedcbb001);
+        assert_eq!(registers[28], 0xfedcbb00);
+        assert_eq!(registers[29], 1);
+        assert_eq!(registers[30], 0x24689ffe);
+        assert_eq!(registers[31], 0x1118);
+        assert_eq!(registers[0], 0);
+    }
+
+    #[test]
+    fn native_code_takes_branches() {
+        // addi x10,x10,1; blt x10,x11,-4
+        let ops = [0x00150513, 0xfeb54ee3].map(|raw| Decoded::new(Instruction::from_u32(raw)));
+        let native = NativeCode::compile(0x100, ops.iter()).unwrap();
+        assert!(native.whole());
+
+        let mut registers = [0; 32];
+        registers[11] = 2;
+        assert_eq!(native.run(&mut registers), 0x100);
+        assert_eq!(native.run(&mut registers), 0x108);
+        assert_eq!(registers[10], 2);
+
+        // jal x1,-8
+        let ops = [Decoded::new(Instruction::from_u32(0xff9ff0ef))];
+        let native = NativeCode::compile(0x100, ops.iter()).unwrap();
+        assert_eq!(native.run(&mut registers), 0xF8);
+        assert_eq!(registers[1], 0x104);
+    }
+}
@@ -0,0 +1,43 @@
+#![allow(clippy::upper_case_acronyms, clippy::needless_return)]
+
+//! A RISC-V RV32 emulator. Build a `Machine` with `MachineBuilder`, then drive it with
+//! `step`, `run_for` or `run_until` and inspect it through its register and memory accessors.
+
+mod bitmanip;
+mod bus;
+mod check;
+mod clint;
+pub mod conformance;
+mod counters;
+mod crypto;
+mod csr;
+pub mod debugger;
+mod decode_cache;
+mod elf;
+mod error;
+pub mod gdb;
+mod history;
+mod instruction;
+mod isa;
+#[cfg(feature = "jit")]
+mod jit;
+mod machine;
+mod memory;
+mod pmp;
+mod registers;
+pub mod rvfi;
+mod snapshot;
+mod trace;
+mod trap;
+mod vector_registers;
+mod cpu;
+
+pub use check::Checker;
+pub use clint::CLINT_BASE;
+pub use cpu::hooks::{Action, CsrAccess, Hart, Hooks, TrapEntry};
+pub use elf::{Elf, Segment};
+pub use error::Error;
+pub use instruction::Instruction;
+pub use machine::{Engine, Machine, MachineBuilder};
+pub use trace::{MemoryAccess, Retired, TraceFormat, Tracer};
+pub use trap::{Exception, Misaligned, Privilege};
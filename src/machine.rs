@@ -0,0 +1,625 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bus::Bus;
+use crate::check::Checker;
+use crate::cpu::hooks::Hooks;
+use crate::cpu::{Observers, CPU};
+use crate::elf::Elf;
+use crate::error::Error;
+use crate::history::History;
+use crate::isa::Isa;
+use crate::memory::Memory;
+use crate::snapshot::{Reader, Writer};
+use crate::trace::Tracer;
+use crate::trap::Misaligned;
+
+/// How `Machine::run` executes instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Engine {
+    /// Fetch, decode and execute one instruction at a time; the reference implementation
+    Interp,
+    /// Translate basic blocks once and run them whole
+    Block,
+    /// Run blocks like `block`, compiling hot blocks, or their register-only start, to x86-64
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+/// Configures and builds a `Machine`. Main memory starts at address zero and the CLINT sits
+/// at `CLINT_BASE`.
+///
+/// ```
+/// use riscv_emulator::MachineBuilder;
+///
+/// // addi x10,x0,42; ebreak
+/// let program = [0x02a00513u32, 0x00100073].map(u32::to_le_bytes).concat();
+/// let mut machine = MachineBuilder::new(0x1000).isa("rv32i_zicsr").program(0, &program).build().unwrap();
+/// machine.run();
+/// assert_eq!(machine.get_register(0, 10).unwrap(), 42);
+/// ```
+#[derive(Clone)]
+pub struct MachineBuilder {
+    memory_size: usize,
+    images: Vec<(usize, Vec<u8>)>,
+    entry: usize,
+    isa: Option<String>,
+    harts: usize,
+    quantum: usize,
+    engine: Engine,
+    clint: bool,
+    strict_fence_i: bool,
+    misaligned: Misaligned,
+    vlen: usize,
+    elen: usize,
+    vector_agnostic_ones: bool,
+    cache_block_size: usize,
+}
+
+impl MachineBuilder {
+    /// A single hart with `memory_size` bytes of main memory and every extension enabled.
+    pub fn new(memory_size: usize) -> Self {
+        Self {
+            memory_size,
+            images: Vec::new(),
+            entry: 0,
+            isa: None,
+            harts: 1,
+            quantum: 100,
+            engine: Engine::Interp,
+            clint: true,
+            strict_fence_i: false,
+            misaligned: Misaligned::Allow,
+            vlen: 128,
+            elen: 64,
+            vector_agnostic_ones: false,
+            cache_block_size: 64,
+        }
+    }
+
+    /// Copies `bytes` into main memory at `address`.
+    pub fn program(mut self, address: usize, bytes: &[u8]) -> Self {
+        self.images.push((address, bytes.to_vec()));
+        self
+    }
+
+    /// Loads the segments of `elf` moved down by its lowest address, since main memory starts
+    /// at zero, and starts at its entry point.
+    pub fn elf(mut self, elf: &Elf) -> Self {
+        let base = elf.base();
+        for segment in &elf.segments {
+            self.images.push(((segment.address - base) as usize, segment.data.clone()));
+        }
+        self.entry = (elf.entry - base) as usize;
+        self
+    }
+
+    /// The PC every hart starts at.
+    pub fn entry(mut self, entry: usize) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    /// The extensions to implement, such as `rv32ia_zicsr_zifencei`. Instructions of the others
+    /// are illegal.
+    pub fn isa(mut self, isa: &str) -> Self {
+        self.isa = Some(isa.to_string());
+        self
+    }
+
+    pub fn harts(mut self, harts: usize) -> Self {
+        self.harts = harts;
+        self
+    }
+
+    /// Instructions each hart runs before the scheduler switches to the next.
+    pub fn quantum(mut self, quantum: usize) -> Self {
+        self.quantum = quantum;
+        self
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Whether the CLINT is mapped, and with it software and timer interrupts.
+    pub fn clint(mut self, clint: bool) -> Self {
+        self.clint = clint;
+        self
+    }
+
+    /// Halts any hart that executes code stored to since its last FENCE.I.
+    pub fn strict_fence_i(mut self, strict_fence_i: bool) -> Self {
+        self.strict_fence_i = strict_fence_i;
+        self
+    }
+
+    pub fn misaligned(mut self, misaligned: Misaligned) -> Self {
+        self.misaligned = misaligned;
+        self
+    }
+
+    /// The width of each vector register and the widest vector element, in bits.
+    pub fn vector(mut self, vlen: usize, elen: usize) -> Self {
+        self.vlen = vlen;
+        self.elen = elen;
+        self
+    }
+
+    /// Fills tail and masked-off elements with all ones when vtype marks them agnostic.
+    pub fn vector_agnostic_ones(mut self, vector_agnostic_ones: bool) -> Self {
+        self.vector_agnostic_ones = vector_agnostic_ones;
+        self
+    }
+
+    /// The size in bytes of the cache block cleared by CBO.ZERO.
+    pub fn cache_block_size(mut self, cache_block_size: usize) -> Self {
+        self.cache_block_size = cache_block_size;
+        self
+    }
+
+    pub fn build(&self) -> Result<Machine, Error> {
+        if self.memory_size < 8 {
+            return Err(Error::Config("memory must be at least 8 bytes".to_string()));
+        }
+        if !self.vlen.is_power_of_two() || self.vlen < 64 || self.vlen > 65536 {
+            return Err(Error::Config("VLEN must be a power of two between 64 and 65536".to_string()));
+        }
+        if ![32, 64].contains(&self.elen) || self.elen > self.vlen {
+            return Err(Error::Config("ELEN must be 32 or 64 and no larger than VLEN".to_string()));
+        }
+        if !self.cache_block_size.is_power_of_two() || self.cache_block_size < 4 {
+            return Err(Error::Config("cache block size must be a power of two of at least 4 bytes".to_string()));
+        }
+        if self.harts == 0 {
+            return Err(Error::Config("at least one hart is required".to_string()));
+        }
+        let isa = match &self.isa {
+            Some(isa) => Isa::parse(isa)?,
+            None => Isa::all(),
+        };
+
+        let mut memory = Memory::new(self.memory_size);
+        for (address, bytes) in &self.images {
+            if address.checked_add(bytes.len()).is_none_or(|end| end > self.memory_size) {
+                return Err(Error::Load(format!("{} bytes at {:08x} do not fit in memory", bytes.len(), address)));
+            }
+            for (index, byte) in bytes.iter().enumerate() {
+                memory.set8(*byte, address + index)?;
+            }
+        }
+
+        let mut machine = Machine::new(memory, self.harts, self.quantum);
+        machine.engine = self.engine;
+        if self.strict_fence_i {
+            machine.set_strict_fetch();
+        }
+        if !self.clint {
+            machine.bus.borrow_mut().unmap_clint();
+        }
+        for cpu in machine.harts_mut() {
+            cpu.set_pc(self.entry);
+            cpu.isa = isa;
+            cpu.misaligned = self.misaligned;
+            cpu.set_vector_config(self.vlen, self.elen);
+            cpu.vector_agnostic_ones = self.vector_agnostic_ones;
+            cpu.cache_block_size = self.cache_block_size;
+        }
+        Ok(machine)
+    }
+}
+
+/// A set of harts sharing one bus, run by a deterministic round-robin scheduler that gives
+/// each running hart `quantum` instructions before moving on to the next.
+pub struct Machine {
+    bus: Rc<RefCell<Bus>>,
+    harts: Vec<CPU>,
+    quantum: usize,
+    current: usize,
+    executed: usize,
+    /// Instructions run by all harts since the machine started, counted like `run_for`.
+    instructions: u64,
+    engine: Engine,
+    /// Snapshots for reverse execution, once `record_history` has been called
+    history: Option<History>,
+}
+
+impl Machine {
+    pub(crate) fn new(memory: Memory, harts: usize, quantum: usize) -> Self {
+        let bus = Rc::new(RefCell::new(Bus::new(memory, harts)));
+        let harts = (0..harts).map(|hart_id| CPU::new(hart_id, bus.clone())).collect();
+        Self { bus, harts, quantum: quantum.max(1), current: 0, executed: 0, instructions: 0, engine: Engine::Interp, history: None }
+    }
+
+    pub(crate) fn harts_mut(&mut self) -> &mut [CPU] {
+        &mut self.harts
+    }
+
+    fn set_strict_fetch(&mut self) {
+        self.bus.borrow_mut().set_strict_fetch();
+    }
+
+    /// Runs `hooks` as any hart executes, in place of any set before.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        let hooks = Rc::new(RefCell::new(hooks));
+        for cpu in self.harts.iter_mut() {
+            cpu.set_hooks(hooks.clone());
+        }
+    }
+
+    /// Runs `hooks` as any hart executes, after those already set.
+    pub fn add_hooks(&mut self, hooks: Hooks) {
+        match self.harts.first().and_then(CPU::get_hooks) {
+            Some(existing) => existing.borrow_mut().append(hooks),
+            None => self.set_hooks(hooks),
+        }
+    }
+
+    /// Traces every instruction retired by any hart.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        let tracer = Rc::new(RefCell::new(tracer));
+        for cpu in self.harts.iter_mut() {
+            cpu.set_tracer(tracer.clone());
+        }
+    }
+
+    /// Checks every instruction retired by any hart against the reference of `checker`.
+    pub fn set_checker(&mut self, checker: Rc<RefCell<Checker>>) {
+        for cpu in self.harts.iter_mut() {
+            cpu.set_checker(checker.clone());
+        }
+    }
+
+    pub fn harts(&self) -> usize {
+        self.harts.len()
+    }
+
+    /// Whether any hart has work left.
+    pub fn running(&self) -> bool {
+        self.harts.iter().any(|hart| hart.running())
+    }
+
+    /// Lets harts stopped by a hook or EBREAK run again.
+    pub fn resume(&mut self) {
+        self.harts.iter_mut().for_each(CPU::resume);
+    }
+
+    /// The first guest fault that stopped a hart, such as an illegal instruction or an access
+    /// fault taken with no trap handler installed.
+    pub fn get_fault(&self) -> Option<&Error> {
+        self.harts.iter().find_map(|hart| hart.get_fault())
+    }
+
+    /// Moves on to the next running hart once the current one has stopped or used up its
+    /// quantum, returning whether any hart is left to run.
+    fn schedule(&mut self) -> bool {
+        let Some(next) = self.get_next_hart() else { return false };
+        if next != self.current || self.executed >= self.quantum {
+            self.current = next;
+            self.executed = 0;
+        }
+        true
+    }
+
+    /// The hart the next instruction will run on, or None if every hart has stopped.
+    pub fn get_next_hart(&self) -> Option<usize> {
+        if self.harts[self.current].running() && self.executed < self.quantum {
+            return Some(self.current);
+        }
+        (1..=self.harts.len())
+            .map(|offset| (self.current + offset) % self.harts.len())
+            .find(|&hart| self.harts[hart].running())
+    }
+
+    /// Runs at most `limit` instructions on the scheduled hart: one with the interpreter, or
+    /// the rest of the current basic block with the block engines, stopping early at the end
+    /// of the quantum. Returns how many ran.
+    fn run_limited(&mut self, limit: usize) -> usize {
+        if !self.schedule() {
+            return 0;
+        }
+        let limit = limit.min(self.quantum - self.executed);
+        let hart = &mut self.harts[self.current];
+        let executed = match self.engine {
+            Engine::Interp => hart.tick(),
+            Engine::Block => hart.run_block(limit),
+            #[cfg(feature = "jit")]
+            Engine::Jit => hart.run_native_block(limit),
+        };
+        self.executed += executed;
+        self.instructions += executed as u64;
+        if self.history.as_ref().is_some_and(|history| history.due(self.instructions)) {
+            self.checkpoint();
+        }
+        executed
+    }
+
+    /// Executes one instruction on the scheduled hart, whatever the engine.
+    pub fn tick(&mut self) {
+        self.run_limited(1);
+    }
+
+    /// Runs one instruction, or one block with the block engines, returning how many ran.
+    pub fn step(&mut self) -> usize {
+        self.run_limited(usize::MAX)
+    }
+
+    /// Runs until every hart has stopped.
+    pub fn run(&mut self) {
+        while self.running() {
+            self.step();
+        }
+    }
+
+    /// Runs at most `instructions` instructions, returning how many ran before every hart
+    /// stopped.
+    pub fn run_for(&mut self, instructions: usize) -> usize {
+        let mut executed = 0;
+        while executed < instructions && self.running() {
+            executed += self.run_limited(instructions - executed);
+        }
+        executed
+    }
+
+    /// Steps until `condition` holds, returning false if every hart stopped first. The
+    /// condition is checked between steps, so after whole blocks with the block engines.
+    pub fn run_until(&mut self, mut condition: impl FnMut(&Machine) -> bool) -> bool {
+        loop {
+            if condition(self) {
+                return true;
+            }
+            if !self.running() {
+                return false;
+            }
+            self.step();
+        }
+    }
+
+    fn hart(&self, hart: usize) -> Result<&CPU, Error> {
+        self.harts.get(hart).ok_or(Error::NoHart(hart))
+    }
+
+    fn hart_mut(&mut self, hart: usize) -> Result<&mut CPU, Error> {
+        self.harts.get_mut(hart).ok_or(Error::NoHart(hart))
+    }
+
+    pub fn get_pc(&self, hart: usize) -> Result<usize, Error> {
+        Ok(self.hart(hart)?.get_pc())
+    }
+
+    pub fn set_pc(&mut self, hart: usize, pc: usize) -> Result<(), Error> {
+        self.hart_mut(hart)?.set_pc(pc);
+        self.checkpoint();
+        Ok(())
+    }
+
+    pub fn get_register(&self, hart: usize, register: usize) -> Result<u32, Error> {
+        let cpu = self.hart(hart)?;
+        if register >= 32 {
+            return Err(Error::NoRegister(register));
+        }
+        Ok(cpu.get_register(register))
+    }
+
+    pub fn set_register(&mut self, hart: usize, register: usize, data: u32) -> Result<(), Error> {
+        if register >= 32 {
+            return Err(Error::NoRegister(register));
+        }
+        self.hart_mut(hart)?.set_register(register, data);
+        self.checkpoint();
+        Ok(())
+    }
+
+    /// Reads `data.len()` bytes of memory or device registers from `address`.
+    pub fn read_memory(&self, address: usize, data: &mut [u8]) -> Result<(), Error> {
+        let bus = self.bus.borrow();
+        if !bus.contains(address, data.len()) {
+            return Err(Error::Bus { address, size: data.len() });
+        }
+        for (index, byte) in data.iter_mut().enumerate() {
+            *byte = bus.get8(address + index)?;
+        }
+        Ok(())
+    }
+
+    /// Writes `data` to memory or device registers at `address`, as a guest store would.
+    pub fn write_memory(&mut self, address: usize, data: &[u8]) -> Result<(), Error> {
+        let mut bus = self.bus.borrow_mut();
+        if !bus.contains(address, data.len()) {
+            return Err(Error::Bus { address, size: data.len() });
+        }
+        for (index, byte) in data.iter().enumerate() {
+            bus.set8(*byte, address + index)?;
+        }
+        drop(bus);
+        self.checkpoint();
+        Ok(())
+    }
+
+    pub fn read32(&self, address: usize) -> Result<u32, Error> {
+        let mut data = [0; 4];
+        self.read_memory(address, &mut data)?;
+        Ok(u32::from_le_bytes(data))
+    }
+
+    /// Instructions run by all harts so far, including those before a restored snapshot.
+    pub fn get_instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Saves the registers, PC, CSRs, counters, PMP and vector state of every hart, memory,
+    /// the CLINT and the scheduler position. The snapshot can be restored into a machine
+    /// built with the same memory size, harts and VLEN.
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        self.save(true)
+    }
+
+    /// Saves a snapshot, leaving out memory unless `memory` is set.
+    fn save(&self, memory: bool) -> Vec<u8> {
+        let mut snapshot = Writer::new();
+        snapshot.write_u64(self.harts.len() as u64);
+        snapshot.write_u64(self.current as u64);
+        snapshot.write_u64(self.executed as u64);
+        snapshot.write_u64(self.instructions);
+        if memory {
+            self.bus.borrow().save(&mut snapshot);
+        } else {
+            self.bus.borrow().save_devices(&mut snapshot);
+        }
+        for hart in &self.harts {
+            hart.save(&mut snapshot);
+        }
+        snapshot.finish()
+    }
+
+    /// Restores a snapshot made by `save_snapshot`. Tracers, checkers and hooks stay attached,
+    /// and any history starts again from the restored state. If the snapshot turns out to be
+    /// invalid part way through, the machine is left partly restored.
+    pub fn load_snapshot(&mut self, snapshot: &[u8]) -> Result<(), Error> {
+        self.restore(snapshot, true)?;
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        self.checkpoint();
+        Ok(())
+    }
+
+    /// Restores a snapshot made by `save`, with the same `memory`.
+    fn restore(&mut self, snapshot: &[u8], memory: bool) -> Result<(), Error> {
+        let mut snapshot = Reader::new(snapshot)?;
+        snapshot.expect("number of harts", self.harts.len())?;
+        let current = snapshot.read_usize()?;
+        if current >= self.harts.len() {
+            return Err(Error::Snapshot(format!("hart {} does not exist", current)));
+        }
+        self.current = current;
+        self.executed = snapshot.read_usize()?.min(self.quantum);
+        self.instructions = snapshot.read_u64()?;
+        if memory {
+            self.bus.borrow_mut().restore(&mut snapshot)?;
+        } else {
+            self.bus.borrow_mut().restore_devices(&mut snapshot)?;
+        }
+        for hart in self.harts.iter_mut() {
+            hart.restore(&mut snapshot)?;
+        }
+        snapshot.finish()
+    }
+
+    /// Keeps a snapshot every `interval` instructions from now on, so that `reverse_step` and
+    /// `reverse_until` can go back to any point since. Changes made through the register, PC
+    /// and memory setters are kept too.
+    pub fn record_history(&mut self, interval: u64) {
+        self.history = Some(History::new(interval));
+        self.checkpoint();
+    }
+
+    /// Adds the current state to the history, if one is kept.
+    fn checkpoint(&mut self) {
+        if self.history.is_some() {
+            let state = self.save(false);
+            if let Some(history) = self.history.as_mut() {
+                history.record(self.instructions, state, self.bus.borrow_mut().memory_mut());
+            }
+        }
+    }
+
+    /// Restores the latest history snapshot at or before `target` instructions and runs
+    /// forward to `target`, calling `visit` at every point on the way, including both ends.
+    /// Replayed instructions are not traced, checked or reported, but hooks run again. Returns
+    /// when the snapshot was taken, or None if the history does not reach back that far.
+    fn replay(&mut self, target: u64, mut visit: impl FnMut(&Machine)) -> Result<Option<u64>, Error> {
+        let Some((taken, state)) = self.history.as_ref()
+            .and_then(|history| history.rewind(target, self.bus.borrow_mut().memory_mut())) else {
+            return Ok(None);
+        };
+        let state = state.to_vec();
+        let observers: Vec<Observers> = self.harts.iter_mut().map(CPU::take_observers).collect();
+        let restored = self.restore(&state, false);
+        if restored.is_ok() {
+            visit(self);
+        }
+        while restored.is_ok() && self.instructions < target {
+            if self.run_limited(1) == 0 {
+                // Harts stopped by hooks were resumed the first time round
+                self.resume();
+                if !self.running() {
+                    break;
+                }
+                continue;
+            }
+            visit(self);
+        }
+        for (hart, observers) in self.harts.iter_mut().zip(observers) {
+            hart.set_observers(observers);
+        }
+        restored.map(|()| Some(taken))
+    }
+
+    /// Goes back one instruction, returning false if the history does not reach that far.
+    pub fn reverse_step(&mut self) -> Result<bool, Error> {
+        if self.instructions == 0 {
+            return Ok(false);
+        }
+        Ok(self.replay(self.instructions - 1, |_| ())?.is_some())
+    }
+
+    /// Goes back to the latest earlier point at which `condition` held, checked between
+    /// instructions. Returns false if there is none, leaving the machine at the start of the
+    /// history.
+    pub fn reverse_until(&mut self, mut condition: impl FnMut(&Machine) -> bool) -> Result<bool, Error> {
+        let Some(start) = self.history.as_ref().and_then(History::start) else {
+            return Ok(false);
+        };
+        let mut end = self.instructions;
+        while end > start {
+            let mut found = None;
+            let Some(taken) = self.replay(end - 1, |machine| {
+                if condition(machine) {
+                    found = Some(machine.instructions);
+                }
+            })? else {
+                break;
+            };
+            if let Some(found) = found {
+                self.replay(found, |_| ())?;
+                return Ok(true);
+            }
+            end = taken;
+        }
+        self.replay(start, |_| ())?;
+        Ok(false)
+    }
+
+    pub fn dump_memory(&self) {
+        self.bus.borrow().dump()
+    }
+
+    pub fn dump_registers(&self) {
+        for (hart_id, hart) in self.harts.iter().enumerate() {
+            if self.harts.len() > 1 {
+                println!("hart {}", hart_id);
+            }
+            hart.dump_registers();
+        }
+    }
+
+    pub fn dump_csrs(&self) {
+        for (hart_id, hart) in self.harts.iter().enumerate() {
+            if self.harts.len() > 1 {
+                println!("hart {}", hart_id);
+            }
+            hart.dump_csrs();
+        }
+    }
+
+    pub fn dump_vector_registers(&self) {
+        for (hart_id, hart) in self.harts.iter().enumerate() {
+            if self.harts.len() > 1 {
+                println!("hart {}", hart_id);
+            }
+            hart.dump_vector_registers();
+        }
+    }
+}
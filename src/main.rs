@@ -1,13 +1,17 @@
-use clap::Parser;
-use getch::Getch;
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::net::TcpListener;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use cpu::CPU;
-use memory::Memory;
+use clap::Parser;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
 
-mod instruction;
-mod memory;
-mod registers;
-mod cpu;
+use riscv_emulator::conformance::Outcome;
+use riscv_emulator::debugger::Debugger;
+use riscv_emulator::{conformance, gdb, rvfi, Checker, Elf, Engine, Error, Machine, MachineBuilder, Misaligned, TraceFormat, Tracer};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -16,43 +20,306 @@ struct Args {
     #[arg(short, long)]
     memory: usize,
 
-    /// Run emulator in interactive mode. (Space - run next instruction, m - dump memory, r - dump registers)
+    /// Run emulator in interactive mode, a debugger prompt. Type help there for its commands
     #[arg(short, long, default_value_t = false)]
     interactive: bool,
 
-    /// Program file to emulate
-    file: String,
+    /// Wait for GDB on this local TCP port and let it debug the program, instead of running it
+    #[arg(long, conflicts_with = "interactive")]
+    gdb: Option<u16>,
+
+    /// Instructions between the snapshots interactive mode and --gdb keep for reverse execution
+    #[arg(long, default_value_t = 10_000)]
+    history_interval: u64,
+
+    /// Width of each vector register in bits (VLEN)
+    #[arg(long, default_value_t = 128)]
+    vlen: usize,
+
+    /// Widest supported vector element in bits (ELEN)
+    #[arg(long, default_value_t = 64)]
+    elen: usize,
+
+    /// Fill tail and masked-off elements with all ones when vtype marks them agnostic
+    #[arg(long, default_value_t = false)]
+    vector_agnostic_ones: bool,
+
+    /// Size in bytes of the cache block cleared by CBO.ZERO
+    #[arg(long, default_value_t = 64)]
+    cache_block_size: usize,
+
+    /// How misaligned loads and stores are handled. Misaligned atomics always raise an access fault
+    #[arg(long, value_enum, default_value_t = Misaligned::Allow)]
+    misaligned: Misaligned,
+
+    /// Stop when a hart executes code stored to since its last FENCE.I, reporting the PC
+    #[arg(long, default_value_t = false)]
+    strict_fence_i: bool,
+
+    /// Execution engine. Interactive mode always single-steps
+    #[arg(long, value_enum, default_value_t = Engine::Interp)]
+    engine: Engine,
+
+    /// Extensions to implement, such as rv32ia_zicsr_zifencei. Defaults to all supported ones
+    #[arg(long)]
+    isa: Option<String>,
+
+    /// Number of harts sharing memory
+    #[arg(long, default_value_t = 1)]
+    harts: usize,
+
+    /// Instructions each hart runs before the scheduler switches to the next
+    #[arg(long, default_value_t = 100)]
+    quantum: usize,
+
+    /// Print a line for every executed instruction
+    #[arg(long, default_value_t = false)]
+    trace: bool,
+
+    /// Write the trace to a file instead of stdout. Implies --trace
+    #[arg(long)]
+    trace_file: Option<String>,
+
+    /// What each trace line shows
+    #[arg(long, value_enum, default_value_t = TraceFormat::Pc)]
+    trace_format: TraceFormat,
+
+    /// Compare every committed instruction against a reference commit log, in Spike or RVFI
+    /// format, and stop at the first divergence
+    #[arg(long)]
+    check_against: Option<String>,
+
+    /// Instructions shown before a divergence found by --check-against
+    #[arg(long, default_value_t = 10)]
+    check_history: usize,
+
+    /// Run each file as a riscv-tests or riscv-arch-test ELF executable and print a summary
+    #[arg(long, default_value_t = false)]
+    conformance: bool,
+
+    /// Directory for the signatures of --conformance tests. Defaults to each test's directory
+    #[arg(long)]
+    signature_dir: Option<String>,
+
+    /// Serve RVFI-DII on this local TCP port, executing injected instructions instead of a program
+    #[arg(long)]
+    rvfi_dii: Option<u16>,
+
+    /// Save a snapshot of the machine to this file on SIGUSR1, at --snapshot-at or on `save` in
+    /// interactive mode
+    #[arg(long)]
+    save_snapshot: Option<String>,
+
+    /// Save a snapshot once this many instructions have run in total
+    #[arg(long, requires = "save_snapshot")]
+    snapshot_at: Option<u64>,
+
+    /// Restore a snapshot saved with --save-snapshot before running. The machine options must
+    /// match those it was saved with
+    #[arg(long)]
+    load_snapshot: Option<String>,
+
+    /// Program file to emulate, or the tests to run with --conformance
+    #[arg(required_unless_present_any = ["rvfi_dii", "load_snapshot"])]
+    file: Vec<String>,
+}
+
+/// Instructions run between checks for a snapshot request.
+const SNAPSHOT_POLL: u64 = 10_000;
+
+/// Set by the SIGUSR1 handler and cleared once the snapshot is saved.
+static SNAPSHOT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_snapshot(_: libc::c_int) {
+    SNAPSHOT_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Set by the SIGINT handler to stop the command the debugger is running.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
 }
 
 fn main() {
     let args = Args::parse();
+    match run(&args) {
+        Ok(true) => (),
+        Ok(false) => std::process::exit(1),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs whatever `args` ask for, returning whether it succeeded: the program ran without a
+/// guest fault or divergence from the reference, or every conformance test passed.
+fn run(args: &Args) -> Result<bool, Error> {
+    let mut builder = MachineBuilder::new(args.memory)
+        .harts(args.harts)
+        .quantum(args.quantum)
+        .engine(args.engine)
+        .strict_fence_i(args.strict_fence_i)
+        .misaligned(args.misaligned)
+        .vector(args.vlen, args.elen)
+        .vector_agnostic_ones(args.vector_agnostic_ones)
+        .cache_block_size(args.cache_block_size);
+    if let Some(isa) = &args.isa {
+        builder = builder.isa(isa);
+    }
+    builder.build()?;
+
+    if let Some(port) = args.rvfi_dii {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("RVFI-DII server listening on port {}", listener.local_addr()?.port());
+        rvfi::listen(&listener, &builder.harts(1))?;
+        return Ok(true);
+    }
+    if args.conformance {
+        let results = conformance::run_tests(&args.file, &builder.harts(1), args.signature_dir.as_deref());
+        return Ok(print_conformance(&results));
+    }
+    if args.file.len() > 1 {
+        return Err(Error::Config("only one program file can be run outside --conformance".to_string()));
+    }
+
+    let mut elf = None;
+    if let Some(file) = args.file.first() {
+        let program = fs::read(file).map_err(|error| Error::Load(format!("{}: {}", file, error)))?;
+        if program.starts_with(b"\x7fELF") {
+            let parsed = Elf::parse(&program).map_err(|error| Error::Load(format!("{}: {}", file, error)))?;
+            builder = builder.elf(&parsed);
+            elf = Some(parsed);
+        } else {
+            builder = builder.program(0, &program);
+        }
+    }
+    let mut machine = builder.build()?;
+    if let Some(file) = &args.load_snapshot {
+        machine.load_snapshot(&fs::read(file).map_err(|error| in_file(file, error))?)?;
+    }
+    if let Some(at) = args.snapshot_at.filter(|&at| at <= machine.get_instructions()) {
+        let message = format!("--snapshot-at {} is not after the {} instructions already run", at, machine.get_instructions());
+        return Err(Error::Config(message));
+    }
+    if args.trace || args.trace_file.is_some() {
+        let output: Box<dyn Write> = match &args.trace_file {
+            Some(file) => Box::new(BufWriter::new(File::create(file).map_err(|error| in_file(file, error))?)),
+            None => Box::new(io::stdout()),
+        };
+        machine.set_tracer(Tracer::new(args.trace_format, output, args.harts));
+    }
+    let checker = match &args.check_against {
+        Some(file) => {
+            let reference = BufReader::new(File::open(file).map_err(|error| in_file(file, error))?);
+            Some(Rc::new(RefCell::new(Checker::new(Box::new(reference), args.check_history))))
+        }
+        None => None,
+    };
+    if let Some(checker) = &checker {
+        machine.set_checker(checker.clone());
+    }
+
+    if let Some(port) = args.gdb {
+        machine.record_history(args.history_interval);
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("GDB server listening on port {}", listener.local_addr()?.port());
+        gdb::listen(&listener, &mut machine)?;
+    } else if args.interactive {
+        machine.record_history(args.history_interval);
+        let mut debugger = Debugger::new(&mut machine).interrupt(&INTERRUPTED);
+        if let Some(elf) = &elf {
+            debugger = debugger.symbols(elf);
+        }
+        if let Some(file) = &args.save_snapshot {
+            debugger = debugger.snapshot_file(file);
+        }
+        // SAFETY: the handler only stores to an atomic
+        unsafe { libc::signal(libc::SIGINT, interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t) };
 
-    let mut memory = Memory::new(args.memory);
-    memory.load_file(args.file.as_str()).expect("File not found");
-
-    let mut cpu = CPU::from_memory(&memory);
-
-
-    if args.interactive {
-        let g = Getch::new();
-        println!("---INTERACTIVE MODE---");
-        println!("<space> - run next command");
-        println!("m - dump memory");
-        println!("r - dump registers");
-        println!("q - quite");
-        println!();
-
-        while !cpu.halted {
-            let char = g.getch().unwrap_or(0) as char;
-            match char {
-                ' ' => cpu.tick(),
-                'r' => cpu.dump_registers(),
-                'm' => cpu.dump_memory(),
-                'q' => break,
-                _ => ()
+        let mut editor = DefaultEditor::new().map_err(io::Error::other)?;
+        println!("---INTERACTIVE MODE--- (help - list commands, Ctrl-C - stop running)");
+        loop {
+            let line = match editor.readline("(rv) ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(error) => return Err(io::Error::other(error).into()),
+            };
+            if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(line.as_str());
+            }
+            match debugger.execute(&mut machine, &line) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(error) => println!("{}", error),
             }
         }
+    } else if let Some(file) = &args.save_snapshot {
+        // SAFETY: the handler only stores to an atomic
+        unsafe { libc::signal(libc::SIGUSR1, request_snapshot as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+        let mut saved_at = false;
+        while machine.running() {
+            let chunk = match args.snapshot_at {
+                Some(at) if at > machine.get_instructions() => (at - machine.get_instructions()).min(SNAPSHOT_POLL),
+                _ => SNAPSHOT_POLL,
+            };
+            machine.run_for(chunk as usize);
+            let at = args.snapshot_at == Some(machine.get_instructions());
+            saved_at |= at;
+            if at || SNAPSHOT_REQUESTED.swap(false, Ordering::Relaxed) {
+                save_snapshot(&machine, file)?;
+            }
+        }
+        if let Some(at) = args.snapshot_at.filter(|_| !saved_at) {
+            eprintln!("warning: no snapshot saved: the program stopped after {} instructions, before --snapshot-at {}",
+                machine.get_instructions(), at);
+        }
     } else {
-        cpu.run();
+        machine.run();
+    }
+
+    if let Some(checker) = checker {
+        checker.borrow_mut().finish();
+        if let Some(divergence) = checker.borrow().get_divergence() {
+            eprint!("{}", divergence);
+            eprintln!("Registers:");
+            machine.dump_registers();
+            return Ok(false);
+        }
+    }
+    if let Some(fault) = machine.get_fault() {
+        eprintln!("{}", fault);
+        machine.dump_registers();
+        return Ok(false);
     }
+    Ok(true)
+}
+
+fn save_snapshot(machine: &Machine, file: &str) -> Result<(), Error> {
+    fs::write(file, machine.save_snapshot()).map_err(|error| in_file(file, error))?;
+    eprintln!("saved snapshot to {} after {} instructions", file, machine.get_instructions());
+    Ok(())
+}
+
+/// Prints a table of conformance test results and a summary, returning whether none failed.
+fn print_conformance(results: &[(String, Outcome)]) -> bool {
+    let width = results.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(4);
+    println!("{:width$}  Result", "Test");
+    for (name, outcome) in results {
+        println!("{:width$}  {}", name, outcome);
+    }
+
+    let passed = results.iter().filter(|(_, outcome)| *outcome == Outcome::Pass).count();
+    let skipped = results.iter().filter(|(_, outcome)| matches!(outcome, Outcome::Skipped(_))).count();
+    let failed = results.len() - passed - skipped;
+    println!();
+    println!("{} passed, {} failed, {} skipped", passed, failed, skipped);
+    failed == 0
+}
+
+/// Names `file` in an I/O error about it.
+fn in_file(file: &str, error: io::Error) -> Error {
+    Error::Io(io::Error::new(error.kind(), format!("{}: {}", file, error)))
 }
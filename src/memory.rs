@@ -1,73 +1,132 @@
-use std::{fs, io};
+use crate::error::Error;
+use crate::snapshot::{self, Reader, Writer};
+
+/// Memory is saved in pages of this size, leaving out those that are all zero. Writes are
+/// tracked by page of this size too.
+pub(crate) const SNAPSHOT_PAGE: usize = 4096;
 
 #[derive(Clone)]
 pub struct Memory {
     memory: Vec<u8>,
+    /// Whether each page has been written since `take_dirty` last cleared it
+    dirty: Vec<bool>,
 }
 
 impl Memory {
     pub fn new(size: usize) -> Self {
-        return Self { memory: vec![0; size] };
+        return Self { memory: vec![0; size], dirty: vec![false; size.div_ceil(SNAPSHOT_PAGE)] };
     }
 
-    pub fn load_file(&mut self, file: &str) -> Result<(), io::Error> {
-        let memory = fs::read(file)?;
-        for (index, byte) in memory.iter().enumerate() {
-           self.set8(*byte, index);
-        }
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// The `size` bytes at `index`, or a bus error when they run past the end of memory.
+    fn bytes(&self, index: usize, size: usize) -> Result<&[u8], Error> {
+        index.checked_add(size).and_then(|end| self.memory.get(index..end)).ok_or(Error::Bus { address: index, size })
+    }
+
+    fn bytes_mut(&mut self, index: usize, size: usize) -> Result<&mut [u8], Error> {
+        let bytes = index.checked_add(size).and_then(|end| self.memory.get_mut(index..end)).ok_or(Error::Bus { address: index, size })?;
+        self.dirty[index / SNAPSHOT_PAGE..=(index + size - 1) / SNAPSHOT_PAGE].fill(true);
+        Ok(bytes)
+    }
+
+    pub fn get8(&self, index: usize) -> Result<u8, Error> {
+        Ok(self.bytes(index, 1)?[0])
+    }
+
+    pub fn get16(&self, index: usize) -> Result<u16, Error> {
+        let bytes = self.bytes(index, 2)?;
+        return Ok(u16::from_le_bytes([bytes[0], bytes[1]]));
+    }
+
+    pub fn get32(&self, index: usize) -> Result<u32, Error> {
+        let bytes = self.bytes(index, 4)?;
+        return Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+    }
+
+    pub fn get8_sx(&self, index: usize) -> Result<u32, Error> {
+        Ok(self.get8(index)? as i8 as u32)
+    }
+
+    pub fn get16_sx(&self, index: usize) -> Result<u32, Error> {
+        Ok(self.get16(index)? as i16 as u32)
+    }
+
+    pub fn set8(&mut self, data: u8, index: usize) -> Result<(), Error> {
+        self.bytes_mut(index, 1)?[0] = data;
         Ok(())
     }
 
-    pub fn len(&self) -> usize {
-        self.memory.len()
+    pub fn set16(&mut self, data: u16, index: usize) -> Result<(), Error> {
+        self.bytes_mut(index, 2)?.copy_from_slice(&data.to_le_bytes());
+        Ok(())
     }
 
-    pub fn get8(&self, index: usize) -> u8 {
-        self.memory[index]
+    pub fn set32(&mut self, data: u32, index: usize) -> Result<(), Error> {
+        self.bytes_mut(index, 4)?.copy_from_slice(&data.to_le_bytes());
+        Ok(())
     }
 
-    pub fn get16(&self, index: usize) -> u16 {
-        let last = self.get8(index) as u16;
-        let first = self.get8(index + 1) as u16;
-        return first << 8 | last;
+    /// Sets the `size` bytes at `index` to zero.
+    pub fn zero(&mut self, index: usize, size: usize) -> Result<(), Error> {
+        self.bytes_mut(index, size)?.fill(0);
+        Ok(())
     }
 
-    pub fn get32(&self, index: usize) -> u32 {
-        let last = self.get16(index) as u32;
-        let first = self.get16(index + 2) as u32;
-        return first << 16 | last;
+    /// The pages written since the last call, by index, clearing their record.
+    pub fn take_dirty(&mut self) -> Vec<usize> {
+        let pages = self.dirty.iter().enumerate().filter(|&(_, &dirty)| dirty).map(|(page, _)| page).collect();
+        self.dirty.fill(false);
+        pages
     }
 
-    pub fn get8_sx(&self, index: usize) -> u32 {
-        let data = self.get8(index);
-        if data & 0x80 == 0 {
-            data as u32
-        } else {
-            data as u32 | 0xFFFFFF00
-        }
+    /// Marks a page as written, so that the next `take_dirty` returns it.
+    pub fn mark_dirty(&mut self, page: usize) {
+        self.dirty[page] = true;
     }
 
-    pub fn get16_sx(&self, index: usize) -> u32 {
-        let data = self.get16(index);
-        if data & 0x8000 == 0 {
-            data as u32
-        } else {
-            data as u32 | 0xFFFF0000
-        }
+    pub fn page(&self, page: usize) -> &[u8] {
+        let start = page * SNAPSHOT_PAGE;
+        &self.memory[start..(start + SNAPSHOT_PAGE).min(self.memory.len())]
     }
 
-    pub fn set8(&mut self, data: u8, index: usize) {
-        self.memory[index] = data;
+    /// Overwrites a page without marking it written.
+    pub fn set_page(&mut self, page: usize, data: &[u8]) {
+        let start = page * SNAPSHOT_PAGE;
+        self.memory[start..start + data.len()].copy_from_slice(data);
     }
 
-    pub fn set16(&mut self, data: u16, index: usize) {
-        self.set8(data as u8, index);
-        self.set8((data >> 8) as u8, index + 1);
+    /// Saves the pages holding any nonzero byte, each compressed.
+    pub fn save(&self, snapshot: &mut Writer) {
+        let pages: Vec<(usize, &[u8])> = self.memory.chunks(SNAPSHOT_PAGE).enumerate()
+            .filter(|(_, page)| page.iter().any(|&byte| byte != 0))
+            .collect();
+        snapshot.write_u64(self.memory.len() as u64);
+        snapshot.write_u64(pages.len() as u64);
+        for (index, page) in pages {
+            let packed = snapshot::pack(page);
+            snapshot.write_u64(index as u64);
+            snapshot.write_u64(packed.len() as u64);
+            snapshot.write_bytes(&packed);
+        }
     }
 
-    pub fn set32(&mut self, data: u32, index: usize) {
-        self.set16(data as u16, index);
-        self.set16((data >> 16) as u16, index + 2);
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        snapshot.expect("memory size", self.memory.len())?;
+        self.memory.fill(0);
+        self.dirty.fill(true);
+        for _ in 0..snapshot.read_u64()? {
+            let index = snapshot.read_usize()?;
+            let size = snapshot.read_usize()?;
+            let packed = snapshot.read_bytes(size)?;
+            let Some(page) = self.memory.chunks_mut(SNAPSHOT_PAGE).nth(index) else {
+                return Err(Error::Snapshot(format!("page {} is outside memory", index)));
+            };
+            snapshot::unpack(packed, page)?;
+        }
+        Ok(())
     }
 
     pub fn dump(&self) {
@@ -75,13 +134,13 @@ impl Memory {
             print!("{:08x}  ", i * 16);
 
             for j in 0..16 {
-                print!("{:02x} ", self.get8(i * 16 + j));
+                print!("{:02x} ", self.memory[i * 16 + j]);
                 if j == 7 { print!(" ") }
             }
 
             print!("  *");
             for j in 0..16 {
-                let byte = self.get8(i * 16 + j);
+                let byte = self.memory[i * 16 + j];
                 match byte {
                     0x20..=0x7E =>
                         print!("{}", byte as char),
@@ -102,24 +161,27 @@ mod tests {
     #[test]
     fn test_get32() {
         let mut memory = Memory::new(8);
-        memory.set8(0x00, 0);
-        memory.set8(0x11, 1);
-        memory.set8(0x22, 2);
-        memory.set8(0x33, 3);
-        memory.set8(0x44, 4);
-        memory.set8(0x55, 5);
-        memory.set8(0x66, 6);
-        memory.set8(0x77, 7);
-
-
-        assert_eq!(memory.get16(0), 0x1100);
-        assert_eq!(memory.get16(0), 0x2211);
-        assert_eq!(memory.get16(2), 0x3322);
-
-        assert_eq!(memory.get32(0), 0x33221100);
-        assert_eq!(memory.get32(1), 0x44332211);
-        assert_eq!(memory.get32(2), 0x55443322);
-        assert_eq!(memory.get32(3), 0x66554433);
-        assert_eq!(memory.get32(4), 0x77665544);
+        memory.set8(0x00, 0).unwrap();
+        memory.set8(0x11, 1).unwrap();
+        memory.set8(0x22, 2).unwrap();
+        memory.set8(0x33, 3).unwrap();
+        memory.set8(0x44, 4).unwrap();
+        memory.set8(0x55, 5).unwrap();
+        memory.set8(0x66, 6).unwrap();
+        memory.set8(0x77, 7).unwrap();
+
+
+        assert_eq!(memory.get16(0).unwrap(), 0x1100);
+        assert_eq!(memory.get16(1).unwrap(), 0x2211);
+        assert_eq!(memory.get16(2).unwrap(), 0x3322);
+
+        assert_eq!(memory.get32(0).unwrap(), 0x33221100);
+        assert_eq!(memory.get32(1).unwrap(), 0x44332211);
+        assert_eq!(memory.get32(2).unwrap(), 0x55443322);
+        assert_eq!(memory.get32(3).unwrap(), 0x66554433);
+        assert_eq!(memory.get32(4).unwrap(), 0x77665544);
+        assert!(memory.get32(5).is_err());
+        assert!(memory.set8(0, 8).is_err());
+        assert!(memory.get8(usize::MAX).is_err());
     }
 }
\ No newline at end of file
@@ -0,0 +1,180 @@
+use crate::error::Error;
+use crate::snapshot::{Reader, Writer};
+use crate::trap::Privilege;
+
+const READ: u8 = 1 << 0;
+const WRITE: u8 = 1 << 1;
+const EXECUTE: u8 = 1 << 2;
+const MATCH_SHIFT: u8 = 3;
+const LOCK: u8 = 1 << 7;
+
+const OFF: u8 = 0;
+const TOR: u8 = 1;
+const NA4: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// Physical memory protection with 64 entries, configured through `pmpcfg0..15`
+/// (four entries per register) and `pmpaddr0..63` (address bits 33:2).
+pub struct Pmp {
+    config: [u8; 64],
+    address: [u32; 64],
+}
+
+impl Pmp {
+    pub fn new() -> Self {
+        Self { config: [0; 64], address: [0; 64] }
+    }
+
+    pub fn save(&self, snapshot: &mut Writer) {
+        for (config, address) in self.config.iter().zip(&self.address) {
+            snapshot.write_u8(*config);
+            snapshot.write_u32(*address);
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        for (config, address) in self.config.iter_mut().zip(self.address.iter_mut()) {
+            *config = snapshot.read_u8()?;
+            *address = snapshot.read_u32()?;
+        }
+        Ok(())
+    }
+
+    fn locked(&self, entry: usize) -> bool {
+        self.config[entry] & LOCK != 0
+    }
+
+    fn match_mode(&self, entry: usize) -> u8 {
+        self.config[entry] >> MATCH_SHIFT & 0b11
+    }
+
+    pub fn get_config(&self, register: usize) -> u32 {
+        (0..4).rev().fold(0, |data, byte| data << 8 | self.config[register * 4 + byte] as u32)
+    }
+
+    /// Locked entries ignore writes. The reserved W-without-R combination is stored as no access.
+    pub fn set_config(&mut self, register: usize, data: u32) {
+        for byte in 0..4 {
+            let entry = register * 4 + byte;
+            if self.locked(entry) {
+                continue;
+            }
+
+            let mut config = (data >> (byte * 8)) as u8 & !0b0110_0000;
+            if config & (READ | WRITE) == WRITE {
+                config &= !WRITE;
+            }
+            self.config[entry] = config;
+        }
+    }
+
+    pub fn get_address(&self, entry: usize) -> u32 {
+        self.address[entry]
+    }
+
+    /// An address is also frozen when it forms the bottom of a locked TOR region above it.
+    pub fn set_address(&mut self, entry: usize, data: u32) {
+        let top_of_locked = entry + 1 < 64 && self.locked(entry + 1) && self.match_mode(entry + 1) == TOR;
+        if !self.locked(entry) && !top_of_locked {
+            self.address[entry] = data;
+        }
+    }
+
+    /// The byte range `[start, end)` covered by `entry`, if it is enabled.
+    fn region(&self, entry: usize) -> Option<(u64, u64)> {
+        let address = self.address[entry] as u64;
+        match self.match_mode(entry) {
+            OFF => None,
+            TOR => {
+                let bottom = if entry == 0 { 0 } else { (self.address[entry - 1] as u64) << 2 };
+                Some((bottom, address << 2))
+            }
+            NA4 => Some((address << 2, (address << 2) + 4)),
+            _ => {
+                let ones = address.trailing_ones();
+                let base = (address & !((1 << ones) - 1)) << 2;
+                Some((base, base + (8 << ones)))
+            }
+        }
+    }
+
+    /// Whether `privilege` may perform `access` on `size` bytes at `address`. The lowest
+    /// matching entry decides; an access straddling its boundary fails. Machine mode is only
+    /// bound by locked entries, and lower modes fail when no entry matches.
+    pub fn check(&self, address: usize, size: usize, access: Access, privilege: Privilege) -> bool {
+        let start = address as u64;
+        let end = start + size as u64;
+
+        for entry in 0..64 {
+            let Some((bottom, top)) = self.region(entry) else { continue };
+            if end <= bottom || start >= top {
+                continue;
+            }
+            if start < bottom || end > top {
+                return false;
+            }
+
+            let config = self.config[entry];
+            if privilege == Privilege::Machine && config & LOCK == 0 {
+                return true;
+            }
+            let permission = match access {
+                Access::Read => READ,
+                Access::Write => WRITE,
+                Access::Execute => EXECUTE,
+            };
+            return config & permission != 0;
+        }
+
+        privilege == Privilege::Machine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pmp::{Access, Pmp};
+    use crate::trap::Privilege;
+
+    #[test]
+    fn pmp_matches_regions() {
+        let mut pmp = Pmp::new();
+        pmp.set_address(0, 0x1000 >> 2);
+        pmp.set_address(1, 0x2000 >> 2);
+        pmp.set_address(2, (0x4000 >> 2) | 0x1FF);
+        pmp.set_address(3, 0x8000 >> 2);
+        pmp.set_config(0, 0x13_1B_0D_00);
+
+        assert!(pmp.check(0x1000, 4, Access::Execute, Privilege::User));
+        assert!(!pmp.check(0x1FFC, 4, Access::Write, Privilege::User));
+        assert!(!pmp.check(0x0FFE, 4, Access::Execute, Privilege::User));
+        assert!(pmp.check(0x4FFC, 4, Access::Write, Privilege::Supervisor));
+        assert!(!pmp.check(0x5000, 4, Access::Read, Privilege::Supervisor));
+        assert!(pmp.check(0x8000, 4, Access::Read, Privilege::User));
+        assert!(!pmp.check(0x8004, 4, Access::Read, Privilege::User));
+        assert!(pmp.check(0x9000, 4, Access::Write, Privilege::Machine));
+    }
+
+    #[test]
+    fn pmp_lock_applies_to_machine_mode() {
+        let mut pmp = Pmp::new();
+        pmp.set_address(0, 0x1000 >> 2);
+        pmp.set_address(1, 0x2000 >> 2);
+        pmp.set_config(0, 0x8D_00);
+
+        assert!(!pmp.check(0x1800, 4, Access::Write, Privilege::Machine));
+        assert!(pmp.check(0x1800, 4, Access::Read, Privilege::Machine));
+
+        pmp.set_config(0, 0);
+        pmp.set_address(0, 0);
+        pmp.set_address(1, 0);
+        assert_eq!(pmp.get_config(0), 0x8D_00);
+        assert_eq!(pmp.get_address(0), 0x1000 >> 2);
+        assert_eq!(pmp.get_address(1), 0x2000 >> 2);
+    }
+}
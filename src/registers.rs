@@ -1,14 +1,45 @@
+use crate::error::Error;
+use crate::snapshot::{Reader, Writer};
+
 pub struct Registers {
     registers: Vec<u32>,
+    /// Writes since the last `take_writes`, recorded only while tracing.
+    writes: Option<Vec<(usize, u32)>>,
 }
 
 impl Registers {
     pub fn new() -> Self {
-        Self { registers: vec![0xF0F0F0F0; 32] }
+        Self { registers: vec![0; 32], writes: None }
     }
 
     pub fn set(&mut self, register: usize, data: u32) {
         if register > 0 { self.registers[register] = data }
+        if let Some(writes) = self.writes.as_mut() {
+            if register > 0 { writes.push((register, data)) }
+        }
+    }
+
+    pub fn record_writes(&mut self) {
+        self.writes = Some(Vec::new());
+    }
+
+    pub fn stop_recording_writes(&mut self) {
+        self.writes = None;
+    }
+
+    pub fn take_writes(&mut self) -> Vec<(usize, u32)> {
+        self.writes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn save(&self, snapshot: &mut Writer) {
+        self.registers.iter().for_each(|data| snapshot.write_u32(*data));
+    }
+
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        for data in self.registers.iter_mut() {
+            *data = snapshot.read_u32()?;
+        }
+        Ok(())
     }
 
     pub fn get(&self, register: usize) -> u32 {
@@ -16,6 +47,12 @@ impl Registers {
         self.registers[register]
     }
 
+    /// The raw registers for native code, which must leave x0 at zero.
+    #[cfg(feature = "jit")]
+    pub fn as_mut_slice(&mut self) -> &mut [u32] {
+        &mut self.registers
+    }
+
     pub fn dump(&self) {
         for (i, data) in self.registers.iter().enumerate() {
             if i % 8 == 0 { print!("x{:02}  ", i) }
@@ -42,7 +79,7 @@ mod tests {
 
     #[test]
     fn all_zero_initialized() {
-        let mut registers = Registers::new();
+        let registers = Registers::new();
 
         for i in 1..24 {
             assert_eq!(registers.get(i), 0)
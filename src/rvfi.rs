@@ -0,0 +1,227 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::rc::Rc;
+
+use crate::decode_cache::Decoded;
+use crate::instruction::Instruction;
+use crate::error::Error;
+use crate::machine::{Machine, MachineBuilder};
+use crate::trace::Retired;
+
+const COMMAND_END_OF_TRACE: u8 = 0;
+const COMMAND_INSTRUCTION: u8 = 1;
+
+/// An RVFI execution trace packet in the layout RVFI-DII sends over the wire: ten 64-bit
+/// fields followed by eight bytes, all little-endian. Register sources are not reported.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    pub order: u64,
+    pub pc_rdata: u64,
+    pub pc_wdata: u64,
+    pub insn: u64,
+    pub rs1_data: u64,
+    pub rs2_data: u64,
+    pub rd_wdata: u64,
+    pub mem_addr: u64,
+    pub mem_rdata: u64,
+    pub mem_wdata: u64,
+    pub mem_rmask: u8,
+    pub mem_wmask: u8,
+    pub rs1_addr: u8,
+    pub rs2_addr: u8,
+    pub rd_addr: u8,
+    pub trap: u8,
+    pub halt: u8,
+    pub intr: u8,
+}
+
+impl Packet {
+    pub fn to_bytes(self) -> [u8; 88] {
+        let mut bytes = [0; 88];
+        let words = [self.order, self.pc_rdata, self.pc_wdata, self.insn, self.rs1_data, self.rs2_data,
+            self.rd_wdata, self.mem_addr, self.mem_rdata, self.mem_wdata];
+        for (index, word) in words.iter().enumerate() {
+            bytes[index * 8..index * 8 + 8].copy_from_slice(&word.to_le_bytes());
+        }
+        bytes[80..].copy_from_slice(&[self.mem_rmask, self.mem_wmask, self.rs1_addr, self.rs2_addr,
+            self.rd_addr, self.trap, self.halt, self.intr]);
+        bytes
+    }
+}
+
+/// Builds the RVFI packet of each instruction a hart retires, numbering them in order.
+#[derive(Default)]
+pub struct Rvfi {
+    order: u64,
+    last: Option<Packet>,
+}
+
+impl Rvfi {
+    /// Records `retired`, after which the hart continues at `next_pc`.
+    pub fn record(&mut self, retired: &Retired, next_pc: usize) {
+        let mut packet = Packet {
+            order: self.order,
+            pc_rdata: retired.pc as u64,
+            pc_wdata: next_pc as u64,
+            insn: retired.instruction.get_raw() as u64,
+            trap: retired.trapped as u8,
+            ..Packet::default()
+        };
+        self.order += 1;
+
+        if let Some(&(register, data)) = retired.registers.last() {
+            packet.rd_addr = register as u8;
+            packet.rd_wdata = data as u64;
+        }
+        if let Some(load) = retired.memory.iter().find(|access| !access.write) {
+            packet.mem_addr = load.address as u64;
+            packet.mem_rdata = load.data as u64;
+            packet.mem_rmask = ((1u32 << load.size) - 1) as u8;
+        }
+        if let Some(store) = retired.memory.iter().find(|access| access.write) {
+            packet.mem_addr = store.address as u64;
+            packet.mem_wdata = store.data as u64;
+            packet.mem_wmask = ((1u32 << store.size.min(8)) - 1) as u8;
+        }
+        self.last = Some(packet);
+    }
+
+    pub fn take(&mut self) -> Option<Packet> {
+        self.last.take()
+    }
+}
+
+/// Serves one RVFI-DII session on `stream`. Each 8-byte instruction packet holds the
+/// instruction word, a 16-bit time and a command: instructions are injected at the current
+/// PC of hart 0 and answered with their execution packet, and an end of trace is answered
+/// with a halt packet before the machine is replaced by a fresh one from `builder`.
+pub fn serve(stream: &mut (impl Read + Write), builder: &MachineBuilder) -> Result<(), Error> {
+    let rvfi = Rc::new(RefCell::new(Rvfi::default()));
+    let start = |rvfi: &Rc<RefCell<Rvfi>>| -> Result<Machine, Error> {
+        let mut machine = builder.build()?;
+        machine.harts_mut()[0].set_rvfi(rvfi.clone());
+        Ok(machine)
+    };
+    let mut machine = start(&rvfi)?;
+
+    let mut command = [0; 8];
+    loop {
+        match stream.read_exact(&mut command) {
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+
+        let packet = match command[6] {
+            COMMAND_INSTRUCTION => {
+                let instruction = Instruction::from_u32(u32::from_le_bytes(command[..4].try_into().unwrap()));
+                machine.harts_mut()[0].execute_instruction(&Decoded::new(instruction));
+                let Some(packet) = rvfi.borrow_mut().take() else {
+                    let message = format!("injected instruction {:08x} did not retire", instruction.get_raw());
+                    return Err(io::Error::other(message).into());
+                };
+                packet
+            }
+            COMMAND_END_OF_TRACE => {
+                *rvfi.borrow_mut() = Rvfi::default();
+                machine = start(&rvfi)?;
+                Packet { halt: 1, ..Packet::default() }
+            }
+            command => {
+                let error = io::Error::new(io::ErrorKind::InvalidData, format!("unknown RVFI-DII command {}", command));
+                return Err(error.into());
+            }
+        };
+        stream.write_all(&packet.to_bytes())?;
+    }
+}
+
+/// Serves the RVFI-DII sessions `listener` accepts one after another.
+pub fn listen(listener: &TcpListener, builder: &MachineBuilder) -> Result<(), Error> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        stream.set_nodelay(true)?;
+        serve(&mut stream, builder)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Read, Write};
+
+    use crate::machine::MachineBuilder;
+    use crate::rvfi::serve;
+
+    /// Reads the commands sent by a harness and collects the replies.
+    struct Session {
+        commands: Cursor<Vec<u8>>,
+        replies: Vec<u8>,
+    }
+
+    impl Read for Session {
+        fn read(&mut self, data: &mut [u8]) -> io::Result<usize> {
+            self.commands.read(data)
+        }
+    }
+
+    impl Write for Session {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.replies.write(data)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn field(reply: &[u8], index: usize) -> u64 {
+        u64::from_le_bytes(reply[index * 8..index * 8 + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn injected_instructions_report_their_effects() {
+        let mut commands = Vec::new();
+        // addi x5,x0,0x42; sw x5,16(x0); lw x6,16(x0); end of trace; addi x5,x5,1
+        for (instruction, command) in [(0x04200293u32, 1u8), (0x00502823, 1), (0x01002303, 1), (0, 0), (0x00128293, 1)] {
+            commands.extend(instruction.to_le_bytes());
+            commands.extend([0, 0, command, 0]);
+        }
+        let mut session = Session { commands: Cursor::new(commands), replies: Vec::new() };
+        serve(&mut session, &MachineBuilder::new(64)).unwrap();
+
+        let replies: Vec<&[u8]> = session.replies.chunks(88).collect();
+        assert_eq!(replies.len(), 5);
+
+        let addi = replies[0];
+        assert_eq!((field(addi, 0), field(addi, 1), field(addi, 2), field(addi, 3)), (0, 0, 4, 0x04200293));
+        assert_eq!((addi[84], field(addi, 6)), (5, 0x42));
+
+        let sw = replies[1];
+        assert_eq!((field(sw, 7), field(sw, 9), sw[81], sw[84]), (16, 0x42, 0xF, 0));
+        let lw = replies[2];
+        assert_eq!((field(lw, 7), field(lw, 8), lw[80], lw[84], field(lw, 6)), (16, 0x42, 0xF, 6, 0x42));
+
+        assert_eq!(replies[3][86], 1);
+        let reset = replies[4];
+        assert_eq!((field(reset, 0), field(reset, 1), field(reset, 6)), (0, 0, 1));
+    }
+
+    #[test]
+    fn traps_redirect_to_mtvec() {
+        let mut commands = Vec::new();
+        // addi x5,x0,1; illegal; addi x5,x5,1
+        for instruction in [0x00100293u32, 0, 0x00128293] {
+            commands.extend(instruction.to_le_bytes());
+            commands.extend([0, 0, 1, 0]);
+        }
+        let mut session = Session { commands: Cursor::new(commands), replies: Vec::new() };
+        serve(&mut session, &MachineBuilder::new(64)).unwrap();
+
+        let replies: Vec<&[u8]> = session.replies.chunks(88).collect();
+        let illegal = replies[1];
+        assert_eq!((field(illegal, 1), field(illegal, 2), illegal[85]), (4, 0, 1));
+        let addi = replies[2];
+        assert_eq!((field(addi, 1), field(addi, 2), addi[84], field(addi, 6)), (0, 4, 5, 2));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::error::Error;
+
+const MAGIC: &[u8; 8] = b"RVSNAPSH";
+/// Bumped whenever the layout changes. Snapshots of other versions are rejected.
+const VERSION: u32 = 1;
+
+/// Builds a snapshot: the magic and version, then each component's state in little-endian
+/// fields, in the order the machine saves them.
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        let mut writer = Self { bytes: MAGIC.to_vec() };
+        writer.write_u32(VERSION);
+        writer
+    }
+
+    pub fn write_u8(&mut self, data: u8) {
+        self.bytes.push(data);
+    }
+
+    pub fn write_u16(&mut self, data: u16) {
+        self.bytes.extend(data.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, data: u32) {
+        self.bytes.extend(data.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, data: u64) {
+        self.bytes.extend(data.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        self.bytes.extend_from_slice(data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Reads back the fields of a snapshot made by `Writer`.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Starts reading `bytes` after checking that they are a snapshot of this version.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+            return Err(Error::Snapshot("not a snapshot".to_string()));
+        };
+        let mut reader = Self { bytes };
+        let version = reader.read_u32()?;
+        if version != VERSION {
+            return Err(Error::Snapshot(format!("version {} is not supported, expected {}", version, VERSION)));
+        }
+        Ok(reader)
+    }
+
+    pub fn read_bytes(&mut self, size: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < size {
+            return Err(Error::Snapshot("truncated".to_string()));
+        }
+        let (data, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+        Ok(data)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a size or address, which is saved as 64 bits.
+    pub fn read_usize(&mut self) -> Result<usize, Error> {
+        let data = self.read_u64()?;
+        usize::try_from(data).map_err(|_| Error::Snapshot(format!("{:#x} is out of range", data)))
+    }
+
+    /// Reads a value the machine it is restored into must share, such as the memory size.
+    pub fn expect(&mut self, what: &str, expected: usize) -> Result<(), Error> {
+        let found = self.read_usize()?;
+        if found != expected {
+            return Err(Error::Snapshot(format!("{} is {} but the machine has {}", what, found, expected)));
+        }
+        Ok(())
+    }
+
+    /// Checks that the whole snapshot was read.
+    pub fn finish(self) -> Result<(), Error> {
+        if !self.bytes.is_empty() {
+            return Err(Error::Snapshot(format!("{} bytes left over", self.bytes.len())));
+        }
+        Ok(())
+    }
+}
+
+/// Compresses `data` with PackBits: a control byte `n` below 128 is followed by `n + 1`
+/// literal bytes, and one above 128 by a byte repeated `257 - n` times.
+pub fn pack(data: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        let run = data[index..].iter().take(128).take_while(|&&byte| byte == data[index]).count();
+        if run >= 2 {
+            packed.extend([(257 - run) as u8, data[index]]);
+            index += run;
+            continue;
+        }
+
+        let start = index;
+        while index < data.len() && index - start < 128 && data.get(index + 1) != Some(&data[index]) {
+            index += 1;
+        }
+        packed.push((index - start - 1) as u8);
+        packed.extend_from_slice(&data[start..index]);
+    }
+    packed
+}
+
+/// Decompresses PackBits data made by `pack`, which must fill `data` exactly.
+pub fn unpack(mut packed: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let corrupt = || Error::Snapshot("corrupt compressed memory".to_string());
+    let mut index = 0;
+    while let Some((&control, rest)) = packed.split_first() {
+        let (size, bytes, rest) = match control {
+            0..=127 => {
+                let size = control as usize + 1;
+                let bytes = rest.get(..size).ok_or_else(corrupt)?;
+                (size, bytes, &rest[size..])
+            }
+            128 => (0, &rest[..0], rest),
+            _ => {
+                let byte = rest.first().ok_or_else(corrupt)?;
+                (257 - control as usize, std::slice::from_ref(byte), &rest[1..])
+            }
+        };
+        let target = data.get_mut(index..index + size).ok_or_else(corrupt)?;
+        if bytes.len() == size {
+            target.copy_from_slice(bytes);
+        } else {
+            target.fill(bytes[0]);
+        }
+        index += size;
+        packed = rest;
+    }
+    if index != data.len() {
+        return Err(corrupt());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::snapshot::{pack, unpack};
+
+    #[test]
+    fn packbits_round_trips() {
+        let mut data = vec![0; 300];
+        data[10..14].copy_from_slice(&[1, 2, 3, 4]);
+        data[200] = 7;
+        data[290..].iter_mut().enumerate().for_each(|(index, byte)| *byte = index as u8);
+
+        let packed = pack(&data);
+        assert!(packed.len() < 40);
+        let mut unpacked = vec![0xFF; 300];
+        unpack(&packed, &mut unpacked).unwrap();
+        assert_eq!(unpacked, data);
+
+        assert!(unpack(&packed, &mut [0; 299]).is_err());
+        assert!(unpack(&[5, 1], &mut [0; 6]).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::io::{self, Write};
+
+use crate::csr::csr_name;
+use crate::instruction::Instruction;
+use crate::trap::Privilege;
+
+/// What each trace line shows. Every format up to `Memory` includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum TraceFormat {
+    /// PC and disassembly
+    Pc,
+    /// Also the values written back to registers
+    Registers,
+    /// Also every memory read and write
+    Memory,
+    /// Spike's `--log-commits` format, for diffing against Spike and RTL traces
+    Spike,
+}
+
+/// A load or store made by the traced instruction. A cache block zeroed by CBO.ZERO is a
+/// single store of the whole block, with `data` standing for its first four bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: usize,
+    pub size: usize,
+    pub data: u32,
+    pub write: bool,
+}
+
+/// What an instruction did, as seen when it retires.
+pub struct Retired<'a> {
+    pub hart: usize,
+    pub privilege: Privilege,
+    pub pc: usize,
+    pub instruction: &'a Instruction,
+    pub trapped: bool,
+    pub registers: &'a [(usize, u32)],
+    pub csrs: &'a [(u16, u32)],
+    pub memory: &'a [MemoryAccess],
+}
+
+/// Writes one line per retired instruction for every hart to a shared output.
+pub struct Tracer {
+    format: TraceFormat,
+    output: Box<dyn Write>,
+    harts: usize,
+}
+
+impl Tracer {
+    pub fn new(format: TraceFormat, output: Box<dyn Write>, harts: usize) -> Self {
+        Self { format, output, harts }
+    }
+
+    /// Traces a retired instruction. Lines are prefixed with the hart when there are several.
+    pub fn record(&mut self, retired: &Retired) -> io::Result<()> {
+        if self.format == TraceFormat::Spike {
+            return self.record_spike(retired);
+        }
+
+        let mut line = String::new();
+        if self.harts > 1 {
+            line += &format!("{} ", retired.hart);
+        }
+        line += &format!("{:08x}    {}", retired.pc, retired.instruction);
+
+        if self.format >= TraceFormat::Registers {
+            for (register, data) in retired.registers {
+                line += &format!("    x{}={:08x}", register, data);
+            }
+        }
+        if self.format >= TraceFormat::Memory {
+            for access in retired.memory {
+                let direction = if access.write { "store" } else { "load" };
+                let width = access.size.min(4) * 2;
+                line += &format!("    {} {:08x} {:0width$x}", direction, access.address, access.data);
+            }
+        }
+
+        writeln!(self.output, "{}", line)
+    }
+
+    /// Writes a line like Spike's commit log: privilege, PC and raw bits, then the registers
+    /// and CSRs written, the addresses loaded and the addresses and data stored. Instructions
+    /// that trapped did not commit and are left out.
+    fn record_spike(&mut self, retired: &Retired) -> io::Result<()> {
+        if retired.trapped {
+            return Ok(());
+        }
+
+        let mut line = format!("core {:3}: {} 0x{:08x} (0x{:08x})",
+            retired.hart, retired.privilege as u32, retired.pc, retired.instruction.get_raw());
+        for (register, data) in retired.registers {
+            line += &format!(" x{:<2} 0x{:08x}", register, data);
+        }
+        for (csr, data) in retired.csrs {
+            line += &format!(" c{:03x}_{} 0x{:08x}", csr, csr_name(*csr), data);
+        }
+        for access in retired.memory.iter().filter(|access| !access.write) {
+            line += &format!(" mem 0x{:08x}", access.address);
+        }
+        for access in retired.memory.iter().filter(|access| access.write) {
+            let width = access.size.min(4) * 2;
+            line += &format!(" mem 0x{:08x} 0x{:0width$x}", access.address, access.data);
+        }
+
+        writeln!(self.output, "{}", line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    use crate::instruction::Instruction;
+    use crate::trace::{MemoryAccess, Retired, TraceFormat, Tracer};
+    use crate::trap::Privilege;
+
+    /// A writer that can still be read after the tracer owning it is done.
+    #[derive(Clone)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn retired<'a>(hart: usize, instruction: &'a Instruction, registers: &'a [(usize, u32)], memory: &'a [MemoryAccess]) -> Retired<'a> {
+        Retired { hart, privilege: Privilege::Machine, pc: 0x10, instruction, trapped: false, registers, csrs: &[], memory }
+    }
+
+    #[test]
+    fn formats_include_earlier_ones() {
+        let buffer = Buffer(Rc::new(RefCell::new(Vec::new())));
+        let load = MemoryAccess { address: 0x100, size: 2, data: 0xbeef, write: false };
+        let instruction = Instruction::from_u32(0x1005d503);
+
+        for format in [TraceFormat::Pc, TraceFormat::Registers, TraceFormat::Memory] {
+            let mut tracer = Tracer::new(format, Box::new(buffer.clone()), 1);
+            tracer.record(&retired(0, &instruction, &[(10, 0xbeef)], &[load])).unwrap();
+        }
+        Tracer::new(TraceFormat::Pc, Box::new(buffer.clone()), 2).record(&retired(1, &instruction, &[], &[])).unwrap();
+
+        assert_eq!(String::from_utf8(buffer.0.borrow().clone()).unwrap(), "\
+00000010    lhu   x10,0x100,x11
+00000010    lhu   x10,0x100,x11    x10=0000beef
+00000010    lhu   x10,0x100,x11    x10=0000beef    load 00000100 beef
+1 00000010    lhu   x10,0x100,x11
+");
+    }
+
+    #[test]
+    fn spike_format_matches_commit_log() {
+        let buffer = Buffer(Rc::new(RefCell::new(Vec::new())));
+        let mut tracer = Tracer::new(TraceFormat::Spike, Box::new(buffer.clone()), 1);
+
+        let csrrw = Instruction::from_u32(0x30529373);
+        let mut csr_write = retired(0, &csrrw, &[(6, 0)], &[]);
+        csr_write.csrs = &[(0x305, 0x100)];
+        tracer.record(&csr_write).unwrap();
+
+        let amoadd = Instruction::from_u32(0x00c5a52f);
+        let memory = [
+            MemoryAccess { address: 0x200, size: 4, data: 5, write: false },
+            MemoryAccess { address: 0x200, size: 4, data: 7, write: true },
+        ];
+        let mut amo = retired(0, &amoadd, &[(10, 5)], &memory);
+        amo.privilege = Privilege::User;
+        tracer.record(&amo).unwrap();
+
+        amo.trapped = true;
+        tracer.record(&amo).unwrap();
+
+        assert_eq!(String::from_utf8(buffer.0.borrow().clone()).unwrap(), "\
+core   0: 3 0x00000010 (0x30529373) x6  0x00000000 c305_mtvec 0x00000100
+core   0: 0 0x00000010 (0x00c5a52f) x10 0x00000005 mem 0x00000200 mem 0x00000200 0x00000007
+");
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+
+impl Privilege {
+    pub fn from_bits(bits: u32) -> Self {
+        match bits & 0b11 {
+            0 => Privilege::User,
+            1 => Privilege::Supervisor,
+            _ => Privilege::Machine,
+        }
+    }
+}
+
+/// How loads and stores that are not naturally aligned are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Misaligned {
+    /// Perform the access as if the hardware supported it
+    Allow,
+    /// Raise a load or store address-misaligned exception
+    Trap,
+    /// Raise a load or store access fault
+    Fault,
+}
+
+/// Synchronous exceptions, numbered by their `mcause` code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    InstructionAddressMisaligned = 0,
+    InstructionAccessFault = 1,
+    IllegalInstruction = 2,
+    LoadAddressMisaligned = 4,
+    LoadAccessFault = 5,
+    StoreAddressMisaligned = 6,
+    StoreAccessFault = 7,
+    UserEcall = 8,
+    SupervisorEcall = 9,
+    MachineEcall = 11,
+}
+
+impl Exception {
+    pub fn ecall_from(privilege: Privilege) -> Self {
+        match privilege {
+            Privilege::User => Exception::UserEcall,
+            Privilege::Supervisor => Exception::SupervisorEcall,
+            Privilege::Machine => Exception::MachineEcall,
+        }
+    }
+}
+
+impl Display for Exception {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Exception::InstructionAddressMisaligned => "instruction address misaligned",
+            Exception::InstructionAccessFault => "instruction access fault",
+            Exception::IllegalInstruction => "illegal instruction",
+            Exception::LoadAddressMisaligned => "load address misaligned",
+            Exception::LoadAccessFault => "load access fault",
+            Exception::StoreAddressMisaligned => "store address misaligned",
+            Exception::StoreAccessFault => "store access fault",
+            Exception::UserEcall => "environment call from U-mode",
+            Exception::SupervisorEcall => "environment call from S-mode",
+            Exception::MachineEcall => "environment call from M-mode",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,102 @@
+use crate::error::Error;
+use crate::snapshot::{Reader, Writer};
+
+pub struct VectorRegisters {
+    vlenb: usize,
+    registers: Vec<u8>,
+}
+
+impl VectorRegisters {
+    pub fn new(vlen: usize) -> Self {
+        Self { vlenb: vlen / 8, registers: vec![0; vlen / 8 * 32] }
+    }
+
+    pub fn vlenb(&self) -> usize {
+        self.vlenb
+    }
+
+    pub fn save(&self, snapshot: &mut Writer) {
+        snapshot.write_u64(self.vlenb as u64);
+        snapshot.write_bytes(&self.registers);
+    }
+
+    pub fn restore(&mut self, snapshot: &mut Reader) -> Result<(), Error> {
+        snapshot.expect("VLEN in bytes", self.vlenb)?;
+        let size = self.registers.len();
+        self.registers.copy_from_slice(snapshot.read_bytes(size)?);
+        Ok(())
+    }
+
+    /// Reads element `index` of the register group starting at `register`, with elements `eew` bits wide.
+    pub fn get(&self, register: usize, index: usize, eew: usize) -> u64 {
+        let offset = register * self.vlenb + index * eew / 8;
+        let mut data = 0;
+        for byte in (0..eew / 8).rev() {
+            data = data << 8 | self.registers[offset + byte] as u64;
+        }
+        data
+    }
+
+    pub fn set(&mut self, register: usize, index: usize, eew: usize, data: u64) {
+        let offset = register * self.vlenb + index * eew / 8;
+        for byte in 0..eew / 8 {
+            self.registers[offset + byte] = (data >> (byte * 8)) as u8;
+        }
+    }
+
+    pub fn get_mask(&self, register: usize, index: usize) -> bool {
+        self.registers[register * self.vlenb + index / 8] >> (index % 8) & 1 != 0
+    }
+
+    pub fn set_mask(&mut self, register: usize, index: usize, value: bool) {
+        let byte = &mut self.registers[register * self.vlenb + index / 8];
+        if value {
+            *byte |= 1 << (index % 8);
+        } else {
+            *byte &= !(1 << (index % 8));
+        }
+    }
+
+    pub fn dump(&self) {
+        for register in 0..32 {
+            print!("v{:02}  ", register);
+            for byte in (0..self.vlenb).rev() {
+                print!("{:02x}", self.registers[register * self.vlenb + byte]);
+                if byte % 4 == 0 && byte != 0 { print!(" ") }
+            }
+            println!();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vector_registers::VectorRegisters;
+
+    #[test]
+    fn elements_span_register_groups() {
+        let mut registers = VectorRegisters::new(128);
+
+        for i in 0..8 {
+            registers.set(2, i, 32, 0x11111111 * i as u64);
+        }
+
+        assert_eq!(registers.get(2, 3, 32), 0x33333333);
+        assert_eq!(registers.get(3, 0, 32), 0x44444444);
+        assert_eq!(registers.get(2, 1, 64), 0x3333333322222222);
+        assert_eq!(registers.get(3, 0, 8), 0x44);
+    }
+
+    #[test]
+    fn mask_bits_set_correctly() {
+        let mut registers = VectorRegisters::new(128);
+
+        registers.set_mask(0, 0, true);
+        registers.set_mask(0, 9, true);
+
+        assert_eq!(registers.get(0, 0, 16), 0x0201);
+        assert!(registers.get_mask(0, 9));
+        registers.set_mask(0, 9, false);
+        assert!(!registers.get_mask(0, 9));
+    }
+}
@@ -0,0 +1,115 @@
+use riscv_emulator::{Engine, Error, MachineBuilder};
+
+fn words(program: &[u32]) -> Vec<u8> {
+    program.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+#[test]
+fn drives_a_machine_in_process() {
+    // addi x10,x10,1; sw x10,256(x0); jal x0,-8
+    let program = words(&[0x00150513, 0x10a02023, 0xff9ff06f]);
+    for engine in [Engine::Interp, Engine::Block] {
+        let mut machine = MachineBuilder::new(0x1000).engine(engine).program(0, &program).build().unwrap();
+
+        assert_eq!(machine.run_for(30), 30);
+        assert_eq!(machine.get_register(0, 10).unwrap(), 10);
+        assert_eq!(machine.get_pc(0).unwrap(), 0);
+
+        assert!(machine.run_until(|machine| machine.read32(0x100).unwrap() >= 20));
+        assert_eq!(machine.get_register(0, 10).unwrap(), 20);
+
+        machine.set_register(0, 10, 99).unwrap();
+        machine.write_memory(0x100, &[0; 4]).unwrap();
+        machine.run_for(3);
+        assert_eq!(machine.read32(0x100).unwrap(), 100);
+    }
+}
+
+#[test]
+fn isa_limits_the_instructions() {
+    // clz x10,x11; ebreak
+    let program = words(&[0x60059513, 0x00100073]);
+    let mut full = MachineBuilder::new(0x1000).program(0, &program).build().unwrap();
+    full.set_register(0, 11, 1).unwrap();
+    full.run();
+    assert_eq!(full.get_register(0, 10).unwrap(), 31);
+
+    let mut base = MachineBuilder::new(0x1000).isa("rv32i_zicsr").program(0, &program).build().unwrap();
+    base.set_register(0, 11, 1).unwrap();
+    base.run();
+    assert_eq!(base.get_register(0, 10).unwrap(), 0);
+    assert_eq!(base.get_pc(0).unwrap(), 0);
+}
+
+#[test]
+fn errors_are_reported() {
+    let mut machine = MachineBuilder::new(0x1000).build().unwrap();
+    assert!(matches!(machine.read32(0xFFE), Err(Error::Bus { address: 0xFFE, size: 4 })));
+    assert!(matches!(machine.get_pc(1), Err(Error::NoHart(1))));
+    assert!(matches!(machine.set_register(0, 32, 1), Err(Error::NoRegister(32))));
+
+    assert!(matches!(MachineBuilder::new(0x1000).vector(100, 32).build(), Err(Error::Config(_))));
+    assert!(matches!(MachineBuilder::new(0x1000).isa("rv32im").build(), Err(Error::Config(_))));
+    assert!(matches!(MachineBuilder::new(0x10).program(0xC, &[0; 8]).build(), Err(Error::Load(_))));
+
+    // lw x5,1024(x0)
+    let mut machine = MachineBuilder::new(0x100).program(0, &words(&[0x40002283])).build().unwrap();
+    machine.run();
+    assert!(matches!(machine.get_fault(), Some(Error::Fault { pc: 0, tval: 0x400, .. })));
+}
+
+#[test]
+fn snapshots_restore_into_a_fresh_machine() {
+    // addi x10,x10,1; csrrw x11,mscratch,x10; sw x11,256(x0); jal x0,-12
+    let program = words(&[0x00150513, 0x340515f3, 0x10b02023, 0xff5ff06f]);
+    let builder = MachineBuilder::new(0x10000).harts(2).quantum(3);
+    let mut original = builder.clone().program(0, &program).build().unwrap();
+    original.run_for(41);
+    let snapshot = original.save_snapshot();
+
+    let mut restored = builder.build().unwrap();
+    restored.load_snapshot(&snapshot).unwrap();
+    assert_eq!(restored.get_instructions(), 41);
+    original.run_for(50);
+    restored.run_for(50);
+    for hart in 0..2 {
+        assert_eq!(restored.get_pc(hart).unwrap(), original.get_pc(hart).unwrap());
+        assert_eq!(restored.get_register(hart, 10).unwrap(), original.get_register(hart, 10).unwrap());
+        assert_eq!(restored.get_register(hart, 11).unwrap(), original.get_register(hart, 11).unwrap());
+    }
+    assert_eq!(restored.read32(0x100).unwrap(), original.read32(0x100).unwrap());
+
+    let mut smaller = MachineBuilder::new(0x1000).harts(2).build().unwrap();
+    assert!(matches!(smaller.load_snapshot(&snapshot), Err(Error::Snapshot(_))));
+    assert!(matches!(restored.load_snapshot(&snapshot[..snapshot.len() - 1]), Err(Error::Snapshot(_))));
+}
+
+#[test]
+fn reverse_execution_goes_back_to_a_store() {
+    // addi x10,x10,1; csrrw x11,mscratch,x10; sw x11,256(x0); jal x0,-12
+    let program = words(&[0x00150513, 0x340515f3, 0x10b02023, 0xff5ff06f]);
+    let builder = MachineBuilder::new(0x1000).program(0, &program);
+    let mut machine = builder.build().unwrap();
+    machine.record_history(7);
+    machine.run_for(40);
+
+    let mut expected = builder.build().unwrap();
+    expected.run_for(39);
+    assert!(machine.reverse_step().unwrap());
+    assert_eq!(machine.get_instructions(), 39);
+    assert_eq!((machine.get_pc(0).unwrap(), machine.get_register(0, 11).unwrap()), (expected.get_pc(0).unwrap(), expected.get_register(0, 11).unwrap()));
+
+    let stored = machine.read32(0x100).unwrap();
+    assert!(machine.reverse_until(|machine| machine.read32(0x100).unwrap() != stored).unwrap());
+    assert_eq!(machine.get_pc(0).unwrap(), 8);
+    assert_eq!(machine.get_register(0, 11).unwrap(), stored);
+
+    machine.set_register(0, 10, 100).unwrap();
+    machine.run_for(5);
+    assert!(machine.reverse_step().unwrap());
+    assert_eq!(machine.get_register(0, 10).unwrap(), 101);
+
+    assert!(!machine.reverse_until(|_| false).unwrap());
+    assert_eq!(machine.get_instructions(), 0);
+    assert_eq!(machine.read32(0x100).unwrap(), 0);
+}